@@ -1,10 +1,12 @@
-use std::{cmp::Ordering, io, time::Duration as Dur};
+use std::{cmp::Ordering, io::{self, Write}, path::{Path, PathBuf}, sync::mpsc, time::{Duration as Dur, Instant}};
+
+use notify::RecommendedWatcher;
 
 use thiserror::Error;
 use tuich::{
     buffer::Buffer,
-    event::Key,
-    layout::{Clip, Rect},
+    event::{Key, Mouse, MouseButton, MouseKind},
+    layout::{Align, Clip, Rect},
     text::Text,
     widget::{Clear, Draw, RefDraw}
 };
@@ -12,17 +14,36 @@ use tuich::{
 use crate::{
     cache::Cache,
     cmdline::CmdLine,
-    commands::{CmdError, Commands},
-    config::Config,
+    commands::{exec_command, CmdError, Commands},
+    config::{Config, ConfigError, ConfigKeys},
+    cover::{Cover, CoverLoader},
+    format::player_var,
+    keyseq::{find_binding, format_keys, KeyBindings, KeySeqMatch},
+    library::Library,
     match_keys,
-    player::{PlaybackError, Player},
+    player::{PlayState, PlaybackError, Player},
+    playlist::{is_playlist_path, LoadPlaylistsError, PlaylistError},
+    scan::{is_audio_file, ScanEvent},
+    search::SearchKind,
+    searchline::SearchLine,
     server::ServerAction,
-    traits::Cycle,
-    view::{PlayerView, PlaylistsView, QueueView},
-    widget::PlayerWidget,
+    scrobble::Scrobbler,
+    stats::{ListenEvent, Stats, StatsError},
+    tageditor::{TagEditor, TagError},
+    track::Id,
+    traits::{ContainsPos, Cycle},
+    view::{HistoryView, LibraryView, LyricsView, PlayerView, PlaylistsView, QueueView, StatsView},
+    watch::{reload_playlist, reload_track},
+    waveform::WaveformLoader,
+    widget::{ListState, PlayerStyle, PlayerWidget, ViewWidget},
     Action,
+    UpdateKind,
 };
 
+// Consts
+/// Min time between rebuilds of the library while the music is being scanned
+const LIBRARY_REBUILD_INTERVAL: Dur = Dur::from_secs(1);
+
 // Errors
 #[derive(Debug, Error)]
 pub enum UpdateError {
@@ -32,6 +53,16 @@ pub enum UpdateError {
     Playback(PlaybackError),
     #[error("Command error: {0}")]
     Cmd(CmdError),
+    #[error("Playlist error: {0}")]
+    Playlist(PlaylistError),
+    #[error("Load playlists error: {0}")]
+    LoadPlaylists(LoadPlaylistsError),
+    #[error("Config error: {0}")]
+    Config(ConfigError),
+    #[error("Stats error: {0}")]
+    Stats(StatsError),
+    #[error("Tag error: {0}")]
+    Tags(TagError),
     #[error("Something went wrong :( : {0}")]
     Unknown(String),
 }
@@ -45,6 +76,21 @@ impl From<CmdError> for UpdateError {
         Self::Cmd(value)
     }
 }
+impl From<PlaylistError> for UpdateError {
+    fn from(value: PlaylistError) -> Self {
+        Self::Playlist(value)
+    }
+}
+impl From<StatsError> for UpdateError {
+    fn from(value: StatsError) -> Self {
+        Self::Stats(value)
+    }
+}
+impl From<TagError> for UpdateError {
+    fn from(value: TagError) -> Self {
+        Self::Tags(value)
+    }
+}
 
 /// View kind
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
@@ -53,23 +99,35 @@ pub enum View {
     #[default]
     Playlists,
     Tracks,
+    Library,
     Queue,
+    History,
+    Stats,
+    Lyrics,
 }
 impl Cycle for View {
     fn cycle_next(&self) -> Self {
         match self {
             Self::Player => Self::Playlists,
             Self::Playlists => Self::Tracks,
-            Self::Tracks => Self::Queue,
-            Self::Queue => Self::Player,
+            Self::Tracks => Self::Library,
+            Self::Library => Self::Queue,
+            Self::Queue => Self::History,
+            Self::History => Self::Stats,
+            Self::Stats => Self::Lyrics,
+            Self::Lyrics => Self::Player,
         }
     }
     fn cycle_prev(&self) -> Self {
         match self {
-            Self::Queue => Self::Tracks,
+            Self::Lyrics => Self::Stats,
+            Self::Stats => Self::History,
+            Self::History => Self::Queue,
+            Self::Queue => Self::Library,
+            Self::Library => Self::Tracks,
             Self::Tracks => Self::Playlists,
             Self::Playlists => Self::Player,
-            Self::Player => Self::Queue
+            Self::Player => Self::Lyrics
         }
     }
 }
@@ -78,7 +136,9 @@ impl Cycle for View {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
     Normal,
-    Cmd
+    Cmd,
+    Search(SearchKind),
+    TagEditor
 }
 
 /// Notification
@@ -108,7 +168,9 @@ pub struct State {
     /// Current view
     pub view: View,
     /// Notification
-    pub notif: Option<Notif>
+    pub notif: Option<Notif>,
+    /// Playlist to show in the tracks view, requested by `:open`
+    pub open: Option<Id>
 }
 impl State {
     pub fn enter_mode(&mut self, mode: Mode) {
@@ -130,26 +192,71 @@ impl State {
 /// App context
 pub struct AppContext {
     pub config: Config,
+    /// Path to the config file
+    pub config_path: PathBuf,
     pub state: State,
     pub player: Player,
     pub cache: Cache,
-    pub commands: Commands
+    pub library: Library,
+    pub commands: Commands,
+    pub stats: Stats,
+    pub scrobbler: Scrobbler,
+    pub waveform: WaveformLoader,
+    pub covers: CoverLoader,
+    /// Watcher of the playlists, music directories and the config file
+    /// The files are not watched anymore when it's dropped
+    pub watcher: Option<RecommendedWatcher>,
+    /// Sender to the main loop for the background threads
+    pub sender: mpsc::Sender<UpdateKind>
 }
 
 /// App
 pub struct App {
     cmdline: CmdLine,
+    searchline: SearchLine,
+    tag_editor: TagEditor,
     player_view: PlayerView,
     playlists_view: PlaylistsView,
+    library_view: LibraryView,
     queue_view: QueueView,
+    history_view: HistoryView,
+    stats_view: StatsView,
+    lyrics_view: LyricsView,
+    /// Keys of a sequence that isn't finished yet
+    pending_keys: Vec<Key>,
+    /// When the last key of the sequence was pressed
+    pending_since: Instant,
+    /// Where the progress bar of the player was drawn
+    progress_rect: Option<Rect>,
+    /// Where the titles of the current view were drawn, clicking them switches the view
+    title_rect: Option<Rect>,
+    /// Whether the progress bar is being dragged
+    seeking: bool,
+    /// The last set title of the terminal window
+    window_title: String,
+    /// When the library was rebuilt from the scanned tracks
+    library_rebuilt_at: Instant,
 }
 impl App {
     pub fn new() -> Self {
         Self {
             cmdline: CmdLine::new(),
+            searchline: SearchLine::new(),
+            tag_editor: TagEditor::new(),
             player_view: PlayerView::new(),
             playlists_view: PlaylistsView::new(),
+            library_view: LibraryView::new(),
             queue_view: QueueView::new(),
+            history_view: HistoryView::new(),
+            stats_view: StatsView::new(),
+            lyrics_view: LyricsView::new(),
+            pending_keys: vec![],
+            pending_since: Instant::now(),
+            progress_rect: None,
+            title_rect: None,
+            seeking: false,
+            window_title: String::new(),
+            library_rebuilt_at: Instant::now(),
         }
     }
 
@@ -193,35 +300,73 @@ impl App {
         key: Key,
     ) -> Action {
         let result = self.try_handle_key(ctx, key);
+        // Any command could reorder the lists
+        self.invalidate_lists();
+        self.open_requested(ctx);
         self.catch_error(ctx, result)
     }
     fn try_handle_key(&mut self, ctx: &mut AppContext, key: Key) -> Result<Action, UpdateError> {
         let action = if ctx.state.notif.is_some() {
             ctx.state.notif = None;
             Action::Draw
+        } else if !self.pending_keys.is_empty() {
+            // Redraw the unfinished key sequence
+            Action::Draw
         } else {
             Action::Nope
         };
 
         let action = action | match ctx.state.mode {
             Mode::Normal => self.handle_normal_mode_key(ctx, key)?,
-            Mode::Cmd => self.cmdline.handle_key(ctx, key)?
+            Mode::Cmd => self.cmdline.handle_key(ctx, key)?,
+            Mode::TagEditor => self.tag_editor.handle_key(ctx, key)?,
+            Mode::Search(kind) => {
+                let list = focused_list(
+                    &mut self.playlists_view,
+                    &mut self.library_view,
+                    &mut self.queue_view,
+                    &mut self.history_view,
+                    &mut self.lyrics_view,
+                    ctx.state.view
+                );
+
+                match list {
+                    Some(list) => self.searchline.handle_key(ctx, kind, list, key)?,
+                    None => {
+                        ctx.state.enter_mode(Mode::Normal);
+                        Action::Draw
+                    }
+                }
+            }
         };
 
         Ok(action)
     }
 
     fn handle_normal_mode_key(&mut self, ctx: &mut AppContext, key: Key) -> Result<Action, UpdateError> {
+        match self.handle_key_seq(ctx, key)? {
+            Some(action) => Ok(action),
+            None => self.handle_single_key(ctx, key)
+        }
+    }
+    /// Handle a key bound to something by itself, ignoring the key sequences
+    fn handle_single_key(&mut self, ctx: &mut AppContext, key: Key) -> Result<Action, UpdateError> {
         match_keys! {
             ctx.config, key,
 
             enter_cmd => ctx.state.enter_mode(Mode::Cmd),
+            search => self.enter_search(ctx, SearchKind::Search),
+            filter => self.enter_search(ctx, SearchKind::Filter),
 
             next_view => ctx.state.next_view(),
             prev_view => ctx.state.prev_view(),
             player_view => ctx.state.view = View::Player,
             playlists_view => ctx.state.view = View::Playlists,
+            library_view => ctx.state.view = View::Library,
             queue_view => ctx.state.view = View::Queue,
+            history_view => ctx.state.view = View::History,
+            stats_view => ctx.state.view = View::Stats,
+            lyrics_view => ctx.state.view = View::Lyrics,
 
             play_next => ctx.player.play_next()?,
             play_prev => ctx.player.play_prev()?,
@@ -241,6 +386,7 @@ impl App {
             cycle_loopstate => ctx.player.cycle_loopstate(),
 
             queue_shuffle => ctx.player.queue_shuffle(),
+            edit_tags => self.open_tag_editor(ctx),
 
             quit => return Ok(Action::Quit);
 
@@ -248,8 +394,12 @@ impl App {
                 return Ok(match ctx.state.view {
                     View::Tracks |
                     View::Playlists => self.playlists_view.handle_key(ctx, key)?,
+                    View::Library => self.library_view.handle_key(ctx, key)?,
                     View::Queue => self.queue_view.handle_key(ctx, key)?,
-                    View::Player => Action::Nope
+                    View::History => self.history_view.handle_key(ctx, key)?,
+                    View::Lyrics => self.lyrics_view.handle_key(ctx, key)?,
+                    View::Player |
+                    View::Stats => Action::Nope
                 })
             }
         }
@@ -257,12 +407,250 @@ impl App {
         Ok(Action::Draw)
     }
 
+    pub fn handle_mouse(
+        &mut self,
+        ctx: &mut AppContext,
+        mouse: Mouse,
+    ) -> Action {
+        let result = self.try_handle_mouse(ctx, mouse);
+        // Clicks could select another group or playlist
+        if !matches!(result, Ok(Action::Nope)) {
+            self.invalidate_lists();
+        }
+        self.catch_error(ctx, result)
+    }
+    fn try_handle_mouse(&mut self, ctx: &mut AppContext, mouse: Mouse) -> Result<Action, UpdateError> {
+        if ctx.state.mode != Mode::Normal {
+            return Ok(Action::Nope);
+        }
+
+        let mut action = Action::Nope;
+        match mouse.kind {
+            MouseKind::Down(MouseButton::Left) => {
+                if ctx.state.notif.take().is_some() {
+                    action = Action::Draw;
+                }
+                self.seeking = self.progress_rect.is_some_and(|r| r.contains_pos(mouse.pos));
+            }
+            MouseKind::Up(_) => self.seeking = false,
+            _ => ()
+        }
+
+        // Switch to the next view by clicking the title
+        // Titles of the playlists and the tracks are handled by their view, they switch between the two
+        let on_title = action == Action::Nope &&
+            self.title_rect.is_some_and(|r| r.contains_pos(mouse.pos)) &&
+            !matches!(ctx.state.view, View::Playlists | View::Tracks);
+        if on_title && matches!(mouse.kind, MouseKind::Down(MouseButton::Left)) {
+            ctx.state.next_view();
+            return Ok(Action::Draw);
+        }
+
+        // Seek by clicking or dragging the progress bar
+        if self.seeking && matches!(mouse.kind, MouseKind::Down(_) | MouseKind::Drag(_)) {
+            if let Some(rect) = self.progress_rect.filter(|r| r.width > 0) {
+                let x = mouse.pos.x.clamp(rect.x, rect.x + rect.width - 1) - rect.x;
+                let progress = x as f32 / rect.width as f32;
+                ctx.player.seek(ctx.player.duration().mul_f32(progress))?;
+            }
+            return Ok(Action::Draw);
+        }
+
+        Ok(action | match ctx.state.view {
+            View::Tracks |
+            View::Playlists => self.playlists_view.handle_mouse(ctx, mouse)?,
+            View::Library => self.library_view.handle_mouse(ctx, mouse)?,
+            View::Queue => self.queue_view.handle_mouse(ctx, mouse)?,
+            View::History => self.history_view.handle_mouse(ctx, mouse)?,
+            View::Lyrics => self.lyrics_view.handle_mouse(ctx, mouse)?,
+            View::Player |
+            View::Stats => Action::Nope
+        })
+    }
+
+    /// Handle a key of the sequences bound to commands
+    /// Returns `None` if the key doesn't start or continue any sequence
+    fn handle_key_seq(&mut self, ctx: &mut AppContext, key: Key) -> Result<Option<Action>, UpdateError> {
+        // The key doesn't continue a sequence which timed out already
+        let action =
+            if self.is_pending_timed_out(ctx) { self.flush_pending_keys(ctx)? }
+            else { Action::Nope };
+        if action == Action::Quit {
+            return Ok(Some(action));
+        }
+
+        let was_pending = !self.pending_keys.is_empty();
+        self.pending_keys.push(key);
+
+        let bindings = [view_bindings(&ctx.config.keys, ctx.state.view), &ctx.config.keys.custom];
+
+        match find_binding(bindings, &self.pending_keys) {
+            KeySeqMatch::Pending => {
+                self.pending_since = Instant::now();
+                Ok(Some(action | Action::Draw))
+            }
+            KeySeqMatch::Command(command) => {
+                self.pending_keys.clear();
+                exec_command(ctx, command).map(|a| Some(a | action | Action::Draw))
+            }
+            KeySeqMatch::Nope if !was_pending && action == Action::Nope => {
+                self.pending_keys.clear();
+                Ok(None)
+            }
+            // Handle the keys of the unfinished sequence on their own, then this key
+            KeySeqMatch::Nope => {
+                self.pending_keys.pop();
+                let action = action | self.flush_pending_keys(ctx)?;
+                if action == Action::Quit {
+                    return Ok(Some(action));
+                }
+
+                let key_action =
+                    if ctx.state.mode == Mode::Normal { self.handle_normal_mode_key(ctx, key)? }
+                    else { self.try_handle_key(ctx, key)? };
+                // Quitting goes first
+                Ok(Some(key_action | action))
+            }
+        }
+    }
+    /// Run the unfinished sequence if it's bound to a command by itself,
+    /// otherwise handle its keys one by one as if there were no sequences
+    fn flush_pending_keys(&mut self, ctx: &mut AppContext) -> Result<Action, UpdateError> {
+        let keys = std::mem::take(&mut self.pending_keys);
+        if keys.is_empty() {
+            return Ok(Action::Nope);
+        }
+
+        let bindings = [view_bindings(&ctx.config.keys, ctx.state.view), &ctx.config.keys.custom];
+        let command = bindings
+            .into_iter()
+            .find_map(|b| b.iter().find(|(seq, _)| seq.is(&keys)))
+            .map(|(_, command)| command.clone());
+
+        if let Some(command) = command {
+            return exec_command(ctx, command).map(|a| a | Action::Draw);
+        }
+
+        for key in keys {
+            // Some key could open the command line or start a search
+            let action =
+                if ctx.state.mode == Mode::Normal { self.handle_single_key(ctx, key)? }
+                else { self.try_handle_key(ctx, key)? };
+
+            if action == Action::Quit {
+                return Ok(action);
+            }
+        }
+
+        Ok(Action::Draw)
+    }
+    /// Returns whether there is an unfinished sequence which wasn't continued in time
+    fn is_pending_timed_out(&self, ctx: &AppContext) -> bool {
+        !self.pending_keys.is_empty() &&
+            self.pending_since.elapsed() >= Dur::from_millis(ctx.config.key_timeout)
+    }
+
+    /// Recalculate search matches of all the lists on their next draw
+    fn invalidate_lists(&mut self) {
+        self.playlists_view.invalidate();
+        self.library_view.invalidate();
+        self.queue_view.invalidate();
+    }
+    /// Show the playlist requested by `:open` in the tracks view
+    fn open_requested(&mut self, ctx: &mut AppContext) {
+        if let Some(id) = ctx.state.open.take() {
+            self.playlists_view.open_playlist(ctx, id);
+        }
+    }
+
+    /// Show the cover loaded in the background
+    pub fn set_cover(&mut self, ctx: &mut AppContext, track_path: PathBuf, cover: Option<Cover>) -> Action {
+        self.player_view.set_cover(ctx, track_path, cover)
+    }
+
+    /// Handle a tick of the main loop
+    pub fn handle_tick(&mut self, ctx: &mut AppContext) -> Action {
+        let result = self.try_handle_tick(ctx);
+        // Commands of the startup script run before the first tick
+        self.open_requested(ctx);
+        self.catch_error(ctx, result)
+    }
+    fn try_handle_tick(&mut self, ctx: &mut AppContext) -> Result<Action, UpdateError> {
+        self.player_view.update_cover(ctx);
+        self.lyrics_view.update_lyrics(ctx);
+
+        // Decoding the whole track is only worth it if the waveform is shown
+        let waveform_path = ctx.player.cur_track
+            .as_ref()
+            .filter(|_| ctx.config.style.player == PlayerStyle::Waveform)
+            .map(|t| t.path.clone());
+        ctx.waveform.update(waveform_path);
+
+        let (events, result) = ctx.stats.handle_tick(&ctx.player);
+        // The queue could be shuffled for the next track
+        if !events.is_empty() {
+            self.invalidate_lists();
+        }
+        for event in events {
+            match event {
+                ListenEvent::Started(track) => ctx.scrobbler.now_playing(&track),
+                ListenEvent::Played(track, listened_at) => {
+                    ctx.scrobbler.scrobble(&track, listened_at);
+                    // Most played and recently played tracks could change
+                    ctx.player.refresh_stats_playlists(&ctx.cache, &ctx.stats);
+                }
+            }
+        }
+
+        // Scrobbling and key sequences shouldn't stop because of the statistics
+        let action = match result {
+            Ok(()) => Action::Nope,
+            Err(e) => {
+                ctx.state.notify(Notif::Error(UpdateError::Stats(e).to_string()));
+                Action::Draw
+            }
+        };
+
+        if self.is_pending_timed_out(ctx) {
+            return Ok(self.flush_pending_keys(ctx)? | action);
+        }
+
+        Ok(action)
+    }
+
+    /// Open the tag editor for the marked tracks or the selected one
+    fn open_tag_editor(&mut self, ctx: &mut AppContext) {
+        if ctx.state.view != View::Tracks {
+            return;
+        }
+
+        let paths = self.playlists_view.take_marked_paths(ctx);
+        self.tag_editor.open(ctx, paths);
+    }
+
+    /// Enter search mode if the current view has a list to search in
+    fn enter_search(&mut self, ctx: &mut AppContext, kind: SearchKind) {
+        let list = focused_list(
+            &mut self.playlists_view,
+            &mut self.library_view,
+            &mut self.queue_view,
+            &mut self.history_view,
+            &mut self.lyrics_view,
+            ctx.state.view
+        );
+
+        if list.is_some() {
+            ctx.state.enter_mode(Mode::Search(kind));
+        }
+    }
+
     pub fn handle_server_action(
         &mut self,
         ctx: &mut AppContext,
         action: ServerAction,
     ) -> Action {
         let result = self.try_handle_server_action(ctx, action);
+        self.invalidate_lists();
         self.catch_error(ctx, result)
     }
     fn try_handle_server_action(
@@ -296,6 +684,113 @@ impl App {
         Ok(Action::Draw)
     }
 
+    pub fn handle_ipc_command(
+        &mut self,
+        ctx: &mut AppContext,
+        command: String,
+    ) -> Action {
+        let result = exec_command(ctx, command);
+        self.invalidate_lists();
+        self.open_requested(ctx);
+        self.catch_error(ctx, result)
+    }
+
+    pub fn handle_scan_event(
+        &mut self,
+        ctx: &mut AppContext,
+        event: ScanEvent,
+    ) -> Action {
+        match event {
+            ScanEvent::Tracks { tracks, done, total } => {
+                for (path, data) in tracks {
+                    ctx.cache.insert(path, data);
+                }
+
+                // Rebuilding on every batch is too slow for big libraries
+                if self.library_rebuilt_at.elapsed() >= LIBRARY_REBUILD_INTERVAL {
+                    self.rebuild_library(ctx);
+                }
+                ctx.state.notify(format!("Scanning music... {}/{}", done, total));
+            }
+            ScanEvent::Done { total, failed } => {
+                self.rebuild_library(ctx);
+
+                if failed == 0 {
+                    ctx.state.notify(format!("{} tracks were scanned", total));
+                } else {
+                    ctx.state.notify(Notif::Error(format!(
+                        "{} tracks were scanned, {} couldn't be read",
+                        total - failed,
+                        failed
+                    )));
+                }
+            }
+        }
+
+        Action::Draw
+    }
+
+    /// Rebuild the library and the smart playlists from the cached tracks
+    fn rebuild_library(&mut self, ctx: &mut AppContext) {
+        ctx.library = Library::from_cache(&ctx.cache);
+        ctx.player.refresh_smart_playlists(&ctx.cache, &ctx.stats);
+        self.library_rebuilt_at = Instant::now();
+        self.invalidate_lists();
+    }
+
+    pub fn handle_fs_change(
+        &mut self,
+        ctx: &mut AppContext,
+        paths: Vec<PathBuf>,
+    ) -> Action {
+        let result = self.try_handle_fs_change(ctx, paths);
+        self.invalidate_lists();
+        self.catch_error(ctx, result)
+    }
+    fn try_handle_fs_change(
+        &mut self,
+        ctx: &mut AppContext,
+        paths: Vec<PathBuf>,
+    ) -> Result<Action, UpdateError> {
+        let mut library_changed = false;
+        let mut playlists_changed = false;
+        let mut config_changed = false;
+        let mut error = None;
+
+        for path in paths {
+            if is_same_file(&path, &ctx.config_path) {
+                config_changed = true;
+            } else if is_audio_file(&path) {
+                library_changed |= reload_track(ctx, &path);
+            } else if is_playlist_path(&ctx.config, &path) {
+                playlists_changed = true;
+
+                // Keep reloading other playlists on error
+                if let Err(e) = reload_playlist(ctx, &path) {
+                    error = Some(e);
+                }
+            }
+        }
+
+        if library_changed {
+            ctx.library = Library::from_cache(&ctx.cache);
+            ctx.player.refresh_smart_playlists(&ctx.cache, &ctx.stats);
+        }
+        if playlists_changed {
+            ctx.player.refresh_all_playlist();
+        }
+
+        if error.is_none() && config_changed && ctx.config.watch_config {
+            exec_command(ctx, "config-reload")?;
+        }
+
+        match error {
+            Some(e) => Err(e.into()),
+            None if library_changed || playlists_changed || config_changed => Ok(Action::Draw),
+            None => Ok(Action::Nope)
+        }
+    }
+
     pub fn draw(
         &mut self,
         ctx: &AppContext,
@@ -318,7 +813,11 @@ impl App {
         let player_rect = match ctx.state.view {
             View::Tracks |
             View::Playlists |
-            View::Queue => PlayerWidget {
+            View::Library |
+            View::Queue |
+            View::History |
+            View::Stats |
+            View::Lyrics => PlayerWidget {
                 ctx,
                 style: ctx.config.style.player,
             }.draw(buf, rect.with_y(rect.bottom()).sub_y(2)),
@@ -329,13 +828,32 @@ impl App {
         let view_rect = rect.margin_bottom(player_rect.height + 1);
 
         // Draw the views
-        match ctx.state.view {
+        let drawn_rect = match ctx.state.view {
             View::Player => self.player_view.draw(ctx, buf, view_rect),
             View::Tracks |
             View::Playlists => self.playlists_view.draw(ctx, buf, view_rect),
-            View::Queue => self.queue_view.draw(ctx, buf, view_rect)
+            View::Library => self.library_view.draw(ctx, buf, view_rect),
+            View::Queue => self.queue_view.draw(ctx, buf, view_rect),
+            View::History => self.history_view.draw(ctx, buf, view_rect),
+            View::Stats => self.stats_view.draw(ctx, buf, view_rect),
+            View::Lyrics => self.lyrics_view.draw(ctx, buf, view_rect)
         };
 
+        // The player view returns where the player was drawn
+        let player_rect =
+            if ctx.state.view == View::Player { drawn_rect }
+            else { player_rect };
+        self.progress_rect = PlayerWidget::progress_rect(player_rect, ctx.config.style.player);
+        // The player view has no titles
+        self.title_rect =
+            if ctx.state.view == View::Player { None }
+            else { Some(ViewWidget::header_rect(view_rect)) };
+
+        // Draw tag editor over the view
+        if ctx.state.mode == Mode::TagEditor {
+            self.tag_editor.draw(ctx, buf, view_rect);
+        }
+
         // Draw error message
         if let Some(notif) = &ctx.state.notif {
             // Place message at the top
@@ -354,11 +872,109 @@ impl App {
                 .draw(buf, error_rect.margin((1, 0)));
         }
 
+        // Draw unfinished key sequence at the top right corner
+        if !self.pending_keys.is_empty() {
+            Text::new(format_keys(&self.pending_keys), ctx.config.theme.pending_keys)
+                .align(Align::End)
+                .draw(buf, rect.with_height(1).margin((1, 0)));
+        }
+
         // Draw command line at the top
-        if ctx.state.mode == Mode::Cmd {
-            self.cmdline.draw(ctx, buf, rect);
+        match ctx.state.mode {
+            Mode::Cmd => { self.cmdline.draw(ctx, buf, rect); },
+            Mode::Search(kind) => { self.searchline.draw(ctx, kind, buf, rect); },
+            Mode::Normal |
+            Mode::TagEditor => ()
         }
 
         rect
     }
+    /// Send images to the terminal, they are drawn over the buffer
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the output couldn't be written
+    pub fn draw_images<W: Write>(&mut self, ctx: &AppContext, out: &mut W) -> io::Result<()> {
+        let visible = ctx.state.view == View::Player && ctx.state.mode != Mode::TagEditor;
+        self.player_view.draw_image(ctx, visible, out)
+    }
+    /// Set title of the terminal window if it was changed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the output couldn't be written
+    pub fn update_window_title<W: Write>(&mut self, ctx: &AppContext, out: &mut W) -> io::Result<()> {
+        let template = &ctx.config.format.window_title;
+        if template.is_empty() {
+            return Ok(());
+        }
+
+        let title = template.render(|name| player_var(ctx, name));
+        if title == self.window_title {
+            return Ok(());
+        }
+
+        crossterm::execute!(out, crossterm::terminal::SetTitle(&title))?;
+        self.window_title = title;
+        Ok(())
+    }
+    /// Returns how often the app should be redrawn to animate the visualizer
+    /// Returns `None` if no visualizer is shown or nothing is playing
+    pub fn frame_interval(&self, ctx: &AppContext) -> Option<Dur> {
+        let in_player = ctx.config.style.player == PlayerStyle::Visualizer;
+        let is_shown = match ctx.state.view {
+            View::Player => ctx.config.visualizer.enabled || in_player,
+            _ => in_player
+        };
+        let is_animated = is_shown &&
+            ctx.state.mode != Mode::TagEditor &&
+            ctx.player.playstate() == PlayState::Playing;
+
+        Some(Dur::from_secs(1) / ctx.config.visualizer.fps.max(1) as u32)
+            .filter(|_| is_animated)
+    }
+}
+
+/// Returns state of the focused list in the view
+/// Takes the views separately, so other fields of the [App] can be borrowed at the same time
+fn focused_list<'a>(
+    playlists_view: &'a mut PlaylistsView,
+    library_view: &'a mut LibraryView,
+    queue_view: &'a mut QueueView,
+    history_view: &'a mut HistoryView,
+    lyrics_view: &'a mut LyricsView,
+    view: View,
+) -> Option<&'a mut ListState> {
+    match view {
+        View::Tracks |
+        View::Playlists => playlists_view.list_state_mut(view),
+        View::Library => Some(library_view.list_state_mut()),
+        View::Queue => Some(queue_view.list_state_mut()),
+        View::History => Some(history_view.list_state_mut()),
+        View::Lyrics => Some(lyrics_view.list_state_mut()),
+        View::Player |
+        View::Stats => None
+    }
+}
+
+/// Returns key bindings of the view
+fn view_bindings(keys: &ConfigKeys, view: View) -> &KeyBindings {
+    match view {
+        View::Player => &keys.view.player,
+        View::Playlists => &keys.view.playlists,
+        View::Tracks => &keys.view.tracks,
+        View::Library => &keys.view.library,
+        View::Queue => &keys.view.queue,
+        View::History => &keys.view.history,
+        View::Stats => &keys.view.stats,
+        View::Lyrics => &keys.view.lyrics
+    }
+}
+
+/// Returns whether both paths point to the same file
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b
+    }
 }
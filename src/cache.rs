@@ -1,6 +1,6 @@
 use std::{collections::HashMap, path::{Path, PathBuf}, rc::Rc};
 
-use crate::track::{TrackData, TrackDataError};
+use crate::{query::Query, stats::Stats, track::{Track, TrackData, TrackDataError}};
 
 #[derive(Debug)]
 pub struct Cache {
@@ -27,10 +27,29 @@ impl Cache {
             self.tracks_data.insert(path.into(), track_data.into());
         }
     }
+    /// Add or replace track data
+    pub fn insert<P: AsRef<Path>, T: Into<Rc<TrackData>>>(&mut self, path: P, track_data: T) {
+        self.tracks_data.insert(path.as_ref().into(), track_data.into());
+    }
     pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<&Rc<TrackData>> {
         self.tracks_data.get(path.as_ref())
     }
     pub fn has<P: AsRef<Path>>(&self, path: P) -> bool {
         self.tracks_data.contains_key(path.as_ref())
     }
+
+    /// Returns all the known tracks matching the query sorted by their paths
+    pub fn query(&self, query: &Query, stats: &Stats) -> Vec<Rc<Track>> {
+        let mut paths: Vec<&PathBuf> = self.tracks_data
+            .iter()
+            .filter(|(path, data)| query.matches(path, data, stats.get(path)))
+            .map(|(path, _)| path)
+            .collect();
+        paths.sort();
+
+        paths
+            .into_iter()
+            .map(|path| Rc::new(Track::new(path, Rc::clone(&self.tracks_data[path]))))
+            .collect()
+    }
 }
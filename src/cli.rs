@@ -37,6 +37,7 @@ pub fn print_help(commands: &Commands) {
     println!("    -h, --help           Print this message again!");
     println!("    -c, --config <PATH>  Specify path to config.toml");
     println!("    --echo <MSG>         Send a command with a message");
+    println!("    -s, --send <CMD>     Execute a command in the running VORU");
     println!();
     println!("EXAMPLES:");
     println!("    Launch VORU with a welcome message!");
@@ -47,6 +48,9 @@ pub fn print_help(commands: &Commands) {
     println!();
     println!("    Add tracks to the queue:");
     println!("        voru add ~/my-cool-music/*");
+    println!();
+    println!("    Play some tracks in the running VORU:");
+    println!("        voru --send 'play artist:\"Low Roar\" year:>2014 -title:live'");
 }
 
 /// Cli
@@ -55,7 +59,9 @@ pub struct Cli {
     pub print_version: bool,
     pub print_help: bool,
     pub config_path: Option<PathBuf>,
-    pub echo_msg: Option<String>
+    pub echo_msg: Option<String>,
+    /// Command to send to the running VORU
+    pub send_cmd: Option<String>
 }
 impl Cli {
     /// Tries to parse options and commands from a list of args
@@ -84,6 +90,9 @@ impl Cli {
                 "--echo" => {
                     cli.echo_msg = args_iter.next().cloned();
                 }
+                "-s" | "--send" => {
+                    cli.send_cmd = Some(args_iter.next()?.clone());
+                }
                 _ => return None
             }
         }
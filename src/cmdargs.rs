@@ -0,0 +1,431 @@
+use std::time::Duration;
+
+use crate::commands::CmdError;
+
+/// Argument kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    /// Any text, taken as is without splitting and unquoting
+    Text,
+    /// Duration: `90`, `90s`, `1m30s`, `1:30`, `1:02:03`
+    Duration,
+    /// Position to seek to: a duration or a relative duration `+10s`/`-1:00`
+    Position,
+    /// Percentage: `80`, `80%`
+    Percent,
+    /// Volume: a percentage or a relative percentage `+5%`/`-5%`
+    Volume,
+    /// Path to a file or a directory, can end with an asterisk
+    Path,
+    /// Name of a playlist, all the arguments are joined with spaces
+    Playlist,
+    /// Title of a track from the library, all the arguments are joined with spaces
+    Track,
+    /// One of the values
+    Choice(&'static [&'static str]),
+}
+impl ArgKind {
+    /// Returns whether all the arguments are joined into a single value
+    pub fn is_joined(&self) -> bool {
+        matches!(self, Self::Text | Self::Playlist | Self::Track)
+    }
+}
+
+/// Argument spec of a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArgSpec {
+    /// Name of the argument shown in the usage: `<SECONDS>`
+    pub name: &'static str,
+    pub kind: ArgKind,
+    /// Whether the argument can be repeated
+    pub multiple: bool,
+}
+impl ArgSpec {
+    pub const fn new(name: &'static str, kind: ArgKind) -> Self {
+        Self { name, kind, multiple: false }
+    }
+    pub const fn multiple(name: &'static str, kind: ArgKind) -> Self {
+        Self { name, kind, multiple: true }
+    }
+
+    /// Check that the args match the spec
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are not enough, too many or invalid args
+    pub fn check<S: AsRef<str>>(&self, args: &[S]) -> Result<(), CmdError> {
+        if args.is_empty() {
+            return Err(CmdError::NotEnoughArgs);
+        }
+        if args.len() > 1 && !self.multiple && !self.kind.is_joined() {
+            return Err(CmdError::TooManyArgs);
+        }
+
+        for arg in args {
+            let arg = arg.as_ref();
+            match self.kind {
+                ArgKind::Text |
+                ArgKind::Path |
+                ArgKind::Playlist |
+                ArgKind::Track => (),
+                ArgKind::Duration => { parse_duration(arg)?; }
+                ArgKind::Position => { parse_position(arg)?; }
+                ArgKind::Percent => { parse_percent(arg)?; }
+                ArgKind::Volume => { parse_volume(arg)?; }
+                ArgKind::Choice(values) => if !values.contains(&arg) {
+                    return Err(CmdError::InvalidArg(arg.to_string()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Position to seek to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    At(Duration),
+    Forward(Duration),
+    Backward(Duration),
+}
+
+/// Volume change
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Volume {
+    Set(f32),
+    Up(f32),
+    Down(f32),
+}
+
+/// Split the input into separate commands by `;`
+/// Quoted and escaped semicolons are kept, quotes and escapes are kept as is too
+/// Apostrophes after a letter don't start a quote: `echo it's`
+///
+/// # Errors
+///
+/// Returns [CmdError::UnclosedQuote] if a quote was not closed
+pub fn split_commands(input: &str) -> Result<Vec<String>, CmdError> {
+    let mut commands = vec![];
+    let mut command = String::new();
+    let mut quote = None;
+    let mut prev = None;
+    let mut chars = input.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if quote != Some('\'') => {
+                command.push(c);
+                if let Some(c) = chars.next() {
+                    command.push(c);
+                }
+                prev = None;
+                continue;
+            }
+            _ if quote.is_none() && is_apostrophe(prev, c) => (),
+            '"' | '\'' if quote.is_none() => quote = Some(c),
+            _ if quote == Some(c) => quote = None,
+            ';' if quote.is_none() => {
+                commands.push(std::mem::take(&mut command));
+                continue;
+            }
+            _ => ()
+        }
+
+        command.push(c);
+        prev = Some(c);
+    }
+
+    if quote.is_some() {
+        return Err(CmdError::UnclosedQuote);
+    }
+    commands.push(command);
+
+    Ok(commands
+        .into_iter()
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .collect())
+}
+
+/// Split a command into args by whitespaces
+/// Supports single and double quotes and escaping with a backslash:
+/// `queue-add "~/Music/My Album/*" ~/Music/Other\ Album/*`
+/// Apostrophes after a letter are kept as is: `Guns N' Roses`
+///
+/// # Errors
+///
+/// Returns [CmdError::UnclosedQuote] if a quote was not closed
+pub fn tokenize(command: &str) -> Result<Vec<String>, CmdError> {
+    let mut args = vec![];
+    let mut arg: Option<String> = None;
+    let mut quote = None;
+    let mut prev = None;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            // Everything is literal in the single quotes
+            _ if quote == Some('\'') && c != '\'' => arg.get_or_insert_with(String::new).push(c),
+            '\\' => {
+                let arg = arg.get_or_insert_with(String::new);
+                if let Some(c) = chars.next() {
+                    arg.push(c);
+                }
+                prev = None;
+                continue;
+            }
+            _ if quote.is_none() && is_apostrophe(prev, c) => arg.get_or_insert_with(String::new).push(c),
+            '"' | '\'' if quote.is_none() => {
+                quote = Some(c);
+                arg.get_or_insert_with(String::new);
+            }
+            _ if quote == Some(c) => quote = None,
+            _ if c.is_whitespace() && quote.is_none() => {
+                if let Some(arg) = arg.take() {
+                    args.push(arg);
+                }
+            }
+            _ => arg.get_or_insert_with(String::new).push(c)
+        }
+        prev = Some(c);
+    }
+
+    if quote.is_some() {
+        return Err(CmdError::UnclosedQuote);
+    }
+    if let Some(arg) = arg {
+        args.push(arg);
+    }
+
+    Ok(args)
+}
+
+/// Returns byte offset of the last arg in the command, or length of the command
+/// if it ends with a whitespace and the next arg is not started yet
+pub fn last_arg_start(command: &str) -> usize {
+    let mut start = 0;
+    let mut in_arg = false;
+    let mut quote = None;
+    let mut prev = None;
+    let mut chars = command.char_indices();
+
+    while let Some((index, c)) = chars.next() {
+        if !in_arg && !c.is_whitespace() {
+            start = index;
+            in_arg = true;
+        }
+
+        match c {
+            _ if quote == Some('\'') && c != '\'' => (),
+            '\\' => {
+                chars.next();
+                prev = None;
+                continue;
+            }
+            _ if quote.is_none() && is_apostrophe(prev, c) => (),
+            '"' | '\'' if quote.is_none() => quote = Some(c),
+            _ if quote == Some(c) => quote = None,
+            _ if c.is_whitespace() && quote.is_none() => in_arg = false,
+            _ => ()
+        }
+        prev = Some(c);
+    }
+
+    if in_arg { start } else { command.len() }
+}
+
+/// Returns whether the char is an apostrophe inside a word rather than an opening quote
+fn is_apostrophe(prev: Option<char>, c: char) -> bool {
+    c == '\'' && prev.is_some_and(char::is_alphanumeric)
+}
+
+/// Escape whitespaces, quotes and other special characters with a backslash,
+/// so the value is read back as a single arg by [tokenize]
+pub fn escape_arg(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_whitespace() || matches!(c, '\\' | '"' | '\'' | ';') {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Parse a duration: `90`, `90s`, `1m30s`, `1h`, `1:30`, `1:02:03`
+///
+/// # Errors
+///
+/// Returns [CmdError::InvalidArg] if the duration is invalid
+pub fn parse_duration(arg: &str) -> Result<Duration, CmdError> {
+    let invalid = || CmdError::InvalidArg(arg.to_string());
+
+    if arg.is_empty() {
+        return Err(invalid());
+    }
+
+    // `H:MM:SS` or `M:SS`
+    if arg.contains(':') {
+        let parts = arg
+            .split(':')
+            .map(|p| p.parse::<u64>())
+            .collect::<Result<Vec<u64>, _>>()
+            .map_err(|_| invalid())?;
+        if parts.len() > 3 {
+            return Err(invalid());
+        }
+
+        let secs = parts
+            .iter()
+            .try_fold(0_u64, |acc, p| acc.checked_mul(60)?.checked_add(*p))
+            .ok_or_else(invalid)?;
+        return Ok(Duration::from_secs(secs));
+    }
+
+    // `1h2m3s` or plain seconds
+    let mut secs = 0_u64;
+    let mut number = String::new();
+    for c in arg.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let value = number.parse::<u64>().map_err(|_| invalid())?;
+        number.clear();
+
+        let unit = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(invalid())
+        };
+        secs = value
+            .checked_mul(unit)
+            .and_then(|v| secs.checked_add(v))
+            .ok_or_else(invalid)?;
+    }
+    if !number.is_empty() {
+        let value = number.parse::<u64>().map_err(|_| invalid())?;
+        secs = secs.checked_add(value).ok_or_else(invalid)?;
+    }
+
+    Ok(Duration::from_secs(secs))
+}
+/// Parse a position: a duration or a relative duration `+10s`/`-1:00`
+///
+/// # Errors
+///
+/// Returns [CmdError::InvalidArg] if the position is invalid
+pub fn parse_position(arg: &str) -> Result<Position, CmdError> {
+    if let Some(arg) = arg.strip_prefix('+') {
+        Ok(Position::Forward(parse_duration(arg)?))
+    } else if let Some(arg) = arg.strip_prefix('-') {
+        Ok(Position::Backward(parse_duration(arg)?))
+    } else {
+        Ok(Position::At(parse_duration(arg)?))
+    }
+}
+/// Parse a percentage `80`/`80%` into a value from `0.0`
+///
+/// # Errors
+///
+/// Returns [CmdError::InvalidArg] if the percentage is invalid
+pub fn parse_percent(arg: &str) -> Result<f32, CmdError> {
+    let percent = arg
+        .trim_end_matches('%')
+        .parse::<u16>()
+        .map_err(|_| CmdError::InvalidArg(arg.to_string()))?;
+
+    Ok(percent as f32 / 100.0)
+}
+/// Parse a volume: a percentage or a relative percentage `+5%`/`-5%`
+///
+/// # Errors
+///
+/// Returns [CmdError::InvalidArg] if the volume is invalid
+pub fn parse_volume(arg: &str) -> Result<Volume, CmdError> {
+    if let Some(arg) = arg.strip_prefix('+') {
+        Ok(Volume::Up(parse_percent(arg)?))
+    } else if let Some(arg) = arg.strip_prefix('-') {
+        Ok(Volume::Down(parse_percent(arg)?))
+    } else {
+        Ok(Volume::Set(parse_percent(arg)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split() {
+        assert_eq!(split_commands("stop; play-next ;;").unwrap(), vec!["stop", "play-next"]);
+        assert_eq!(split_commands(r#"echo "a; b"; echo 'c;' d\;e"#).unwrap(), vec![
+            r#"echo "a; b""#,
+            r#"echo 'c;' d\;e"#,
+        ]);
+        assert!(matches!(split_commands("echo \"a; b"), Err(CmdError::UnclosedQuote)));
+        assert_eq!(split_commands("echo it's; stop").unwrap(), vec!["echo it's", "stop"]);
+    }
+
+    #[test]
+    fn tokens() {
+        assert_eq!(tokenize("  queue-add  a b ").unwrap(), vec!["queue-add", "a", "b"]);
+        assert_eq!(tokenize(r#"echo "My Album/*" Other\ Album ''"#).unwrap(), vec![
+            "echo",
+            "My Album/*",
+            "Other Album",
+            "",
+        ]);
+        assert_eq!(tokenize(r#"echo 'a \ "b"' "c \" d""#).unwrap(), vec!["echo", r#"a \ "b""#, r#"c " d"#]);
+        assert!(matches!(tokenize("echo 'a"), Err(CmdError::UnclosedQuote)));
+        assert_eq!(tokenize("play-playlist Guns N' Roses").unwrap(), vec!["play-playlist", "Guns", "N'", "Roses"]);
+        assert_eq!(tokenize("play title:don't artist:'Low Roar'").unwrap(), vec!["play", "title:don't", "artist:Low Roar"]);
+    }
+
+    #[test]
+    fn escape() {
+        let value = r#"My "Album"; it's\here"#;
+        assert_eq!(tokenize(&escape_arg(value)).unwrap(), vec![value]);
+        assert_eq!(last_arg_start(r"queue-add My\ Al"), 10);
+        assert_eq!(last_arg_start("queue-add "), 10);
+        assert_eq!(last_arg_start("play-playlist Guns N' Ro"), 22);
+    }
+
+    #[test]
+    fn durations() {
+        let secs = |s: u64| Duration::from_secs(s);
+
+        assert_eq!(parse_duration("90").unwrap(), secs(90));
+        assert_eq!(parse_duration("90s").unwrap(), secs(90));
+        assert_eq!(parse_duration("1m30s").unwrap(), secs(90));
+        assert_eq!(parse_duration("1h2m3").unwrap(), secs(3723));
+        assert_eq!(parse_duration("1:30").unwrap(), secs(90));
+        assert_eq!(parse_duration("1:02:03").unwrap(), secs(3723));
+
+        for invalid in ["", "1x", "m", "1:2:3:4", "1::2", "-1", "99999999999999999999"] {
+            assert!(parse_duration(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn duration_overflow() {
+        assert!(parse_duration(&format!("{}h", u64::MAX / 60)).is_err());
+        assert!(parse_duration(&format!("{}:00", u64::MAX)).is_err());
+        assert!(parse_duration(&format!("{}s1s", u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn positions_and_volumes() {
+        assert_eq!(parse_position("+10s").unwrap(), Position::Forward(Duration::from_secs(10)));
+        assert_eq!(parse_position("-1:00").unwrap(), Position::Backward(Duration::from_secs(60)));
+        assert_eq!(parse_position("5").unwrap(), Position::At(Duration::from_secs(5)));
+
+        assert_eq!(parse_volume("80%").unwrap(), Volume::Set(0.8));
+        assert_eq!(parse_volume("+5").unwrap(), Volume::Up(0.05));
+        assert_eq!(parse_volume("-5%").unwrap(), Volume::Down(0.05));
+        assert!(parse_volume("loud").is_err());
+    }
+}
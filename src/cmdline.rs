@@ -8,26 +8,49 @@ use tuich::{
 
 use crate::{
     app::{AppContext, UpdateError},
-    commands::{exec_command, Cmd},
+    commands::exec_command,
+    completion::{complete, Completion},
+    history::History,
     match_keys,
     Action,
 };
 
+// Consts
+/// Max number of the completion candidates shown at once
+const MAX_COMPLETION_ITEMS: usize = 10;
+
+/// Reverse incremental search through the history
+#[derive(Debug)]
+struct HistorySearch {
+    state: PromptState,
+    /// Index of the found history item
+    found: Option<usize>
+}
+
 /// Command line
 #[derive(Debug)]
 pub struct CmdLine {
     pub state: PromptState,
-    history: Vec<String>,
+    history: History,
     cur_history_item: Option<usize>,
-    is_completing: bool
+    /// Value of the prompt before moving through the history,
+    /// only items starting with it are shown
+    history_prefix: String,
+    history_search: Option<HistorySearch>,
+    is_completing: bool,
+    /// Completion which candidates are being cycled through
+    completion: Option<Completion>
 }
 impl CmdLine {
     pub fn new() -> Self {
         Self {
             state: PromptState::default(),
-            history: vec![],
+            history: History::load(),
             cur_history_item: None,
-            is_completing: false
+            history_prefix: String::new(),
+            history_search: None,
+            is_completing: false,
+            completion: None
         }
     }
 
@@ -36,6 +59,14 @@ impl CmdLine {
         ctx: &mut AppContext,
         key: Key,
     ) -> Result<Action, UpdateError> {
+        if self.history_search.is_some() {
+            self.handle_search_key(ctx, key);
+            if self.history_search.is_none() {
+                self.refresh_completion(ctx);
+            }
+            return Ok(Action::Draw);
+        }
+
         match_keys! {
             ctx.config, key,
 
@@ -44,15 +75,34 @@ impl CmdLine {
                 if self.is_completing {
                     // Don't exit if completion was enabled, just turn it off
                     self.is_completing = false;
+                    self.completion = None;
                 } else {
                     self.exit(ctx)
                 }
             },
-            complete => self.is_completing = true,
-            next_history => self.next_history(),
-            prev_history => self.prev_history();
+            complete => self.complete(ctx, true),
+            complete_prev => self.complete(ctx, false),
+            next_history => {
+                self.next_history();
+                self.refresh_completion(ctx);
+            },
+            prev_history => {
+                self.prev_history();
+                self.refresh_completion(ctx);
+            },
+            search_history => {
+                self.completion = None;
+                self.history_search = Some(HistorySearch {
+                    state: PromptState::default(),
+                    found: None
+                });
+            };
 
-            else { self.state.handle_keys(key); }
+            else {
+                // Candidates are collected again after the input was changed
+                self.state.handle_keys(key);
+                self.refresh_completion(ctx);
+            }
         }
 
         Ok(Action::Draw)
@@ -70,11 +120,9 @@ impl CmdLine {
         // Execute the command
         let result = exec_command(ctx, &value);
 
-        // Save the command to the history and remove old duplicate
-        if let Some(dup_index) = self.history.iter().position(|i| i.eq(&value)) {
-            self.history.remove(dup_index);
-        }
-        self.history.push(value);
+        // Save the command to the history
+        // Failing to save it shouldn't hide result of the command
+        let _ = self.history.push(value, ctx.config.history_size);
 
         self.exit(ctx);
         result
@@ -82,39 +130,105 @@ impl CmdLine {
     fn exit(&mut self, ctx: &mut AppContext) {
         self.state.clear();
         self.is_completing = false;
+        self.completion = None;
         self.cur_history_item = None;
+        self.history_search = None;
         ctx.state.enter_mode(crate::app::Mode::Normal);
     }
 
+    /// Insert next or previous completion candidate into the command line
+    fn complete(&mut self, ctx: &AppContext, forward: bool) {
+        self.is_completing = true;
+
+        let completion = self.completion
+            .get_or_insert_with(|| complete(ctx, self.state.value()));
+        let Some(value) = completion.select(forward) else {
+            return;
+        };
+        let is_single = completion.candidates.len() == 1;
+
+        self.state.set_value(value);
+        self.state.move_end();
+
+        // Complete further on the next time if there was only one candidate,
+        // for example to go into the completed directory
+        if is_single {
+            self.refresh_completion(ctx);
+        }
+    }
+    /// Collect the candidates for the current input if the completion is shown
+    /// They are kept until the input is changed, so they aren't collected on every draw
+    fn refresh_completion(&mut self, ctx: &AppContext) {
+        self.completion = self.is_completing
+            .then(|| complete(ctx, self.state.value()));
+    }
+
+    fn handle_search_key(&mut self, ctx: &AppContext, key: Key) {
+        let Some(search) = &mut self.history_search else {
+            return;
+        };
+
+        match_keys! {
+            ctx.config, key,
+
+            // Accept the found item
+            enter => {
+                if let Some(item) = search.found.and_then(|i| self.history.get(i)) {
+                    self.state.set_value(item.clone());
+                    self.state.move_end();
+                }
+                self.history_search = None;
+            },
+            escape => self.history_search = None,
+            // Find an older item
+            search_history => {
+                if let Some(found) = self.history.find_prev_containing(search.found, search.state.value()) {
+                    search.found = Some(found);
+                }
+            };
+
+            else {
+                search.state.handle_keys(key);
+                search.found = self.history.find_prev_containing(None, search.state.value());
+            }
+        }
+    }
+
     fn next_history(&mut self) {
-        let history_len = self.history.len();
-        if history_len == 0 { return; }
         let Some(cur_item) = self.cur_history_item else {
             return;
         };
-        let cur_item = cur_item + 1;
-
-        if cur_item >= history_len {
-            self.state.clear();
-            self.cur_history_item = None;
-        } else {
-            self.state.set_value(self.history[cur_item].clone());
-            self.cur_history_item = Some(cur_item);
+
+        match self.history.find_next_prefixed(cur_item, &self.history_prefix) {
+            Some(index) => {
+                if let Some(item) = self.history.get(index) {
+                    self.state.set_value(item.clone());
+                }
+                self.cur_history_item = Some(index);
+            }
+            None => {
+                // Back to what was typed
+                self.state.set_value(self.history_prefix.clone());
+                self.cur_history_item = None;
+            }
         }
 
         self.state.move_end();
     }
     fn prev_history(&mut self) {
-        let history_len = self.history.len();
-        if history_len == 0 { return; }
-        let cur_item = match self.cur_history_item {
-            Some(cur_item) => cur_item.saturating_sub(1),
-            None => history_len.saturating_sub(1)
+        if self.cur_history_item.is_none() {
+            self.history_prefix = self.value().clone();
+        }
+
+        let Some(index) = self.history.find_prev_prefixed(self.cur_history_item, &self.history_prefix) else {
+            return;
         };
 
-        self.state.set_value(self.history[cur_item].clone());
+        if let Some(item) = self.history.get(index) {
+            self.state.set_value(item.clone());
+        }
         self.state.move_end();
-        self.cur_history_item = Some(cur_item);
+        self.cur_history_item = Some(index);
     }
 
     pub fn draw(&self, ctx: &AppContext, buf: &mut Buffer, rect: Rect) -> Rect {
@@ -123,6 +237,12 @@ impl CmdLine {
         Clear::new(ctx.config.theme.cmdline)
             .draw(buf, prompt_rect);
 
+        // Draw history search instead of the prompt
+        if let Some(search) = &self.history_search {
+            self.draw_history_search(search, ctx, buf, prompt_rect);
+            return prompt_rect;
+        }
+
         // Draw colon (:)
         buf.set(prompt_rect.pos(), Some(":"), ());
 
@@ -138,51 +258,61 @@ impl CmdLine {
 
         prompt_rect
     }
+    fn draw_history_search(&self, search: &HistorySearch, ctx: &AppContext, buf: &mut Buffer, prompt_rect: Rect) {
+        let style = ctx.config.theme.cmdline;
+        let label_rect = Text::new("(history) ", ())
+            .draw(buf, prompt_rect);
+
+        // Draw query
+        let query_width = search.state.value().chars().count() as u16 + 1;
+        let query_rect = prompt_rect
+            .margin_left(label_rect.width)
+            .with_width(query_width);
+        Prompt::new(&search.state)
+            .style(style)
+            .draw(buf, query_rect);
+
+        // Draw found item
+        let found = match search.found.and_then(|i| self.history.get(i)) {
+            Some(item) => format!(": {}", item),
+            None if search.state.value().is_empty() => String::new(),
+            None => ": <nothing found>".to_string()
+        };
+        Text::new(found, ())
+            .clip(Clip::Ellipsis)
+            .draw(buf, prompt_rect.margin_left(label_rect.width + query_width));
+    }
     fn draw_completion(&self, ctx: &AppContext, buf: &mut Buffer, prompt_rect: Rect) {
-        let value = self.value().trim_start();
-        if value.contains(' ') || value.is_empty() {
+        let Some(completion) = &self.completion else {
             return;
-        }
-
-        let mut compl_height = 0_u16;
-        for cmd in &ctx.commands.list {
-            let alias = match cmd {
-                Cmd::Normal(_, _) => None,
-                Cmd::Alias(_, _, to) => Some(to)
-            };
-
-            let name = cmd.name();
+        };
 
-            if !name.contains(value) && !alias.is_some_and(|a| a.contains(value)) {
-                continue;
-            }
+        // Scroll the list, so the selected candidate is always visible
+        let selected = completion.selected.unwrap_or(0);
+        let offset = (selected + 1).saturating_sub(MAX_COMPLETION_ITEMS);
 
-            let kind = cmd.kind();
-            let args = kind.args();
-
-            let name = match args {
-                Some(args) => format!("{} {}", name, args),
-                None => name.to_string()
-            };
-            let desc = kind.description();
-            let desc = match alias {
-                Some(alias) => format!("(alias to :{alias}) {desc}"),
-                None => desc.to_string()
-            };
+        let candidates = completion.candidates
+            .iter()
+            .enumerate()
+            .skip(offset)
+            .take(MAX_COMPLETION_ITEMS);
 
+        let mut compl_height = 0_u16;
+        for (index, candidate) in candidates {
             let item_rect = prompt_rect.add_y(compl_height + 1);
             let text_rect = item_rect.margin((1, 0));
 
             let style =
-                if alias.is_some() { ctx.config.theme.completion_alias }
+                if candidate.is_alias { ctx.config.theme.completion_alias }
                 else { ctx.config.theme.completion };
+            let style = style.reverse(completion.selected == Some(index));
 
             Clear::new(style)
                 .draw(buf, item_rect);
 
-            let name_rect = Text::from(name)
+            let name_rect = Text::new(&candidate.label, ())
                 .draw(buf, text_rect);
-            Text::from(desc)
+            Text::new(&candidate.desc, ())
                 .clip(Clip::Ellipsis)
                 .draw(buf, text_rect.margin_left(name_rect.width.max(35)));
 
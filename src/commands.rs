@@ -1,8 +1,22 @@
-use std::{path::PathBuf, rc::Rc, time::Duration};
+use std::{cmp::Reverse, collections::BTreeMap, fs, path::{Path, PathBuf}, rc::Rc};
 
 use thiserror::Error;
 
-use crate::{app::{AppContext, UpdateError}, player::{LoopState, QueueTrack}, track::Track, traits::Expand, Action};
+use crate::{
+    app::{AppContext, Notif, UpdateError, View},
+    cmdargs::{parse_duration, parse_percent, parse_position, parse_volume, split_commands, tokenize, ArgKind, ArgSpec, Position, Volume},
+    config::Config,
+    player::{LoopState, PlaybackError, QueueTrack},
+    playlist::{playlists_form_config, Playlist},
+    query::{Query, QueryError},
+    rating::{set_rating, toggle_loved},
+    scan::scan_music_dirs,
+    search::fuzzy_match,
+    track::Track,
+    traits::Expand,
+    watch::watch_config_paths,
+    Action,
+};
 
 // Errors
 #[derive(Debug, Error)]
@@ -11,18 +25,42 @@ pub enum CmdError {
     NoSuchCmd,
     #[error("Not enough arguments")]
     NotEnoughArgs,
+    #[error("Too many arguments")]
+    TooManyArgs,
+    #[error("Unclosed quote")]
+    UnclosedQuote,
     #[error("Invalid argument type \"{0}\"")]
     InvalidArg(String),
     #[error("No such file or directory \"{0}\"")]
-    NoSuchFile(PathBuf)
+    NoSuchFile(PathBuf),
+    #[error("No such playlist \"{0}\"")]
+    NoSuchPlaylist(String),
+    #[error("No such track \"{0}\"")]
+    NoSuchTrack(String),
+    #[error("Invalid query: {0}")]
+    Query(QueryError),
+    #[error("Too many nested commands, probably a recursive command")]
+    TooDeep,
+    #[error("Script error at line {0}: {1}")]
+    Script(usize, String)
 }
 
+// Consts
+/// Max nesting depth of the user-defined commands
+const MAX_DEPTH: usize = 16;
+
 /// Command kind
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum CmdKind {
     Quit,
     Hello,
     Echo,
+    ConfigReload,
+    Stats,
+    History,
+    HistoryClear,
+    Lyrics,
+    Open,
 
     PlayNext,
     PlayPrev,
@@ -31,6 +69,9 @@ pub enum CmdKind {
     Pause,
     Stop,
     Toggle,
+    Play,
+    PlayPlaylist,
+    PlayTrack,
     Seek,
     SeekForward,
     SeekBackward,
@@ -44,24 +85,39 @@ pub enum CmdKind {
     LoopNone,
     LoopQueue,
     LoopShuffle,
+    Loop,
+    Rate,
+    LoveToggle,
 
     QueueAdd,
+    QueueAddQuery,
+    QueuePlaylist,
     QueueClear,
     QueueShuffle,
+    QueuePrev,
 }
 impl CmdKind {
-    pub fn args(&self) -> Option<&'static str> {
+    pub fn args(&self) -> Option<ArgSpec> {
         Some(match self {
-            Self::Echo => "<MSG>",
-
-            Self::Seek => "<SECONDS>",
-            Self::SeekForward => "<SECONDS>",
-            Self::SeekBackward => "<SECONDS>",
-            Self::Volume => "<PERCENTAGE>",
-            Self::VolumeUp => "<PERCENTAGE>",
-            Self::VolumeDown => "<PERCENTAGE>",
-
-            Self::QueueAdd => "<TRACKS>",
+            Self::Echo => ArgSpec::new("<MSG>", ArgKind::Text),
+            Self::Open => ArgSpec::new("<PLAYLIST>", ArgKind::Playlist),
+
+            Self::Play => ArgSpec::new("<QUERY>", ArgKind::Text),
+            Self::PlayPlaylist => ArgSpec::new("<PLAYLIST>", ArgKind::Playlist),
+            Self::PlayTrack => ArgSpec::new("<TRACK>", ArgKind::Track),
+            Self::Seek => ArgSpec::new("<POSITION>", ArgKind::Position),
+            Self::SeekForward => ArgSpec::new("<DURATION>", ArgKind::Duration),
+            Self::SeekBackward => ArgSpec::new("<DURATION>", ArgKind::Duration),
+            Self::Volume => ArgSpec::new("<VOLUME>", ArgKind::Volume),
+            Self::VolumeUp => ArgSpec::new("<PERCENTAGE>", ArgKind::Percent),
+            Self::VolumeDown => ArgSpec::new("<PERCENTAGE>", ArgKind::Percent),
+
+            Self::Loop => ArgSpec::new("<STATE>", ArgKind::Choice(&["none", "queue", "shuffle"])),
+            Self::Rate => ArgSpec::new("<STARS>", ArgKind::Choice(&["0", "1", "2", "3", "4", "5"])),
+
+            Self::QueueAdd => ArgSpec::multiple("<TRACKS>", ArgKind::Path),
+            Self::QueueAddQuery => ArgSpec::new("<QUERY>", ArgKind::Text),
+            Self::QueuePlaylist => ArgSpec::new("<PLAYLIST>", ArgKind::Playlist),
 
             _ => return None
         })
@@ -71,6 +127,12 @@ impl CmdKind {
             Self::Quit => "Say \"goodbye\" to VORU",
             Self::Hello => "Say \"hello\" to VORU!",
             Self::Echo => "Say something else",
+            Self::ConfigReload => "Load the config file again",
+            Self::Stats => "Show play statistics",
+            Self::History => "Show recently played tracks",
+            Self::HistoryClear => "Forget recently played tracks",
+            Self::Lyrics => "Show lyrics of the current track",
+            Self::Open => "Show tracks of <PLAYLIST>, the name can be fuzzy",
 
             Self::PlayNext => "Play next track in the queue",
             Self::PlayPrev => "Play previous track in the queue",
@@ -79,10 +141,13 @@ impl CmdKind {
             Self::Pause => "Pause playback",
             Self::Stop => "Stop playback and clear currently playing track",
             Self::Toggle => "Resume/pause playback",
-            Self::Seek => "Seek to <SECONDS>",
-            Self::SeekForward => "Seek forward by <SECONDS>",
-            Self::SeekBackward => "Seek backward by <SECONDS>",
-            Self::Volume => "Set volume to <PERCENTAGE>",
+            Self::Play => "Play all the known tracks matching <QUERY> (`artist:\"Low Roar\" year:>2014`)",
+            Self::PlayPlaylist => "Play <PLAYLIST> from the start, the name can be fuzzy",
+            Self::PlayTrack => "Play <TRACK> from the library with the rest of its album",
+            Self::Seek => "Seek to <POSITION> (`1:30`), or relatively (`+10s`, `-10s`)",
+            Self::SeekForward => "Seek forward by <DURATION>",
+            Self::SeekBackward => "Seek backward by <DURATION>",
+            Self::Volume => "Set volume to <VOLUME> (`80%`), or change it (`+5%`, `-5%`)",
             Self::VolumeUp => "Increase volume by <PERCENTAGE>",
             Self::VolumeDown => "Decrease volume by <PERCENTAGE>",
             Self::VolumeReset => "Reset volume to 100%",
@@ -92,10 +157,16 @@ impl CmdKind {
             Self::LoopNone => "Disable looping",
             Self::LoopQueue => "Repeat the queue after the end",
             Self::LoopShuffle => "Shuffle and repeat the queue after the end",
+            Self::Loop => "Set looping to <STATE>: none, queue or shuffle",
+            Self::Rate => "Rate the current track with <STARS>, `0` removes the rating",
+            Self::LoveToggle => "Love/unlove the current track",
 
             Self::QueueAdd => "Add <TRACKS> to the queue",
+            Self::QueueAddQuery => "Add all the known tracks matching <QUERY> to the queue",
+            Self::QueuePlaylist => "Add tracks of <PLAYLIST> to the queue, the name can be fuzzy",
             Self::QueueClear => "Clear the queue",
-            Self::QueueShuffle => "Randomize order of the queue"
+            Self::QueueShuffle => "Randomize order of the queue",
+            Self::QueuePrev => "Go back to the previous queue"
         }
     }
 }
@@ -104,39 +175,72 @@ impl CmdKind {
 #[derive(Debug)]
 pub enum Cmd {
     Normal(&'static str, CmdKind),
-    Alias(&'static str, CmdKind, &'static str)
+    Alias(&'static str, CmdKind, &'static str),
+    /// User-defined command from the config
+    /// Executes one or multiple commands separated by `;`
+    Custom(String, String)
 }
 impl Cmd {
-    pub fn name(&self) -> &'static str {
+    pub fn name(&self) -> &str {
         match self {
             Self::Normal(name, _) => name,
-            Self::Alias(name, _, _) => name
+            Self::Alias(name, _, _) => name,
+            Self::Custom(name, _) => name
+        }
+    }
+    pub fn kind(&self) -> Option<&CmdKind> {
+        match self {
+            Self::Normal(_, kind) => Some(kind),
+            Self::Alias(_, kind, _) => Some(kind),
+            Self::Custom(_, _) => None
         }
     }
-    pub fn kind(&self) -> &CmdKind {
+    pub fn alias(&self) -> Option<&'static str> {
         match self {
-            Self::Normal(_, kind) => kind,
-            Self::Alias(_, kind, _) => kind
+            Self::Alias(_, _, to) => Some(to),
+            _ => None
         }
     }
     pub fn is_alias(&self) -> bool {
         matches!(self, Self::Alias(_, _, _))
     }
+
+    /// Returns command name with its args: `"command <ARGS>"`
+    pub fn usage(&self) -> String {
+        match self.kind().and_then(|k| k.args()) {
+            Some(args) => format!("{} {}", self.name(), args.name),
+            None => self.name().to_string()
+        }
+    }
+    /// Returns command description: `"(alias to :command) Command description"`
+    pub fn description(&self) -> String {
+        match self {
+            Self::Normal(_, kind) => kind.description().to_string(),
+            Self::Alias(_, kind, to) => format!("(alias to :{to}) {}", kind.description()),
+            Self::Custom(_, body) => format!("(runs :{body})")
+        }
+    }
 }
 
 /// Commands
 #[derive(Debug)]
 pub struct Commands {
-    pub list: [Cmd; 40]
+    pub list: Vec<Cmd>
 }
 impl Commands {
     pub fn new() -> Self {
-        Self { list: [
+        Self { list: vec![
             Cmd::Normal("quit", CmdKind::Quit),
             Cmd::Alias("q", CmdKind::Quit, "quit"),
             Cmd::Alias("bye", CmdKind::Quit, "quit"),
             Cmd::Normal("hello", CmdKind::Hello),
             Cmd::Normal("echo", CmdKind::Echo),
+            Cmd::Normal("config-reload", CmdKind::ConfigReload),
+            Cmd::Normal("stats", CmdKind::Stats),
+            Cmd::Normal("history", CmdKind::History),
+            Cmd::Normal("history-clear", CmdKind::HistoryClear),
+            Cmd::Normal("lyrics", CmdKind::Lyrics),
+            Cmd::Normal("open", CmdKind::Open),
 
             Cmd::Normal("play-next", CmdKind::PlayNext),
             Cmd::Alias("next", CmdKind::PlayNext, "play-next"),
@@ -147,6 +251,9 @@ impl Commands {
             Cmd::Normal("pause", CmdKind::Pause),
             Cmd::Normal("stop", CmdKind::Stop),
             Cmd::Normal("toggle", CmdKind::Toggle),
+            Cmd::Normal("play", CmdKind::Play),
+            Cmd::Normal("play-playlist", CmdKind::PlayPlaylist),
+            Cmd::Normal("play-track", CmdKind::PlayTrack),
             Cmd::Normal("seek", CmdKind::Seek),
             Cmd::Normal("seek-forw", CmdKind::SeekForward),
             Cmd::Alias("seekf", CmdKind::SeekForward, "seek-forw"),
@@ -167,77 +274,151 @@ impl Commands {
             Cmd::Normal("loop-none", CmdKind::LoopNone),
             Cmd::Normal("loop-queue", CmdKind::LoopQueue),
             Cmd::Normal("loop-shuffle", CmdKind::LoopShuffle),
+            Cmd::Normal("loop", CmdKind::Loop),
 
             Cmd::Normal("queue-add", CmdKind::QueueAdd),
             Cmd::Alias("add", CmdKind::QueueAdd, "queue-add"),
+            Cmd::Normal("queue-add-query", CmdKind::QueueAddQuery),
+            Cmd::Normal("queue-playlist", CmdKind::QueuePlaylist),
             Cmd::Normal("queue-clear", CmdKind::QueueClear),
             Cmd::Alias("clear", CmdKind::QueueClear, "queue-clear"),
             Cmd::Normal("queue-shuffle", CmdKind::QueueShuffle),
             Cmd::Alias("shuffle", CmdKind::QueueShuffle, "queue-shuffle"),
+            Cmd::Normal("queue-prev", CmdKind::QueuePrev),
+
+            Cmd::Normal("rate", CmdKind::Rate),
+            Cmd::Normal("love-toggle", CmdKind::LoveToggle),
+            Cmd::Alias("love", CmdKind::LoveToggle, "love-toggle"),
         ] }
     }
+    /// Returns builtin commands together with the user-defined ones
+    /// User-defined commands shadow builtin commands with the same name
+    pub fn with_custom(custom: &BTreeMap<String, String>) -> Self {
+        let mut commands = Self::new();
+
+        for (name, body) in custom {
+            commands.list.retain(|c| c.name() != name.as_str());
+            commands.list.push(Cmd::Custom(name.clone(), body.clone()));
+        }
+
+        commands
+    }
 
     /// Returns formatted list of the commands:
     /// `(is_alias, "command <ARGS>", "(alias to :command) Command description")`
     pub fn formatted_list(&self) -> Vec<(bool, String, String)> {
-        let mut result = vec![];
-
-        for cmd in &self.list {
-            let alias = match cmd {
-                Cmd::Normal(_, _) => None,
-                Cmd::Alias(_, _, to) => Some(to)
-            };
-
-            let name = cmd.name();
-            let kind = cmd.kind();
-            let args = kind.args();
-
-            let name = match args {
-                Some(args) => format!("{} {}", name, args),
-                None => name.to_string()
-            };
-            let desc = kind.description();
-            let desc = match alias {
-                Some(alias) => format!("(alias to :{alias}) {desc}"),
-                None => desc.to_string()
-            };
-
-            result.push((alias.is_some(), name, desc));
-        }
-
-        result
+        self.list
+            .iter()
+            .map(|cmd| (cmd.is_alias(), cmd.usage(), cmd.description()))
+            .collect()
     }
 
     pub fn find<S: AsRef<str>>(&self, name: S) -> Option<&Cmd> {
-        let index = self.list
+        self.list
             .iter()
-            .position(|c| c.name().eq(name.as_ref()))?;
-        Some(&self.list[index])
+            .find(|c| c.name().eq(name.as_ref()))
     }
 }
 
 /// Execute command with args by given string
 /// For example: `"queue-add ~/my-cool-music-dir/*"`
 pub fn exec_command<S: AsRef<str>>(ctx: &mut AppContext, command: S) -> Result<Action, UpdateError> {
-    let command = command.as_ref().trim();
-    let (cmd_name, args_str) = match command.split_once(' ') {
+    exec_nested_command(ctx, command.as_ref(), 0)
+}
+/// Execute every line of a script file as a command
+/// Empty lines and lines starting with `#` are skipped
+///
+/// # Errors
+///
+/// Stops and returns an error at the first line that failed
+pub fn exec_script<P: AsRef<Path>>(ctx: &mut AppContext, path: P) -> Result<Action, UpdateError> {
+    let content = fs::read_to_string(path)
+        .map_err(UpdateError::Io)?;
+    let mut action = Action::Nope;
+
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { continue; }
+
+        match exec_command(ctx, line) {
+            Ok(Action::Quit) => return Ok(Action::Quit),
+            Ok(a) => action = action | a,
+            Err(e) => return Err(CmdError::Script(index + 1, e.to_string()).into())
+        }
+    }
+
+    Ok(action)
+}
+
+fn exec_nested_command(ctx: &mut AppContext, input: &str, depth: usize) -> Result<Action, UpdateError> {
+    if depth > MAX_DEPTH {
+        return Err(CmdError::TooDeep.into());
+    }
+
+    let mut action = Action::Nope;
+
+    for command in split_commands(input)? {
+        match exec_single_command(ctx, &command, depth)? {
+            Action::Quit => return Ok(Action::Quit),
+            a => action = action | a
+        }
+    }
+
+    Ok(action)
+}
+
+fn exec_single_command(ctx: &mut AppContext, command: &str, depth: usize) -> Result<Action, UpdateError> {
+    let (cmd_name, args_str) = match command.split_once(char::is_whitespace) {
         Some((cmd, args)) => (cmd, args.trim()),
         None => (command, "")
     };
-    let args: Vec<&str> = args_str
-        .split(' ')
-        .filter(|a| !a.is_empty())
-        .collect();
-
-    let first_arg = args.first();
 
     let cmd = ctx.commands.find(cmd_name)
         .ok_or(CmdError::NoSuchCmd)?;
 
-    match cmd.kind() {
+    let kind = match cmd {
+        Cmd::Custom(_, body) => {
+            let body = body.clone();
+            return exec_custom_command(ctx, &body, args_str, depth);
+        }
+        Cmd::Normal(_, kind) |
+        Cmd::Alias(_, kind, _) => kind.clone()
+    };
+
+    // The text is taken as is, so `echo it's` doesn't need quoting and keeps the spaces
+    let args = match kind.args() {
+        Some(spec) if spec.kind == ArgKind::Text => {
+            if args_str.is_empty() { vec![] }
+            else { vec![args_str.to_string()] }
+        }
+        _ => tokenize(args_str)?
+    };
+    match kind.args() {
+        Some(spec) => spec.check(&args)?,
+        None if !args.is_empty() => return Err(CmdError::TooManyArgs.into()),
+        None => ()
+    }
+
+    // Args are already checked, so the first one is always there if the command has args
+    let first_arg = args.first().map(String::as_str).unwrap_or_default();
+
+    match kind {
         CmdKind::Quit => return Ok(Action::Quit),
         CmdKind::Hello => ctx.state.notify("hey"),
         CmdKind::Echo => ctx.state.notify(args_str),
+        CmdKind::ConfigReload => cmd_config_reload(ctx)?,
+        CmdKind::Stats => ctx.state.view = View::Stats,
+        CmdKind::History => ctx.state.view = View::History,
+        CmdKind::Lyrics => ctx.state.view = View::Lyrics,
+        CmdKind::Open => {
+            let index = find_playlist(ctx, &args.join(" "))?;
+            ctx.state.open = Some(ctx.player.playlists[index].borrow().id);
+            ctx.state.view = View::Tracks;
+        }
+        CmdKind::HistoryClear => {
+            ctx.stats.clear_history()?;
+            ctx.state.notify("History was cleared");
+        }
 
         CmdKind::PlayNext => ctx.player.play_next()?,
         CmdKind::PlayPrev => ctx.player.play_prev()?,
@@ -246,10 +427,28 @@ pub fn exec_command<S: AsRef<str>>(ctx: &mut AppContext, command: S) -> Result<A
         CmdKind::Pause => ctx.player.pause()?,
         CmdKind::Stop => ctx.player.stop()?,
         CmdKind::Toggle => ctx.player.toggle()?,
-        CmdKind::Seek => ctx.player.seek(parse_secs(args.first())?)?,
-        CmdKind::SeekForward => ctx.player.seek_forward(parse_secs(first_arg)?)?,
-        CmdKind::SeekBackward => ctx.player.seek_backward(parse_secs(first_arg)?)?,
-        CmdKind::Volume => ctx.player.set_volume(parse_percent(first_arg)?)?,
+        CmdKind::Play => {
+            let tracks = query_tracks(ctx, args_str)?;
+            ctx.state.notify(format!("Playing {} tracks", tracks.len()));
+            ctx.player.play_tracks(tracks, 0)?;
+        }
+        CmdKind::PlayPlaylist => {
+            let index = find_playlist(ctx, &args.join(" "))?;
+            ctx.player.play_playlist(index, 0)?;
+        }
+        CmdKind::PlayTrack => cmd_play_track(ctx, &args.join(" "))?,
+        CmdKind::Seek => match parse_position(first_arg)? {
+            Position::At(pos) => ctx.player.seek(pos)?,
+            Position::Forward(dur) => ctx.player.seek_forward(dur)?,
+            Position::Backward(dur) => ctx.player.seek_backward(dur)?,
+        },
+        CmdKind::SeekForward => ctx.player.seek_forward(parse_duration(first_arg)?)?,
+        CmdKind::SeekBackward => ctx.player.seek_backward(parse_duration(first_arg)?)?,
+        CmdKind::Volume => match parse_volume(first_arg)? {
+            Volume::Set(value) => ctx.player.set_volume(value)?,
+            Volume::Up(value) => ctx.player.volume_up(value)?,
+            Volume::Down(value) => ctx.player.volume_down(value)?,
+        },
         CmdKind::VolumeUp => ctx.player.volume_up(parse_percent(first_arg)?)?,
         CmdKind::VolumeDown => ctx.player.volume_down(parse_percent(first_arg)?)?,
         CmdKind::VolumeReset => ctx.player.set_volume(1.0)?,
@@ -259,16 +458,62 @@ pub fn exec_command<S: AsRef<str>>(ctx: &mut AppContext, command: S) -> Result<A
         CmdKind::LoopNone => ctx.player.set_loop(LoopState::None),
         CmdKind::LoopQueue => ctx.player.set_loop(LoopState::Queue),
         CmdKind::LoopShuffle => ctx.player.set_loop(LoopState::Shuffle),
+        CmdKind::Loop => ctx.player.set_loop(match first_arg {
+            "queue" => LoopState::Queue,
+            "shuffle" => LoopState::Shuffle,
+            _ => LoopState::None
+        }),
+        CmdKind::Rate => {
+            let path = cur_track_path(ctx)?;
+            set_rating(ctx, &path, first_arg.parse().unwrap_or_default())?;
+        }
+        CmdKind::LoveToggle => {
+            let path = cur_track_path(ctx)?;
+            let loved = toggle_loved(ctx, &path)?;
+            ctx.state.notify(if loved { "The track was loved" } else { "The track was unloved" });
+        }
 
         CmdKind::QueueAdd => cmd_add(ctx, args)?,
+        CmdKind::QueueAddQuery => {
+            let tracks = query_tracks(ctx, args_str)?;
+            ctx.state.notify(format!("{} tracks were added", tracks.len()));
+            for track in tracks {
+                ctx.player.queue_add_track(track);
+            }
+        }
+        CmdKind::QueuePlaylist => {
+            let index = find_playlist(ctx, &args.join(" "))?;
+            ctx.player.queue_add_playlist(index)?;
+        }
         CmdKind::QueueClear => ctx.player.queue_clear()?,
         CmdKind::QueueShuffle => ctx.player.queue_shuffle(),
+        CmdKind::QueuePrev => ctx.player.queue_restore_prev()?,
     }
 
     Ok(Action::Draw)
 }
 
-fn cmd_add(ctx: &mut AppContext, args: Vec<&str>) -> Result<(), UpdateError> {
+/// Execute commands of a user-defined command one by one
+/// Args of the user-defined command are passed to its last command
+fn exec_custom_command(ctx: &mut AppContext, body: &str, args: &str, depth: usize) -> Result<Action, UpdateError> {
+    let commands = split_commands(body)?;
+    let mut action = Action::Nope;
+
+    for (index, command) in commands.iter().enumerate() {
+        let command =
+            if index + 1 == commands.len() && !args.is_empty() { format!("{} {}", command, args) }
+            else { command.clone() };
+
+        match exec_nested_command(ctx, &command, depth + 1)? {
+            Action::Quit => return Ok(Action::Quit),
+            a => action = action | a
+        }
+    }
+
+    Ok(action)
+}
+
+fn cmd_add(ctx: &mut AppContext, args: Vec<String>) -> Result<(), UpdateError> {
     if args.is_empty() {
         return Err(CmdError::NotEnoughArgs.into());
     }
@@ -299,18 +544,127 @@ fn cmd_add(ctx: &mut AppContext, args: Vec<&str>) -> Result<(), UpdateError> {
     Ok(())
 }
 
-fn parse_secs<S: AsRef<str>>(arg: Option<S>) -> Result<Duration, CmdError> {
-    let arg = arg.ok_or(CmdError::NotEnoughArgs)?;
-    let arg = arg.as_ref();
-    let secs = arg.parse::<u64>().map_err(|_| CmdError::InvalidArg(arg.to_string()))?;
+/// Returns all the known tracks matching the query
+fn query_tracks(ctx: &AppContext, query: &str) -> Result<Vec<Rc<Track>>, CmdError> {
+    let parsed = Query::parse(query)
+        .map_err(CmdError::Query)?;
+    let tracks = ctx.cache.query(&parsed, &ctx.stats);
 
-    Ok(Duration::from_secs(secs))
+    if tracks.is_empty() {
+        return Err(CmdError::NoSuchTrack(query.to_string()));
+    }
+    Ok(tracks)
+}
+
+fn cmd_play_track(ctx: &mut AppContext, title: &str) -> Result<(), UpdateError> {
+    let found = ctx.library.artists
+        .iter()
+        .flat_map(|g| &g.albums)
+        .find_map(|a| {
+            a.tracks
+                .iter()
+                .position(|t| t.title().eq_ignore_ascii_case(title))
+                .map(|index| (a.tracks.clone(), index))
+        });
+    let (tracks, index) = found
+        .ok_or(CmdError::NoSuchTrack(title.to_string()))?;
+
+    ctx.player.play_tracks(tracks, index)?;
+    Ok(())
 }
-fn parse_percent<S: AsRef<str>>(arg: Option<S>) -> Result<f32, CmdError> {
-    let arg = arg.ok_or(CmdError::NotEnoughArgs)?;
-    let arg = arg.as_ref().trim_end_matches('%');
-    let percent = arg.parse::<u16>()
-        .map_err(|_| CmdError::InvalidArg(arg.to_string()))?;
 
-    Ok(percent as f32 / 100.0)
+fn cmd_config_reload(ctx: &mut AppContext) -> Result<(), UpdateError> {
+    // Keep the current config if the new one is broken
+    let config = Config::from_path(&ctx.config_path)
+        .map_err(UpdateError::Config)?;
+
+    let playlists_changed = config.playlists != ctx.config.playlists;
+    let music_dirs_changed = config.music_dirs != ctx.config.music_dirs;
+    let watch_changed = playlists_changed ||
+        music_dirs_changed ||
+        config.watch != ctx.config.watch ||
+        config.watch_config != ctx.config.watch_config;
+
+    // Build all the playlists before anything is replaced,
+    // so a broken playlist or smart playlist rule doesn't leave the config half-applied
+    let playlists =
+        if playlists_changed {
+            Some(playlists_form_config(&mut ctx.cache, &ctx.stats, &config).map_err(UpdateError::LoadPlaylists)?)
+        } else {
+            None
+        };
+    // New playlists already have the smart ones
+    let smart_playlists =
+        if playlists.is_none() {
+            config.smart_playlists
+                .iter()
+                .map(|p| Playlist::from_config(&ctx.cache, &ctx.stats, p))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(UpdateError::Playlist)?
+        } else {
+            vec![]
+        };
+
+    ctx.config = config;
+    ctx.commands = Commands::with_custom(&ctx.config.commands);
+    ctx.scrobbler.configure(&ctx.config.scrobble);
+    ctx.stats.record = ctx.config.stats;
+
+    match playlists {
+        Some(playlists) => ctx.player.replace_playlists(playlists),
+        None => ctx.player.replace_config_playlists(smart_playlists)
+    }
+
+    // Tracks from the removed dirs stay known until the restart
+    if music_dirs_changed && !ctx.config.music_dirs.is_empty() {
+        scan_music_dirs(ctx.config.music_dirs.clone(), ctx.sender.clone());
+    }
+
+    if watch_changed {
+        // Stop the old watcher first, so the paths aren't watched twice
+        ctx.watcher = None;
+
+        if ctx.config.watch || ctx.config.watch_config {
+            match watch_config_paths(&ctx.config, &ctx.config_path, ctx.sender.clone()) {
+                Ok(watcher) => ctx.watcher = Some(watcher),
+                Err(e) => {
+                    ctx.state.notify(Notif::Error(format!("Config was reloaded, but unable to watch files: {}", e)));
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    ctx.state.notify("Config was reloaded");
+    Ok(())
+}
+
+/// Returns path of the currently playing track
+fn cur_track_path(ctx: &AppContext) -> Result<PathBuf, PlaybackError> {
+    ctx.player.cur_track
+        .as_ref()
+        .map(|t| t.path.clone())
+        .ok_or(PlaybackError::NoTrack)
+}
+
+/// Returns index of the playlist with the name
+/// If there is no playlist with exactly the same name, returns the best fuzzy match
+fn find_playlist(ctx: &AppContext, name: &str) -> Result<usize, CmdError> {
+    let playlists = &ctx.player.playlists;
+
+    if let Some(index) = playlists.iter().position(|p| p.borrow().name.eq_ignore_ascii_case(name)) {
+        return Ok(index);
+    }
+
+    // Prefer shorter names if scores are the same
+    playlists
+        .iter()
+        .enumerate()
+        .filter_map(|(index, p)| {
+            let name_len = p.borrow().name.len();
+            fuzzy_match(name, &p.borrow().name).map(|score| (index, score, Reverse(name_len)))
+        })
+        .max_by_key(|(_, score, len)| (*score, *len))
+        .map(|(index, _, _)| index)
+        .ok_or(CmdError::NoSuchPlaylist(name.to_string()))
 }
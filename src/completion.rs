@@ -0,0 +1,196 @@
+use std::{collections::HashSet, fs, path::PathBuf};
+
+use crate::{
+    app::AppContext,
+    cmdargs::{escape_arg, last_arg_start, tokenize, ArgKind},
+    traits::Expand,
+};
+
+/// Completion candidate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// Value inserted into the command line
+    pub value: String,
+    /// Value shown in the completion list
+    pub label: String,
+    pub desc: String,
+    pub is_alias: bool,
+}
+impl Candidate {
+    fn new<S: ToString>(value: String, label: S) -> Self {
+        Self {
+            value,
+            label: label.to_string(),
+            desc: String::new(),
+            is_alias: false,
+        }
+    }
+    fn with_desc<S: ToString>(mut self, desc: S) -> Self {
+        self.desc = desc.to_string();
+        self
+    }
+}
+
+/// Completion of the command line input
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// Input before the completed part, candidates are appended to it
+    pub base: String,
+    pub candidates: Vec<Candidate>,
+    /// Index of the candidate inserted into the command line
+    pub selected: Option<usize>,
+}
+impl Completion {
+    /// Select next candidate, or previous one if `forward` is false
+    /// Returns the whole command line with the candidate
+    pub fn select(&mut self, forward: bool) -> Option<String> {
+        let len = self.candidates.len();
+        if len == 0 {
+            return None;
+        }
+
+        let index = match self.selected {
+            Some(index) if forward => (index + 1) % len,
+            Some(index) => (index + len - 1) % len,
+            None if forward => 0,
+            None => len - 1
+        };
+        self.selected = Some(index);
+
+        Some(format!("{}{}", self.base, self.candidates[index].value))
+    }
+}
+
+/// Complete a command name or an argument of the command at the end of the input
+pub fn complete(ctx: &AppContext, input: &str) -> Completion {
+    let input = input.trim_start();
+
+    let Some((cmd_name, args)) = input.split_once(char::is_whitespace) else {
+        return complete_cmd_name(ctx, input);
+    };
+
+    let spec = ctx.commands
+        .find(cmd_name)
+        .and_then(|c| c.kind())
+        .and_then(|k| k.args());
+    let Some(spec) = spec else {
+        return Completion::default();
+    };
+
+    // Joined args are completed all at once
+    let args_start = input.len() - args.len();
+    let start =
+        if spec.kind.is_joined() { args_start + (args.len() - args.trim_start().len()) }
+        else { args_start + last_arg_start(args) };
+
+    let Ok(arg) = tokenize(&input[start..]) else {
+        return Completion::default();
+    };
+    let arg = arg.join(" ");
+
+    let candidates = match spec.kind {
+        ArgKind::Path => complete_path(&arg),
+        ArgKind::Playlist => complete_playlist(ctx, &arg),
+        ArgKind::Track => complete_track(ctx, &arg),
+        ArgKind::Choice(values) => values
+            .iter()
+            .filter(|v| v.starts_with(&arg))
+            .map(|v| Candidate::new(v.to_string(), v))
+            .collect(),
+        _ => vec![]
+    };
+
+    Completion {
+        base: input[..start].to_string(),
+        candidates,
+        selected: None
+    }
+}
+
+fn complete_cmd_name(ctx: &AppContext, input: &str) -> Completion {
+    let candidates = ctx.commands.list
+        .iter()
+        .filter(|c| c.name().contains(input) || c.alias().is_some_and(|a| a.contains(input)))
+        .map(|c| Candidate {
+            value: c.name().to_string(),
+            label: c.usage(),
+            desc: c.description(),
+            is_alias: c.is_alias(),
+        })
+        .collect();
+
+    Completion { base: String::new(), candidates, selected: None }
+}
+
+fn complete_path(arg: &str) -> Vec<Candidate> {
+    // Split the arg into a directory and a beginning of a file name
+    let (dir, prefix) = match arg.rfind('/') {
+        Some(index) => arg.split_at(index + 1),
+        None => ("", arg)
+    };
+
+    let read_dir =
+        if dir.is_empty() { PathBuf::from(".") }
+        else { dir.expand().unwrap_or(dir.into()) };
+    let Ok(entries) = fs::read_dir(read_dir) else {
+        return vec![];
+    };
+
+    let mut candidates: Vec<Candidate> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Hidden files are completed only if asked explicitly
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+
+            let label =
+                if entry.path().is_dir() { format!("{}/", name) }
+                else { name };
+
+            Some(Candidate::new(escape_arg(&format!("{}{}", dir, label)), label))
+        })
+        .collect();
+
+    candidates.sort_by(|a, b| a.label.cmp(&b.label));
+    candidates
+}
+
+fn complete_playlist(ctx: &AppContext, arg: &str) -> Vec<Candidate> {
+    let arg = arg.to_lowercase();
+
+    ctx.player.playlists
+        .iter()
+        .map(|p| p.borrow())
+        .filter(|p| p.name.to_lowercase().starts_with(&arg))
+        .map(|p| {
+            Candidate::new(escape_arg(&p.name), &p.name)
+                .with_desc(format!("{} tracks", p.tracks.len()))
+        })
+        .collect()
+}
+
+fn complete_track(ctx: &AppContext, arg: &str) -> Vec<Candidate> {
+    let arg = arg.to_lowercase();
+    let mut candidates = vec![];
+    let mut titles = HashSet::new();
+
+    for group in &ctx.library.artists {
+        for album in &group.albums {
+            for track in &album.tracks {
+                let title = track.title();
+                if !title.to_lowercase().starts_with(&arg) || !titles.insert(title) {
+                    continue;
+                }
+
+                candidates.push(
+                    Candidate::new(escape_arg(title), title)
+                        .with_desc(format!("{} — {}", group.name(), album.name()))
+                );
+            }
+        }
+    }
+
+    candidates
+}
@@ -1,10 +1,18 @@
-use std::{env::var, fs, io, path::{Path, PathBuf}};
+use std::{collections::BTreeMap, env::var, fs, io, path::{Path, PathBuf}};
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tuich::{event::{Key, KeyCode, KeyMod}, style::{Color, Style, Stylized}};
 
-use crate::{key, widget::PlayerStyle};
+use crate::{
+    cover::CoverProtocol,
+    format::{Template, PLAYER_VARS},
+    key,
+    keyseq::KeyBindings,
+    playlist::SmartSort,
+    visualizer::VisualizerStyle,
+    widget::{ColumnAlign, PlayerStyle, TrackColumn},
+};
 
 // Errors
 #[derive(Debug, Error)]
@@ -14,7 +22,9 @@ pub enum ConfigError {
     #[error("Bad syntax: {0}")]
     Parse(toml::de::Error),
     #[error("$HOME variable not found")]
-    NoHomeVar
+    NoHomeVar,
+    #[error("Unknown variable \"{1}\" in format.{0}")]
+    UnknownVar(&'static str, String),
 }
 
 // Sections
@@ -35,16 +45,22 @@ pub struct ConfigTheme {
     pub playlist_selected_playing: Style,
     pub playlist_paused: Style,
     pub playlist_selected_paused: Style,
+    pub playlist_matched: Style,
     pub track: Style,
     pub track_selected: Style,
     pub track_playing: Style,
     pub track_selected_playing: Style,
     pub track_paused: Style,
     pub track_selected_paused: Style,
+    pub track_matched: Style,
+    pub track_marked: Style,
+    pub rating: Style,
 
     pub notif_normal: Style,
     pub notif_error: Style,
     pub cmdline: Style,
+    pub searchline: Style,
+    pub pending_keys: Style,
     pub completion: Style,
     pub completion_alias: Style,
 }
@@ -64,16 +80,22 @@ impl Default for ConfigTheme {
             playlist_selected_playing: Color::Green.reverse(),
             playlist_paused: Color::Blue.into(),
             playlist_selected_paused: Color::Blue.reverse(),
+            playlist_matched: Color::Yellow.into(),
             track: Style::empty(),
             track_selected: Style::cleared().reverse(true),
             track_playing: Color::Green.into(),
             track_selected_playing: Color::Green.reverse(),
             track_paused: Color::Blue.into(),
             track_selected_paused: Color::Blue.reverse(),
+            track_matched: Color::Yellow.into(),
+            track_marked: Color::Magenta.into(),
+            rating: Color::Yellow.into(),
 
             notif_normal: Style::cleared().fg(Color::Black).bg(Color::Blue),
             notif_error: Style::cleared().fg(Color::Black).bg(Color::Red),
             cmdline: Style::cleared().fg(Color::Black).bg(Color::Magenta),
+            searchline: Style::cleared().fg(Color::Black).bg(Color::Yellow),
+            pending_keys: Color::Yellow.into(),
             completion: Style::cleared().fg(Color::Black).bg(Color::Magenta),
             completion_alias: Style::cleared().fg(Color::Black).bg(Color::Magenta).italic(true),
         }
@@ -87,6 +109,45 @@ pub struct ConfigFormat {
     pub progress: char,
     pub progress_track: char,
     pub progress_thumb: String,
+
+    /// Track info on the left side of the player
+    pub player_track: Template,
+    /// Play info on the right side of the player
+    pub player_info: Template,
+    /// Shown in the player if nothing is playing
+    pub player_idle: Template,
+    /// Title of the terminal window, an empty template keeps the title as is
+    pub window_title: Template,
+    /// Description of the queue view
+    /// Variables: `index`, `count`, `elapsed`, `duration`
+    pub queue_desc: Template,
+    /// Description of the playlist and album tracks
+    /// Variables: `count`, `duration`
+    pub tracks_desc: Template,
+}
+impl ConfigFormat {
+    /// Check that the templates use only the variables they have
+    ///
+    /// # Errors
+    ///
+    /// Returns an error with the first unknown variable
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let templates: [(&'static str, &Template, &[&str]); 6] = [
+            ("player_track", &self.player_track, &PLAYER_VARS),
+            ("player_info", &self.player_info, &PLAYER_VARS),
+            ("player_idle", &self.player_idle, &PLAYER_VARS),
+            ("window_title", &self.window_title, &PLAYER_VARS),
+            ("queue_desc", &self.queue_desc, &["index", "count", "elapsed", "duration"]),
+            ("tracks_desc", &self.tracks_desc, &["count", "duration"]),
+        ];
+
+        for (name, template, known) in templates {
+            if let Some(var) = template.find_unknown_var(known) {
+                return Err(ConfigError::UnknownVar(name, var.to_string()));
+            }
+        }
+        Ok(())
+    }
 }
 impl Default for ConfigFormat {
     fn default() -> Self {
@@ -94,6 +155,13 @@ impl Default for ConfigFormat {
             progress: '─',
             progress_track: '─',
             progress_thumb: "".into(),
+
+            player_track: default_template("{state}  {title}[ - {artist}]"),
+            player_info: default_template("{pos} / {dur}  {loop}  {volume}"),
+            player_idle: default_template("There should be some smart quote... - Unknown man"),
+            window_title: default_template("[{title}[ - {artist}] | ]voru"),
+            queue_desc: default_template("[{index} / ]{count} tracks  [{elapsed} / ]{duration}"),
+            tracks_desc: default_template("{count} tracks  {duration}"),
         }
     }
 }
@@ -113,7 +181,9 @@ pub struct ConfigLayout {
     pub max_height: u16,
     pub padding_x: u16,
     pub padding_y: u16,
-    pub player_max_width: u16
+    pub player_max_width: u16,
+    /// Columns of the track lists from left to right
+    pub track_columns: Vec<ConfigTrackColumn>
 }
 impl Default for ConfigLayout {
     fn default() -> Self {
@@ -122,13 +192,160 @@ impl Default for ConfigLayout {
             max_height: 22,
             padding_x: 2,
             padding_y: 1,
-            player_max_width: 80
+            player_max_width: 80,
+            track_columns: vec![
+                ConfigTrackColumn::new(TrackColumn::Index).with_align(ColumnAlign::Right),
+                ConfigTrackColumn::new(TrackColumn::Title).with_ratio(3),
+                ConfigTrackColumn::new(TrackColumn::Album).with_ratio(2).with_style(Color::Gray),
+                ConfigTrackColumn::new(TrackColumn::Artist).with_ratio(2),
+                ConfigTrackColumn::new(TrackColumn::Rating).with_width(7).with_align(ColumnAlign::Right),
+                ConfigTrackColumn::new(TrackColumn::Duration).with_width(7).with_align(ColumnAlign::Right),
+            ]
+        }
+    }
+}
+
+/// Config track column
+/// `[[layout.track_columns]]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigTrackColumn {
+    pub column: TrackColumn,
+    /// Width in cells, the columns without it share the rest of the row
+    pub width: Option<u16>,
+    /// Share of the rest of the row if there is no fixed width
+    #[serde(default = "default_ratio")]
+    pub ratio: u16,
+    #[serde(default)]
+    pub align: ColumnAlign,
+    pub style: Option<Style>,
+}
+impl ConfigTrackColumn {
+    pub fn new(column: TrackColumn) -> Self {
+        Self {
+            column,
+            width: None,
+            ratio: default_ratio(),
+            align: ColumnAlign::default(),
+            style: None,
+        }
+    }
+
+    pub fn with_width(mut self, width: u16) -> Self {
+        self.width = Some(width);
+        self
+    }
+    pub fn with_ratio(mut self, ratio: u16) -> Self {
+        self.ratio = ratio;
+        self
+    }
+    pub fn with_align(mut self, align: ColumnAlign) -> Self {
+        self.align = align;
+        self
+    }
+    pub fn with_style<S: Into<Style>>(mut self, style: S) -> Self {
+        self.style = Some(style.into());
+        self
+    }
+}
+
+/// Config scrobbling
+/// Listens are submitted to a ListenBrainz compatible server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigScrobble {
+    pub enabled: bool,
+    /// Root of the server API
+    pub url: String,
+    /// User token from the server settings
+    pub token: String,
+}
+impl Default for ConfigScrobble {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: "https://api.listenbrainz.org".to_string(),
+            token: String::new(),
+        }
+    }
+}
+
+/// Config cover art
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigCover {
+    /// Show cover art of the current track in the player view
+    pub enabled: bool,
+    pub protocol: CoverProtocol,
+    /// Max width of the cover in cells
+    pub max_width: u16,
+}
+impl Default for ConfigCover {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            protocol: CoverProtocol::default(),
+            max_width: 30,
         }
     }
 }
 
+/// Config visualizer
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigVisualizer {
+    /// Show the visualizer under the player in the player view
+    pub enabled: bool,
+    pub style: VisualizerStyle,
+    /// Height in cells
+    pub height: u16,
+    /// Redraws per second while the visualizer is shown
+    pub fps: u16,
+}
+impl Default for ConfigVisualizer {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            style: VisualizerStyle::default(),
+            height: 8,
+            fps: 30,
+        }
+    }
+}
+
+/// Config smart playlist
+/// Tracks of the playlist are selected from all the known tracks by the query
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigSmartPlaylist {
+    pub name: String,
+    /// Track query: `genre:ambient year:>2014`
+    #[serde(default)]
+    pub query: String,
+    #[serde(default)]
+    pub sort: SmartSort,
+    #[serde(default)]
+    pub reverse: bool,
+    pub limit: Option<usize>,
+}
+
 type Keymap = Vec<Key>;
 
+/// Config key bindings of the specific views
+/// They take precedence over the global ones
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigViewKeys {
+    pub player: KeyBindings,
+    pub playlists: KeyBindings,
+    pub tracks: KeyBindings,
+    pub library: KeyBindings,
+    pub queue: KeyBindings,
+    pub history: KeyBindings,
+    pub stats: KeyBindings,
+    pub lyrics: KeyBindings,
+}
+
 /// Config keys
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(default, deny_unknown_fields)]
@@ -137,8 +354,11 @@ pub struct ConfigKeys {
     pub escape: Option<Keymap>,
     pub enter: Option<Keymap>,
     pub complete: Option<Keymap>,
+    pub complete_prev: Option<Keymap>,
     pub next_history: Option<Keymap>,
     pub prev_history: Option<Keymap>,
+    /// Reverse incremental search through the command line history
+    pub search_history: Option<Keymap>,
 
     pub enter_cmd: Option<Keymap>,
 
@@ -152,11 +372,30 @@ pub struct ConfigKeys {
     pub select_first_item: Option<Keymap>,
     pub select_last_item: Option<Keymap>,
 
+    /// Search items in the focused list
+    pub search: Option<Keymap>,
+    /// Show only items matching a query in the focused list
+    pub filter: Option<Keymap>,
+    /// Select next item matching the search query
+    pub search_next: Option<Keymap>,
+    /// Select previous item matching the search query
+    pub search_prev: Option<Keymap>,
+
     pub next_view: Option<Keymap>,
     pub prev_view: Option<Keymap>,
     pub player_view: Option<Keymap>,
     pub playlists_view: Option<Keymap>,
+    pub library_view: Option<Keymap>,
     pub queue_view: Option<Keymap>,
+    pub stats_view: Option<Keymap>,
+    pub history_view: Option<Keymap>,
+    pub lyrics_view: Option<Keymap>,
+    /// Focus next column in a view with multiple lists
+    pub next_column: Option<Keymap>,
+    /// Focus previous column in a view with multiple lists
+    pub prev_column: Option<Keymap>,
+    /// Switch between grouping by artists and genres in the library
+    pub cycle_grouping: Option<Keymap>,
 
     /// Focus on currently playing track in the queue
     pub queue_focus: Option<Keymap>,
@@ -170,6 +409,17 @@ pub struct ConfigKeys {
     pub queue_add: Option<Keymap>,
     pub queue_shuffle: Option<Keymap>,
 
+    /// Add a star to a selected track
+    pub rating_up: Option<Keymap>,
+    /// Remove a star from a selected track
+    pub rating_down: Option<Keymap>,
+    /// Love or unlove a selected track
+    pub love_toggle: Option<Keymap>,
+    /// Mark a selected track for editing multiple tracks at once
+    pub mark: Option<Keymap>,
+    /// Edit tags of the marked tracks or a selected one
+    pub edit_tags: Option<Keymap>,
+
     pub play: Option<Keymap>,
     pub play_shuffled: Option<Keymap>,
     pub play_next: Option<Keymap>,
@@ -188,7 +438,13 @@ pub struct ConfigKeys {
     pub mute: Option<Keymap>,
     pub unmute: Option<Keymap>,
     pub mute_toggle: Option<Keymap>,
-    pub cycle_loopstate: Option<Keymap>
+    pub cycle_loopstate: Option<Keymap>,
+
+    /// Key sequences bound to commands: `"<space> q s" = "queue-shuffle"`
+    /// They take precedence over the keymaps above
+    pub custom: KeyBindings,
+    /// Key sequences bound to commands in the specific views
+    pub view: ConfigViewKeys
 }
 impl Default for ConfigKeys {
     fn default() -> Self {
@@ -196,9 +452,11 @@ impl Default for ConfigKeys {
             quit: vec![ key!('Q') ].into(),
             escape: vec![ key!(Esc), key!(Ctrl + 'c'), key!(Ctrl + 'o') ].into(),
             enter: vec![ key!(Enter), key!(Ctrl + 'j') ].into(),
-            complete: vec![ key!(Tab), key!(Ctrl + 'n'), key!(Ctrl + 'p') ].into(),
+            complete: vec![ key!(Tab), key!(Ctrl + 'n') ].into(),
+            complete_prev: vec![ key!(BackTab), key!(Ctrl + 'p') ].into(),
             next_history: vec![ key!(Down) ].into(),
             prev_history: vec![ key!(Up) ].into(),
+            search_history: vec![ key!(Ctrl + 'r') ].into(),
 
             enter_cmd: vec![ key!(':'), key!(';') ].into(),
 
@@ -212,11 +470,23 @@ impl Default for ConfigKeys {
             select_first_item: vec![ key!('g'), key!(Home) ].into(),
             select_last_item: vec![ key!('G'), key!(End) ].into(),
 
+            search: vec![ key!('/') ].into(),
+            filter: vec![ key!('\\') ].into(),
+            search_next: vec![ key!('n') ].into(),
+            search_prev: vec![ key!('N') ].into(),
+
             next_view: vec![ key!(Tab) ].into(),
             prev_view: vec![ key!(BackTab) ].into(),
             player_view: vec![ key!('1') ].into(),
             playlists_view: vec![ key!('2') ].into(),
             queue_view: vec![ key!('3') ].into(),
+            library_view: vec![ key!('4') ].into(),
+            stats_view: vec![ key!('5') ].into(),
+            history_view: vec![ key!('6') ].into(),
+            lyrics_view: vec![ key!('7') ].into(),
+            next_column: vec![ key!(']') ].into(),
+            prev_column: vec![ key!('[') ].into(),
+            cycle_grouping: vec![ key!('b') ].into(),
 
             queue_focus: vec![ key!('f') ].into(),
             queue_move_up: vec![ key!(Shift + Up), key!('K') ].into(),
@@ -225,6 +495,12 @@ impl Default for ConfigKeys {
             queue_add: vec![ key!('a') ].into(),
             queue_shuffle: vec![ key!('S') ].into(),
 
+            rating_up: vec![ key!('>') ].into(),
+            rating_down: vec![ key!('<') ].into(),
+            love_toggle: vec![ key!('F') ].into(),
+            mark: vec![ key!('v') ].into(),
+            edit_tags: vec![ key!('e') ].into(),
+
             play: vec![ key!(Enter) ].into(),
             play_shuffled: vec![ key!('P') ].into(),
             play_next: vec![ key!(Shift + Right), key!('L') ].into(),
@@ -244,6 +520,9 @@ impl Default for ConfigKeys {
             unmute: None,
             mute_toggle: vec![ key!('m') ].into(),
             cycle_loopstate: vec![ key!('o') ].into(),
+
+            custom: KeyBindings::new(),
+            view: ConfigViewKeys::default(),
         }
     }
 }
@@ -253,6 +532,29 @@ impl Default for ConfigKeys {
 #[serde(default, deny_unknown_fields)]
 pub struct Config {
     pub playlists: Vec<PathBuf>,
+    /// Playlists which tracks are selected by rules
+    pub smart_playlists: Vec<ConfigSmartPlaylist>,
+    /// Directories to recursively scan for audio files
+    pub music_dirs: Vec<PathBuf>,
+    /// Reload playlists and tracks when they are changed on disk
+    pub watch: bool,
+    /// Reload the config when it is changed on disk
+    pub watch_config: bool,
+    /// Script with commands to execute on startup
+    /// By default `voru.rc` next to the config file
+    pub startup_script: Option<PathBuf>,
+    /// Directory with `.lrc` files named `Artist - Title.lrc` or like the tracks
+    pub lyrics_dir: Option<PathBuf>,
+    /// How long to wait for the next key of a sequence in milliseconds
+    pub key_timeout: u64,
+    /// Execute commands received over the IPC socket (`voru --send <CMD>`)
+    pub ipc: bool,
+    /// Select, play and seek with the mouse
+    pub mouse: bool,
+    /// Max number of the command line history items saved between sessions
+    pub history_size: usize,
+    /// Record play counts, skips, listening time and history
+    pub stats: bool,
     pub seek_jump: u64,
     pub volume_jump: f32,
     pub fast_jump: usize,
@@ -262,7 +564,13 @@ pub struct Config {
     pub style: ConfigStyle,
     pub format: ConfigFormat,
     pub layout: ConfigLayout,
-    pub keys: ConfigKeys
+    pub keys: ConfigKeys,
+    pub scrobble: ConfigScrobble,
+    pub cover: ConfigCover,
+    pub visualizer: ConfigVisualizer,
+    /// User-defined commands: `name = "command; another-command"`
+    /// Arguments are passed to the last command
+    pub commands: BTreeMap<String, String>
 }
 impl Config {
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
@@ -270,14 +578,28 @@ impl Config {
         let content = fs::read_to_string(path)
             .map_err(ConfigError::Io)?;
         // Trying to parse a config from the file
-        toml::from_str(&content)
-            .map_err(ConfigError::Parse)
+        let config: Self = toml::from_str(&content)
+            .map_err(ConfigError::Parse)?;
+
+        config.format.validate()?;
+        Ok(config)
     }
 }
 impl Default for Config {
     fn default() -> Self {
         Self {
             playlists: vec![],
+            smart_playlists: vec![],
+            music_dirs: vec![],
+            watch: true,
+            watch_config: false,
+            startup_script: None,
+            lyrics_dir: None,
+            key_timeout: 1000,
+            ipc: true,
+            mouse: true,
+            history_size: 1000,
+            stats: true,
             seek_jump: 10,
             volume_jump: 0.1,
             fast_jump: 10,
@@ -287,14 +609,39 @@ impl Default for Config {
             style: ConfigStyle::default(),
             format: ConfigFormat::default(),
             layout: ConfigLayout::default(),
-            keys: ConfigKeys::default()
+            keys: ConfigKeys::default(),
+            scrobble: ConfigScrobble::default(),
+            cover: ConfigCover::default(),
+            visualizer: ConfigVisualizer::default(),
+            commands: BTreeMap::new()
         }
     }
 }
 
 // Utils
+fn default_ratio() -> u16 {
+    1
+}
+fn default_template(source: &str) -> Template {
+    Template::parse(source).expect("default templates should be valid")
+}
 pub fn default_config_path() -> Result<PathBuf, ConfigError> {
     let home = var("HOME")
         .map_err(|_| ConfigError::NoHomeVar)?;
     Ok(PathBuf::from(home).join(".config/voru/config.toml"))
 }
+pub fn cache_dir() -> Result<PathBuf, ConfigError> {
+    let home = var("HOME")
+        .map_err(|_| ConfigError::NoHomeVar)?;
+    Ok(PathBuf::from(home).join(".cache/voru"))
+}
+pub fn state_dir() -> Result<PathBuf, ConfigError> {
+    let home = var("HOME")
+        .map_err(|_| ConfigError::NoHomeVar)?;
+    Ok(PathBuf::from(home).join(".local/state/voru"))
+}
+pub fn data_dir() -> Result<PathBuf, ConfigError> {
+    let home = var("HOME")
+        .map_err(|_| ConfigError::NoHomeVar)?;
+    Ok(PathBuf::from(home).join(".local/share/voru"))
+}
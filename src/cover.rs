@@ -0,0 +1,350 @@
+use std::{
+    collections::BTreeMap,
+    env::var,
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use image::{imageops::FilterType, Rgba, RgbaImage};
+use lofty::{file::TaggedFileExt, picture::PictureType};
+use serde::{Deserialize, Serialize};
+use tuich::{buffer::Buffer, layout::Rect, style::{Color, Style}};
+
+use crate::{config::cache_dir, traits::StableHash, UpdateKind};
+
+// Consts
+/// Names of the image files next to the tracks, without extensions
+const COVER_NAMES: &[&str] = &["cover", "folder", "front", "album"];
+const COVER_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+/// Size of a terminal cell in pixels if the terminal doesn't report it
+const DEFAULT_CELL_SIZE: (u32, u32) = (8, 16);
+/// Max size of a chunk of the kitty graphics protocol payload
+const KITTY_CHUNK: usize = 4096;
+
+/// Protocol used to draw images in the terminal
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoverProtocol {
+    /// Detect the protocol from the environment variables
+    #[default]
+    Auto,
+    Kitty,
+    Sixel,
+    /// Colored unicode half blocks, works in any terminal with true colors
+    HalfBlocks,
+}
+impl CoverProtocol {
+    /// Returns the protocol supported by the terminal if it's `Auto`
+    pub fn detect(self) -> Self {
+        if self != Self::Auto {
+            return self;
+        }
+
+        let term = var("TERM").unwrap_or_default();
+        let program = var("TERM_PROGRAM").unwrap_or_default();
+
+        if var("KITTY_WINDOW_ID").is_ok() || term.contains("kitty") || matches!(program.as_str(), "WezTerm" | "ghostty") {
+            Self::Kitty
+        } else if ["foot", "mlterm", "contour"].iter().any(|t| term.contains(t)) || program == "iTerm.app" {
+            Self::Sixel
+        } else {
+            Self::HalfBlocks
+        }
+    }
+}
+
+/// Cover art of a track
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cover {
+    image: RgbaImage,
+    /// Image file, embedded pictures are extracted to the cache dir
+    pub path: Option<PathBuf>,
+    /// The image resized for the last drawn size
+    resized: Option<((u32, u32), RgbaImage)>,
+}
+impl Cover {
+    /// Load the picture embedded into the track, or an image file next to it (`cover.jpg`, `folder.png`)
+    /// Returns `None` if there is no cover or it couldn't be decoded
+    pub fn load(track_path: &Path) -> Option<Self> {
+        let embedded = read_embedded(track_path).and_then(|data| {
+            let image = image::load_from_memory(&data).ok()?.to_rgba8();
+            Some(Self { image, path: save_embedded(&data), resized: None })
+        });
+
+        embedded.or_else(|| {
+            let path = find_cover_file(track_path)?;
+            let image = image::open(&path).ok()?.to_rgba8();
+            Some(Self { image, path: Some(path), resized: None })
+        })
+    }
+
+    /// Returns the image resized to fit into the size, keeping its aspect ratio
+    fn resized(&mut self, max_width: u32, max_height: u32) -> &RgbaImage {
+        let (width, height) = self.image.dimensions();
+        let scale = (max_width as f32 / width as f32).min(max_height as f32 / height as f32);
+        let size = (
+            ((width as f32 * scale) as u32).max(1),
+            ((height as f32 * scale) as u32).max(1),
+        );
+
+        if self.resized.as_ref().map(|(s, _)| *s) != Some(size) {
+            let image = image::imageops::resize(&self.image, size.0, size.1, FilterType::Triangle);
+            self.resized = Some((size, image));
+        }
+
+        self.resized
+            .as_ref()
+            .map(|(_, image)| image)
+            .unwrap_or(&self.image)
+    }
+
+    /// Draw the cover with the upper half blocks, two pixels per cell
+    pub fn draw_half_blocks(&mut self, buf: &mut Buffer, rect: Rect) -> Rect {
+        let image = self.resized(rect.width as u32, rect.height as u32 * 2);
+        let (width, height) = image.dimensions();
+        let rows = height.div_ceil(2);
+
+        let image_rect = rect
+            .min_size((width as u16, rows as u16))
+            .align_center(rect);
+
+        for row in 0..rows {
+            for col in 0..width {
+                let top = image.get_pixel(col, row * 2);
+                let mut style = Style::cleared().fg(rgb(top));
+                // The last row of an image with odd height has no bottom pixels
+                if row * 2 + 1 < height {
+                    style = style.bg(rgb(image.get_pixel(col, row * 2 + 1)));
+                }
+
+                buf.set(image_rect.pos().add((col as u16, row as u16)), Some("▀"), style);
+            }
+        }
+
+        image_rect
+    }
+
+    /// Write the cover at the rect with the kitty graphics protocol
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the output couldn't be written
+    pub fn write_kitty<W: Write>(&mut self, out: &mut W, rect: Rect) -> io::Result<()> {
+        let (cell_width, cell_height) = cell_size();
+        let image_rect = self.fit_pixels(rect, cell_width, cell_height);
+        let image = self.resized(rect.width as u32 * cell_width, rect.height as u32 * cell_height);
+        let (width, height) = image.dimensions();
+
+        let data = BASE64.encode(image.as_raw());
+        let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+
+        crossterm::queue!(out, crossterm::cursor::MoveTo(image_rect.x, image_rect.y))?;
+        for (index, chunk) in chunks.iter().enumerate() {
+            let more = u8::from(index + 1 < chunks.len());
+
+            // Draw under the text and don't move the cursor
+            if index == 0 {
+                write!(out, "\x1b_Ga=T,f=32,s={},v={},z=-1,C=1,q=2,m={};", width, height, more)?;
+            } else {
+                write!(out, "\x1b_Gm={};", more)?;
+            }
+            out.write_all(chunk)?;
+            write!(out, "\x1b\\")?;
+        }
+
+        Ok(())
+    }
+    /// Write the cover at the rect as sixels
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the output couldn't be written
+    pub fn write_sixel<W: Write>(&mut self, out: &mut W, rect: Rect) -> io::Result<()> {
+        let (cell_width, cell_height) = cell_size();
+        let image_rect = self.fit_pixels(rect, cell_width, cell_height);
+        let image = self.resized(rect.width as u32 * cell_width, rect.height as u32 * cell_height);
+
+        crossterm::queue!(out, crossterm::cursor::MoveTo(image_rect.x, image_rect.y))?;
+        out.write_all(encode_sixel(image).as_bytes())
+    }
+    /// Returns cells covered by the image resized to fit into the rect
+    fn fit_pixels(&mut self, rect: Rect, cell_width: u32, cell_height: u32) -> Rect {
+        let image = self.resized(rect.width as u32 * cell_width, rect.height as u32 * cell_height);
+        let (width, height) = image.dimensions();
+
+        rect
+            .min_size((width.div_ceil(cell_width) as u16, height.div_ceil(cell_height) as u16))
+            .align_center(rect)
+    }
+}
+
+/// Cover loader
+/// Reads and decodes covers in the background, so switching tracks doesn't block the UI
+#[derive(Debug)]
+pub struct CoverLoader {
+    sender: mpsc::Sender<PathBuf>,
+}
+impl CoverLoader {
+    pub fn new(notifier: mpsc::Sender<UpdateKind>) -> Self {
+        let (sender, receiver) = mpsc::channel::<PathBuf>();
+
+        thread::spawn(move || {
+            while let Ok(mut path) = receiver.recv() {
+                // Only the latest track matters if they were skipped quickly
+                while let Ok(next) = receiver.try_recv() {
+                    path = next;
+                }
+
+                let cover = Cover::load(&path);
+                if notifier.send(UpdateKind::Cover(path, cover)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self { sender }
+    }
+
+    /// Load cover of the track in the background
+    /// It's sent to the main loop as [UpdateKind::Cover]
+    pub fn load(&self, track_path: PathBuf) {
+        let _ = self.sender.send(track_path);
+    }
+}
+
+/// Remove all the images drawn with the kitty graphics protocol
+///
+/// # Errors
+///
+/// Returns an error if the output couldn't be written
+pub fn clear_kitty<W: Write>(out: &mut W) -> io::Result<()> {
+    write!(out, "\x1b_Ga=d,d=A,q=2\x1b\\")
+}
+
+/// Returns data of the front cover embedded into the track, or of any other picture
+fn read_embedded(track_path: &Path) -> Option<Vec<u8>> {
+    let tagged = lofty::read_from_path(track_path).ok()?;
+    let tag = tagged.primary_tag().or(tagged.first_tag())?;
+    let pictures = tag.pictures();
+
+    pictures
+        .iter()
+        .find(|p| p.pic_type() == PictureType::CoverFront)
+        .or(pictures.first())
+        .map(|p| p.data().to_vec())
+}
+/// Save an embedded picture to the cache dir, so it can be shown by other apps
+/// Returns `None` if the picture couldn't be saved
+fn save_embedded(data: &[u8]) -> Option<PathBuf> {
+    let extension = image::guess_format(data).ok()?
+        .extensions_str()
+        .first()?;
+
+    // Tracks of the same album usually have the same picture
+    let dir = cache_dir().ok()?.join("covers");
+    let path = dir.join(format!("{:016x}.{}", data.stable_hash(), extension));
+    if !path.exists() {
+        fs::create_dir_all(&dir).ok()?;
+        fs::write(&path, data).ok()?;
+    }
+
+    Some(path)
+}
+/// Returns path of an image file next to the track: `cover.jpg`, `folder.png`, etc
+fn find_cover_file(track_path: &Path) -> Option<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(track_path.parent()?).ok()?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            let stem = p.file_stem().and_then(|s| s.to_str()).map(str::to_lowercase);
+            let ext = p.extension().and_then(|s| s.to_str()).map(str::to_lowercase);
+
+            stem.is_some_and(|s| COVER_NAMES.contains(&s.as_str())) &&
+            ext.is_some_and(|e| COVER_EXTENSIONS.contains(&e.as_str()))
+        })
+        .collect();
+
+    // Prefer the names in the order they are listed
+    files.sort_by_key(|p| {
+        let stem = p.file_stem().and_then(|s| s.to_str()).map(str::to_lowercase);
+        COVER_NAMES.iter().position(|n| stem.as_deref() == Some(*n))
+    });
+    files.into_iter().next()
+}
+
+/// Returns size of a terminal cell in pixels
+fn cell_size() -> (u32, u32) {
+    match crossterm::terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => (
+            (size.width / size.columns) as u32,
+            (size.height / size.rows) as u32,
+        ),
+        _ => DEFAULT_CELL_SIZE
+    }
+}
+
+fn rgb(pixel: &Rgba<u8>) -> Color {
+    Color::Rgb(pixel[0], pixel[1], pixel[2])
+}
+
+/// Encode the image as sixels with colors from the 6x6x6 color cube
+fn encode_sixel(image: &RgbaImage) -> String {
+    let (width, height) = image.dimensions();
+    let color_index = |pixel: &Rgba<u8>| {
+        let level = |c: u8| (c as usize * 5 + 127) / 255;
+        level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2])
+    };
+
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", width, height);
+
+    // Palette, colors are in percents
+    for index in 0..216 {
+        let _ = write!(out, "#{};2;{};{};{}", index, index / 36 * 20, index / 6 % 6 * 20, index % 6 * 20);
+    }
+
+    // Every band is six pixels high
+    for band in (0..height).step_by(6) {
+        let mut colors: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+        for y in band..(band + 6).min(height) {
+            for x in 0..width {
+                let sixels = colors
+                    .entry(color_index(image.get_pixel(x, y)))
+                    .or_insert_with(|| vec![0; width as usize]);
+                sixels[x as usize] |= 1 << (y - band);
+            }
+        }
+
+        for (color, sixels) in colors {
+            let _ = write!(out, "#{}", color);
+
+            // Run-length encode repeated sixels
+            let mut iter = sixels.iter().peekable();
+            while let Some(&bits) = iter.next() {
+                let mut count = 1;
+                while iter.next_if_eq(&&bits).is_some() {
+                    count += 1;
+                }
+
+                let char = (63 + bits) as char;
+                if count > 3 {
+                    let _ = write!(out, "!{}{}", count, char);
+                } else {
+                    for _ in 0..count {
+                        out.push(char);
+                    }
+                }
+            }
+
+            // Back to the start of the band
+            out.push('$');
+        }
+        out.push('-');
+    }
+
+    out.push_str("\x1b\\");
+    out
+}
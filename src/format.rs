@@ -0,0 +1,296 @@
+use std::{fmt::Display, str::Chars};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{app::AppContext, rating::format_rating, traits::ToReadable};
+
+// Consts
+/// Names of the variables known to [player_var]
+pub const PLAYER_VARS: [&str; 18] = [
+    "state", "title", "artist", "album", "album_artist", "genre", "year", "track_number",
+    "file", "rating", "loved", "pos", "dur", "remaining", "volume", "loop", "index", "count",
+];
+
+// Errors
+#[derive(Debug, Error)]
+pub enum TemplateError {
+    #[error("\"{0}\" is never closed")]
+    Unclosed(char),
+    #[error("Unexpected \"{0}\"")]
+    Unexpected(char),
+}
+
+/// Value of a variable slot
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Source {
+    Var(String),
+    /// Quoted text: `"unknown"`
+    Literal(String),
+}
+
+/// Part of a template
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    /// The first source with a value: `{artist|album|"unknown"}`
+    Var(Vec<Source>),
+    /// Shown only if all the variables inside have values: `[ - {artist}]`
+    Section(Vec<Part>),
+}
+
+/// Format template
+/// - `{name}` is replaced with the value of the variable
+/// - `{artist|album|"unknown"}` uses the first variable with a value, or the quoted text
+/// - `[ - {artist}]` is shown only if all the variables inside have values
+/// - `\{`, `\[` and others are shown as they are
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Template {
+    source: String,
+    parts: Vec<Part>,
+}
+impl Template {
+    /// # Errors
+    ///
+    /// Returns an error if some brace or bracket isn't closed or is closed without opening
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let mut chars = source.chars();
+        let parts = parse_parts(&mut chars, false)?;
+        Ok(Self { source: source.to_string(), parts })
+    }
+
+    /// Render the template, `var` returns values of the variables
+    /// Variables without values and empty ones are left empty
+    pub fn render<F: Fn(&str) -> Option<String>>(&self, var: F) -> String {
+        let mut out = String::new();
+        render_parts(&self.parts, &var, &mut out);
+        out
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+    /// Returns the first variable which isn't one of the `known` ones
+    pub fn find_unknown_var(&self, known: &[&str]) -> Option<&str> {
+        find_unknown_var(&self.parts, known)
+    }
+}
+impl TryFrom<String> for Template {
+    type Error = TemplateError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+impl From<Template> for String {
+    fn from(value: Template) -> Self {
+        value.source
+    }
+}
+impl Display for Template {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+fn parse_parts(chars: &mut Chars, in_section: bool) -> Result<Vec<Part>, TemplateError> {
+    let mut parts = vec![];
+    let mut text = String::new();
+
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    text.push(escaped);
+                }
+                continue;
+            }
+            '{' | '[' => (),
+            ']' if in_section => {
+                if !text.is_empty() { parts.push(Part::Text(text)); }
+                return Ok(parts);
+            }
+            ']' | '}' => return Err(TemplateError::Unexpected(char)),
+            _ => {
+                text.push(char);
+                continue;
+            }
+        }
+
+        if !text.is_empty() {
+            parts.push(Part::Text(std::mem::take(&mut text)));
+        }
+
+        if char == '[' {
+            parts.push(Part::Section(parse_parts(chars, true)?));
+            continue;
+        }
+
+        let mut slot = String::new();
+        loop {
+            match chars.next() {
+                Some('}') => break,
+                Some(c) => slot.push(c),
+                None => return Err(TemplateError::Unclosed('{'))
+            }
+        }
+        let sources = slot
+            .split('|')
+            .map(str::trim)
+            .map(|s| match s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+                Some(literal) => Source::Literal(literal.to_string()),
+                None => Source::Var(s.to_string())
+            })
+            .collect();
+        parts.push(Part::Var(sources));
+    }
+
+    if in_section {
+        return Err(TemplateError::Unclosed('['));
+    }
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+    Ok(parts)
+}
+fn find_unknown_var<'a>(parts: &'a [Part], known: &[&str]) -> Option<&'a str> {
+    parts.iter().find_map(|part| match part {
+        Part::Text(_) => None,
+        Part::Var(sources) => sources.iter().find_map(|source| match source {
+            Source::Var(name) if !known.contains(&name.as_str()) => Some(name.as_str()),
+            _ => None
+        }),
+        Part::Section(parts) => find_unknown_var(parts, known)
+    })
+}
+/// Returns whether all the variables had values
+fn render_parts<F: Fn(&str) -> Option<String>>(parts: &[Part], var: &F, out: &mut String) -> bool {
+    let mut complete = true;
+
+    for part in parts {
+        match part {
+            Part::Text(text) => out.push_str(text),
+            Part::Var(sources) => {
+                let value = sources
+                    .iter()
+                    .find_map(|source| match source {
+                        Source::Var(name) => var(name),
+                        Source::Literal(text) => Some(text.clone()),
+                    }.filter(|v| !v.is_empty()));
+
+                match value {
+                    Some(value) => out.push_str(&value),
+                    None => complete = false
+                }
+            }
+            Part::Section(parts) => {
+                let mut section = String::new();
+                if render_parts(parts, var, &mut section) {
+                    out.push_str(&section);
+                }
+            }
+        }
+    }
+
+    complete
+}
+
+/// Returns value of a player variable, see [PLAYER_VARS]
+pub fn player_var(ctx: &AppContext, name: &str) -> Option<String> {
+    let player = &ctx.player;
+    let track = player.cur_track.as_ref();
+
+    match name {
+        "state" => Some(player.playstate().to_string()),
+        "volume" =>
+            if player.muted() { Some("muted".to_string()) }
+            else { Some(format!("{}%", (player.volume() * 100.0).round())) },
+        "loop" => Some(player.loopstate().to_string()),
+        "index" => player.cur_track_index.map(|i| (i + 1).to_string()),
+        "count" => Some(player.queue.len().to_string()),
+
+        "pos" => track.map(|_| player.pos().to_readable()),
+        "dur" => track.map(|_| player.duration().to_readable()),
+        "remaining" => track.map(|_| player.duration().saturating_sub(player.pos()).to_readable()),
+
+        "title" => track.map(|t| t.title().to_string()),
+        "artist" => track?.try_artist().map(str::to_string),
+        "album" => track?.try_album().map(str::to_string),
+        "album_artist" => track?.try_album_artist().map(str::to_string),
+        "genre" => track?.try_genre().map(str::to_string),
+        "year" => track?.try_year().map(|y| y.to_string()),
+        "track_number" => track?.try_track_number().map(|n| n.to_string()),
+        "file" => track?.path.file_name().map(|f| f.to_string_lossy().into_owned()),
+        "rating" => track?.try_rating(&ctx.stats).map(format_rating),
+        "loved" => track.filter(|t| t.is_loved(&ctx.stats)).map(|_| "♥".to_string()),
+
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(source: &str) -> String {
+        Template::parse(source).unwrap().render(|name| match name {
+            "title" => Some("Rolling Over".to_string()),
+            "artist" => Some("Low Roar".to_string()),
+            "album" => Some(String::new()),
+            _ => None
+        })
+    }
+
+    #[test]
+    fn parse() {
+        let template = Template::parse(r#"{title} [- {artist|"unknown"}]\{"#).unwrap();
+
+        assert_eq!(template.parts, vec![
+            Part::Var(vec![Source::Var("title".to_string())]),
+            Part::Text(" ".to_string()),
+            Part::Section(vec![
+                Part::Text("- ".to_string()),
+                Part::Var(vec![Source::Var("artist".to_string()), Source::Literal("unknown".to_string())]),
+            ]),
+            Part::Text("{".to_string()),
+        ]);
+        assert_eq!(template.to_string(), r#"{title} [- {artist|"unknown"}]\{"#);
+        assert!(Template::parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(Template::parse("{title"), Err(TemplateError::Unclosed('{'))));
+        assert!(matches!(Template::parse("[{title}"), Err(TemplateError::Unclosed('['))));
+        assert!(matches!(Template::parse("title}"), Err(TemplateError::Unexpected('}'))));
+        assert!(matches!(Template::parse("title]"), Err(TemplateError::Unexpected(']'))));
+    }
+
+    #[test]
+    fn render_vars() {
+        assert_eq!(render("{title} - {artist}"), "Rolling Over - Low Roar");
+        assert_eq!(render("{year}"), "");
+        // Empty values are skipped like missing ones
+        assert_eq!(render(r#"{album|year|"unknown"}"#), "unknown");
+        assert_eq!(render(r#"{ year | artist }"#), "Low Roar");
+        assert_eq!(render(r"\[{title}\]"), "[Rolling Over]");
+    }
+
+    #[test]
+    fn render_sections() {
+        assert_eq!(render("{title}[ - {artist}]"), "Rolling Over - Low Roar");
+        assert_eq!(render("{title}[ ({year})]"), "Rolling Over");
+        assert_eq!(render("[{title}[ ({year})] | ]voru"), "Rolling Over | voru");
+        assert_eq!(render("[{year} | ]voru"), "voru");
+    }
+
+    #[test]
+    fn unknown_vars() {
+        let known = ["title", "artist"];
+
+        assert_eq!(Template::parse(r#"{title}[ - {artist|"?"}]"#).unwrap().find_unknown_var(&known), None);
+        assert_eq!(Template::parse("{title}[ - {artist|album}]").unwrap().find_unknown_var(&known), Some("album"));
+        assert_eq!(Template::parse("{titel}").unwrap().find_unknown_var(&PLAYER_VARS), Some("titel"));
+    }
+}
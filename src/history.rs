@@ -0,0 +1,86 @@
+use std::{fs, io, path::PathBuf};
+
+use crate::config::state_dir;
+
+/// Command line history
+/// Saved to a file in the state dir, so it is kept between sessions
+#[derive(Debug, Default)]
+pub struct History {
+    items: Vec<String>,
+    path: Option<PathBuf>
+}
+impl History {
+    /// Load the history saved by the previous sessions
+    /// Starts with an empty history if there is nothing to load
+    pub fn load() -> Self {
+        let path = state_dir()
+            .map(|d| d.join("history"))
+            .ok();
+        let items = path
+            .as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .map(|c| c.lines().map(String::from).collect())
+            .unwrap_or_default();
+
+        Self { items, path }
+    }
+
+    /// Add an item to the end of the history, remove its old duplicate
+    /// and the oldest items to fit in `max_size`, then save the history
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the history couldn't be saved
+    pub fn push(&mut self, item: String, max_size: usize) -> io::Result<()> {
+        if let Some(dup_index) = self.items.iter().position(|i| i.eq(&item)) {
+            self.items.remove(dup_index);
+        }
+        self.items.push(item);
+
+        let len = self.items.len();
+        if len > max_size {
+            self.items.drain(..len - max_size);
+        }
+
+        self.save()
+    }
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.items.join("\n"))
+    }
+
+    /// Returns index of the latest item before `before` (or the end) that starts with the prefix
+    pub fn find_prev_prefixed(&self, before: Option<usize>, prefix: &str) -> Option<usize> {
+        self.items
+            .get(..before.unwrap_or(self.items.len()))?
+            .iter()
+            .rposition(|i| i.starts_with(prefix))
+    }
+    /// Returns index of the earliest item after `after` that starts with the prefix
+    pub fn find_next_prefixed(&self, after: usize, prefix: &str) -> Option<usize> {
+        self.items
+            .get(after + 1..)?
+            .iter()
+            .position(|i| i.starts_with(prefix))
+            .map(|i| i + after + 1)
+    }
+    /// Returns index of the latest item before `before` (or the end) that contains the query
+    pub fn find_prev_containing(&self, before: Option<usize>, query: &str) -> Option<usize> {
+        self.items
+            .get(..before.unwrap_or(self.items.len()))?
+            .iter()
+            .rposition(|i| i.contains(query))
+    }
+
+    // Get
+
+    pub fn get(&self, index: usize) -> Option<&String> {
+        self.items.get(index)
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    env::var,
+    fs::{self, DirBuilder, Permissions},
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::{DirBuilderExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::PathBuf,
+    sync::mpsc,
+    thread,
+};
+
+use crate::{config::state_dir, UpdateKind};
+
+/// Returns path to the IPC socket
+/// `$XDG_RUNTIME_DIR/voru.sock`, or `~/.local/state/voru/run/voru.sock` if the runtime dir is not set,
+/// the shared `/tmp` is never used, so other users can't take the socket over
+///
+/// # Errors
+///
+/// Returns an error if neither `XDG_RUNTIME_DIR` nor `HOME` is set
+pub fn socket_path() -> io::Result<PathBuf> {
+    if let Ok(dir) = var("XDG_RUNTIME_DIR") {
+        return Ok(PathBuf::from(dir).join("voru.sock"));
+    }
+
+    let dir = state_dir()
+        .map_err(|e| io::Error::new(io::ErrorKind::NotFound, e.to_string()))?;
+    Ok(dir.join("run/voru.sock"))
+}
+
+/// Listen to the IPC socket in the background
+/// Every line received from a client is sent to the main loop as [UpdateKind::Ipc]
+/// and executed as a command
+///
+/// # Errors
+///
+/// Returns [io::ErrorKind::AddrInUse] if another instance is already listening,
+/// or an error if the socket couldn't be created
+pub fn listen(sender: mpsc::Sender<UpdateKind>) -> io::Result<()> {
+    let path = socket_path()?;
+
+    // Only the user can reach the socket in the fallback dir
+    if var("XDG_RUNTIME_DIR").is_err() {
+        if let Some(dir) = path.parent() {
+            DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)?;
+            fs::set_permissions(dir, Permissions::from_mode(0o700))?;
+        }
+    }
+
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "another VORU is running"));
+        }
+        // Left after a crash
+        fs::remove_file(&path)?;
+    }
+
+    let listener = UnixListener::bind(&path)?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let sender = sender.clone();
+
+            thread::spawn(move || {
+                for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                    let line = line.trim();
+                    if line.is_empty() { continue; }
+
+                    if sender.send(UpdateKind::Ipc(line.to_string())).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+/// Send a command to the running instance
+///
+/// # Errors
+///
+/// Returns an error if there is no running instance
+pub fn send(command: &str) -> io::Result<()> {
+    let mut stream = UnixStream::connect(socket_path()?)?;
+    writeln!(stream, "{}", command)
+}
+
+/// Remove the socket file on exit
+pub fn cleanup() {
+    if let Ok(path) = socket_path() {
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,260 @@
+use std::{cmp::Ordering, collections::BTreeMap, fmt::Display};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tuich::event::{Key, KeyCode, KeyMod};
+
+// Errors
+#[derive(Debug, Error)]
+pub enum KeySeqError {
+    #[error("Empty key sequence")]
+    Empty,
+    #[error("Unknown key \"{0}\"")]
+    UnknownKey(String)
+}
+
+/// Key sequence
+/// Parsed from a string of keys separated by spaces: `"g g"`, `"<space> q s"`, `"<C-x> <Tab>"`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct KeySeq {
+    pub keys: Vec<Key>,
+    source: String
+}
+impl KeySeq {
+    /// Returns whether the sequence starts with the keys
+    pub fn starts_with(&self, keys: &[Key]) -> bool {
+        self.keys.len() >= keys.len() && self.keys.iter().zip(keys).all(|(a, b)| a == b)
+    }
+    /// Returns whether the sequence consists of exactly the keys
+    pub fn is(&self, keys: &[Key]) -> bool {
+        self.keys.len() == keys.len() && self.starts_with(keys)
+    }
+}
+impl TryFrom<String> for KeySeq {
+    type Error = KeySeqError;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let keys = value
+            .split_whitespace()
+            .map(parse_key)
+            .collect::<Result<Vec<Key>, KeySeqError>>()?;
+
+        if keys.is_empty() {
+            return Err(KeySeqError::Empty);
+        }
+
+        Ok(Self { keys, source: value })
+    }
+}
+impl From<KeySeq> for String {
+    fn from(value: KeySeq) -> Self {
+        value.source
+    }
+}
+impl Display for KeySeq {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+// Sequences are ordered by their source, so they can be used as keys of a map
+impl PartialEq for KeySeq {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+impl Eq for KeySeq {}
+impl PartialOrd for KeySeq {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for KeySeq {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.source.cmp(&other.source)
+    }
+}
+
+/// Key sequences bound to commands
+pub type KeyBindings = BTreeMap<KeySeq, String>;
+
+/// Result of looking up pressed keys in the bindings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySeqMatch {
+    /// No sequence starts with the keys
+    Nope,
+    /// Some longer sequences start with the keys, more keys are expected
+    Pending,
+    /// The keys are bound to the command
+    Command(String)
+}
+
+/// Look up the pressed keys in the bindings
+/// Prefers waiting for more keys if the keys are both a whole sequence and a start of a longer one
+pub fn find_binding<'a, I>(bindings: I, keys: &[Key]) -> KeySeqMatch
+where
+    I: IntoIterator<Item = &'a KeyBindings>
+{
+    let mut command = None;
+
+    for bindings in bindings {
+        for (seq, cmd) in bindings {
+            if seq.is(keys) {
+                command.get_or_insert(cmd);
+            } else if seq.starts_with(keys) {
+                return KeySeqMatch::Pending;
+            }
+        }
+    }
+
+    match command {
+        Some(cmd) => KeySeqMatch::Command(cmd.clone()),
+        None => KeySeqMatch::Nope
+    }
+}
+
+/// Parse a single key: `"a"`, `"<space>"`, `"<C-x>"`, `"<S-Tab>"`
+fn parse_key(value: &str) -> Result<Key, KeySeqError> {
+    let unknown = || KeySeqError::UnknownKey(value.to_string());
+
+    let mut chars = value.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(Key(KeyMod::Any, KeyCode::Char(c)));
+    }
+
+    let name = value
+        .strip_prefix('<')
+        .and_then(|v| v.strip_suffix('>'))
+        .ok_or_else(unknown)?;
+
+    let (modifier, name) = match name.split_once('-') {
+        Some((m, n)) if !n.is_empty() => match m.to_lowercase().as_str() {
+            "c" | "ctrl" => (KeyMod::Ctrl, n),
+            "s" | "shift" => (KeyMod::Shift, n),
+            _ => return Err(unknown())
+        },
+        _ => (KeyMod::Any, name)
+    };
+
+    let code = match name.to_lowercase().as_str() {
+        "space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
+        "gt" => KeyCode::Char('>'),
+        "esc" => KeyCode::Esc,
+        "enter" | "cr" => KeyCode::Enter,
+        "tab" if modifier == KeyMod::Shift => return Ok(Key(KeyMod::Any, KeyCode::BackTab)),
+        "tab" => KeyCode::Tab,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        _ => {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => KeyCode::Char(c),
+                _ => return Err(unknown())
+            }
+        }
+    };
+
+    Ok(Key(modifier, code))
+}
+
+/// Format the keys the same way as they are written in the config
+pub fn format_keys(keys: &[Key]) -> String {
+    keys.iter()
+        .map(format_key)
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+fn format_key(key: &Key) -> String {
+    let Key(modifier, code) = key;
+
+    let name = match code {
+        KeyCode::Char(' ') => "space".to_string(),
+        KeyCode::Char('<') => "lt".to_string(),
+        KeyCode::Char('>') => "gt".to_string(),
+        KeyCode::Char(c) if *modifier != KeyMod::Ctrl => return c.to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Esc => "Esc".to_string(),
+        KeyCode::Enter => "Enter".to_string(),
+        KeyCode::Tab => "Tab".to_string(),
+        KeyCode::BackTab => return "<S-Tab>".to_string(),
+        KeyCode::Up => "Up".to_string(),
+        KeyCode::Down => "Down".to_string(),
+        KeyCode::Left => "Left".to_string(),
+        KeyCode::Right => "Right".to_string(),
+        KeyCode::PageUp => "PageUp".to_string(),
+        KeyCode::PageDown => "PageDown".to_string(),
+        KeyCode::Home => "Home".to_string(),
+        KeyCode::End => "End".to_string(),
+        _ => "?".to_string()
+    };
+
+    match modifier {
+        KeyMod::Ctrl => format!("<C-{}>", name),
+        KeyMod::Shift => format!("<S-{}>", name),
+        _ => format!("<{}>", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seq(source: &str) -> KeySeq {
+        KeySeq::try_from(source.to_string()).unwrap()
+    }
+    fn bindings(seqs: &[(&str, &str)]) -> KeyBindings {
+        seqs.iter()
+            .map(|(s, cmd)| (seq(s), cmd.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parse_keys() {
+        assert_eq!(seq("g g").keys, vec![
+            Key(KeyMod::Any, KeyCode::Char('g')),
+            Key(KeyMod::Any, KeyCode::Char('g')),
+        ]);
+        assert_eq!(seq("<space> <lt> <Enter>").keys, vec![
+            Key(KeyMod::Any, KeyCode::Char(' ')),
+            Key(KeyMod::Any, KeyCode::Char('<')),
+            Key(KeyMod::Any, KeyCode::Enter),
+        ]);
+        assert_eq!(seq("<C-x> <ctrl-Up>").keys, vec![
+            Key(KeyMod::Ctrl, KeyCode::Char('x')),
+            Key(KeyMod::Ctrl, KeyCode::Up),
+        ]);
+        assert_eq!(seq("<S-Tab>").keys, vec![Key(KeyMod::Any, KeyCode::BackTab)]);
+    }
+
+    #[test]
+    fn parse_invalid_keys() {
+        assert!(matches!(KeySeq::try_from("  ".to_string()), Err(KeySeqError::Empty)));
+        assert!(matches!(KeySeq::try_from("<foo>".to_string()), Err(KeySeqError::UnknownKey(_))));
+        assert!(matches!(KeySeq::try_from("<A-x>".to_string()), Err(KeySeqError::UnknownKey(_))));
+        assert!(matches!(KeySeq::try_from("ab".to_string()), Err(KeySeqError::UnknownKey(_))));
+    }
+
+    #[test]
+    fn find_bindings() {
+        let bindings = bindings(&[("g g", "first"), ("g", "go"), ("<space> q", "quit")]);
+        let g = Key(KeyMod::Any, KeyCode::Char('g'));
+        let q = Key(KeyMod::Any, KeyCode::Char('q'));
+
+        // Waits for more keys even though "g" is bound by itself
+        assert_eq!(find_binding([&bindings], &[g]), KeySeqMatch::Pending);
+        assert_eq!(find_binding([&bindings], &[g, g]), KeySeqMatch::Command("first".to_string()));
+        assert_eq!(find_binding([&bindings], &[g, q]), KeySeqMatch::Nope);
+        assert_eq!(find_binding([&bindings], &[q]), KeySeqMatch::Nope);
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!(format_keys(&seq("g <space> <C-x> <S-Tab> <Esc>").keys), "g <space> <C-x> <S-Tab> <Esc>");
+    }
+}
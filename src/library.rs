@@ -0,0 +1,172 @@
+use std::{cmp::Ordering, collections::HashMap, fmt::Display, rc::Rc, time::Duration};
+
+use crate::{cache::Cache, track::Track, traits::Cycle};
+
+/// Library grouping
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Grouping {
+    #[default]
+    Artist,
+    Genre
+}
+impl Cycle for Grouping {
+    fn cycle_next(&self) -> Self {
+        match self {
+            Self::Artist => Self::Genre,
+            Self::Genre => Self::Artist,
+        }
+    }
+    fn cycle_prev(&self) -> Self {
+        self.cycle_next()
+    }
+}
+impl Display for Grouping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Artist => write!(f, "Artists"),
+            Self::Genre => write!(f, "Genres"),
+        }
+    }
+}
+
+/// Library album
+#[derive(Debug)]
+pub struct Album {
+    pub name: Option<String>,
+    pub year: Option<u32>,
+    pub tracks: Vec<Rc<Track>>,
+    pub duration: Duration
+}
+impl Album {
+    /// Returns album name or `"<no album>"`
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or("<no album>")
+    }
+}
+
+/// Library group of albums (artist or genre)
+#[derive(Debug)]
+pub struct Group {
+    pub name: Option<String>,
+    pub albums: Vec<Album>,
+    pub duration: Duration
+}
+impl Group {
+    /// Returns group name or `"<unknown>"`
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or("<unknown>")
+    }
+    /// Returns tracks of all the albums in the group
+    pub fn tracks(&self) -> Vec<Rc<Track>> {
+        self.albums
+            .iter()
+            .flat_map(|a| a.tracks.iter().cloned())
+            .collect()
+    }
+}
+
+/// Library
+/// All the known tracks grouped by artists and genres
+#[derive(Debug, Default)]
+pub struct Library {
+    pub artists: Vec<Group>,
+    pub genres: Vec<Group>,
+    pub tracks_count: usize
+}
+impl Library {
+    /// Build the library from all the tracks in the cache
+    pub fn from_cache(cache: &Cache) -> Self {
+        let tracks: Vec<Rc<Track>> = cache.tracks_data
+            .iter()
+            .map(|(path, data)| Rc::new(Track::new(path, Rc::clone(data))))
+            .collect();
+
+        Self {
+            artists: group_tracks(&tracks, |t| t.try_album_artist().or(t.try_artist())),
+            genres: group_tracks(&tracks, |t| t.try_genre()),
+            tracks_count: tracks.len()
+        }
+    }
+
+    pub fn groups(&self, grouping: Grouping) -> &Vec<Group> {
+        match grouping {
+            Grouping::Artist => &self.artists,
+            Grouping::Genre => &self.genres,
+        }
+    }
+}
+
+/// Group the tracks by some key and then by albums
+fn group_tracks<F>(tracks: &[Rc<Track>], key: F) -> Vec<Group>
+where
+    F: Fn(&Track) -> Option<&str>
+{
+    let mut groups: HashMap<Option<String>, HashMap<Option<String>, Vec<Rc<Track>>>> = HashMap::new();
+
+    for track in tracks {
+        let group_name = key(track).map(|k| k.to_string());
+        let album_name = track.try_album().map(|a| a.to_string());
+
+        groups
+            .entry(group_name)
+            .or_default()
+            .entry(album_name)
+            .or_default()
+            .push(Rc::clone(track));
+    }
+
+    let mut groups: Vec<Group> = groups
+        .into_iter()
+        .map(|(name, albums)| {
+            let mut albums: Vec<Album> = albums
+                .into_iter()
+                .map(|(name, mut tracks)| {
+                    tracks.sort_by(|a, b| cmp_tracks(a, b));
+
+                    Album {
+                        name,
+                        year: tracks.iter().filter_map(|t| t.try_year()).min(),
+                        duration: tracks.iter().fold(Duration::default(), |acc, t| acc + t.duration()),
+                        tracks
+                    }
+                })
+                .collect();
+
+            // Albums without year go last
+            albums.sort_by(|a, b| cmp_options(&a.year, &b.year)
+                .then_with(|| cmp_names(&a.name, &b.name)));
+
+            Group {
+                name,
+                duration: albums.iter().fold(Duration::default(), |acc, a| acc + a.duration),
+                albums
+            }
+        })
+        .collect();
+
+    groups.sort_by(|a, b| cmp_names(&a.name, &b.name));
+    groups
+}
+
+/// Compare tracks by disc and track numbers
+fn cmp_tracks(a: &Track, b: &Track) -> Ordering {
+    cmp_options(&a.try_disc_number(), &b.try_disc_number())
+        .then_with(|| cmp_options(&a.try_track_number(), &b.try_track_number()))
+        .then_with(|| a.title().to_lowercase().cmp(&b.title().to_lowercase()))
+}
+/// Compare names case-insensitively, `None` goes last
+fn cmp_names(a: &Option<String>, b: &Option<String>) -> Ordering {
+    cmp_options(
+        &a.as_ref().map(|a| a.to_lowercase()),
+        &b.as_ref().map(|b| b.to_lowercase())
+    )
+}
+/// Compare options, `None` goes last
+fn cmp_options<T: Ord>(a: &Option<T>, b: &Option<T>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal
+    }
+}
@@ -0,0 +1,273 @@
+use std::{borrow::Cow, fs, path::Path, time::Duration};
+
+use lofty::{
+    config::ParseOptions,
+    file::{AudioFile, TaggedFileExt},
+    id3::v2::{Frame, FrameFlags, FrameId, SynchronizedTextFrame, TimestampFormat},
+    mpeg::MpegFile,
+    tag::ItemKey,
+};
+
+use crate::{config::Config, search::Searchable, track::Track, traits::Expand};
+
+/// Line of the lyrics
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LyricsLine {
+    /// When the line starts, unsynced lyrics don't have it
+    pub time: Option<Duration>,
+    pub text: String,
+}
+impl Searchable for LyricsLine {
+    fn matches(&self, query: &str) -> bool {
+        self.text.to_lowercase().contains(&query.to_lowercase())
+    }
+}
+
+/// Lyrics of a track
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Lyrics {
+    pub lines: Vec<LyricsLine>,
+}
+impl Lyrics {
+    /// Load lyrics of the track, the first found source is used:
+    /// - `.lrc` file next to the track with the same name
+    /// - `.lrc` file in the lyrics dir named `Artist - Title.lrc` or like the track
+    /// - Lyrics embedded into the tags (USLT, SYLT or `LYRICS`)
+    pub fn load(track: &Track, config: &Config) -> Option<Self> {
+        let sidecar = track.path.with_extension("lrc");
+
+        let in_dir = config.lyrics_dir.as_ref().and_then(|dir| {
+            let dir = dir.expand().unwrap_or(dir.clone());
+            let named = track.try_artist()
+                .zip(track.try_title())
+                .map(|(artist, title)| dir.join(format!("{} - {}.lrc", artist, title)));
+            // Dots in the name aren't an extension: `Song v1.0.mp3` -> `Song v1.0.lrc`
+            let stem = track.path.file_stem().map(|s| dir.join(format!("{}.lrc", s.to_string_lossy())));
+
+            named.into_iter()
+                .chain(stem)
+                .find(|p| p.exists())
+        });
+
+        [Some(sidecar), in_dir]
+            .into_iter()
+            .flatten()
+            .find_map(|p| read_lrc_file(&p))
+            .or_else(|| read_embedded(&track.path))
+    }
+
+    /// Parse LRC lyrics: `[01:23.45]Line`
+    /// Lines without timestamps are kept as unsynced lyrics
+    pub fn parse(content: &str) -> Self {
+        let mut lines = vec![];
+        let mut unsynced = vec![];
+        // Offset in milliseconds, positive values make the lines appear sooner
+        let mut offset = 0_i64;
+
+        for raw in content.lines() {
+            let mut rest = raw.trim();
+            let mut times = vec![];
+
+            // A line can have multiple timestamps: `[00:12.00][01:30.00]Chorus`
+            while let Some((tag, after)) = rest.strip_prefix('[').and_then(|r| r.split_once(']')) {
+                if let Some(time) = parse_timestamp(tag) {
+                    times.push(time);
+                } else if let Some(value) = tag.strip_prefix("offset:") {
+                    offset = value.trim().parse().unwrap_or_default();
+                } else if !times.is_empty() {
+                    break;
+                }
+                // Other ID tags like `[ar:Artist]` are skipped
+                rest = after;
+            }
+
+            let text = rest.trim().to_string();
+            if times.is_empty() {
+                if !raw.trim_start().starts_with('[') {
+                    unsynced.push(LyricsLine { time: None, text });
+                }
+                continue;
+            }
+
+            for time in times {
+                lines.push(LyricsLine { time: Some(time), text: text.clone() });
+            }
+        }
+
+        if lines.is_empty() {
+            // Drop the empty lines around unsynced lyrics
+            while unsynced.last().is_some_and(|l| l.text.is_empty()) { unsynced.pop(); }
+            let start = unsynced.iter().position(|l| !l.text.is_empty()).unwrap_or(unsynced.len());
+            return Self { lines: unsynced.split_off(start) };
+        }
+
+        for line in &mut lines {
+            line.time = line.time.map(|t| apply_offset(t, offset));
+        }
+        lines.sort_by_key(|l| l.time);
+        Self { lines }
+    }
+
+    /// Returns whether the lines have timestamps
+    pub fn is_synced(&self) -> bool {
+        self.lines.first().is_some_and(|l| l.time.is_some())
+    }
+    /// Returns index of the line playing at the position
+    pub fn line_at(&self, pos: Duration) -> Option<usize> {
+        if !self.is_synced() {
+            return None;
+        }
+
+        self.lines
+            .partition_point(|l| l.time.is_some_and(|t| t <= pos))
+            .checked_sub(1)
+    }
+}
+
+/// Parse a timestamp: `mm:ss`, `mm:ss.xx` or `mm:ss:xx`
+fn parse_timestamp(tag: &str) -> Option<Duration> {
+    let (mins, rest) = tag.split_once(':')?;
+    let mins: u64 = mins.trim().parse().ok()?;
+
+    let (secs, fraction) = match rest.split_once(['.', ':']) {
+        Some((secs, fraction)) => (secs, fraction),
+        None => (rest, "")
+    };
+    let secs: u64 = secs.trim().parse().ok()?;
+
+    // `.5` is half a second, `.05` is 50 milliseconds
+    let millis = if fraction.is_empty() { 0 } else {
+        let digits: String = fraction.chars().take(3).collect();
+        let value: u64 = digits.parse().ok()?;
+        value * 10_u64.pow(3 - digits.len() as u32)
+    };
+
+    let millis = mins
+        .checked_mul(60)?
+        .checked_add(secs)?
+        .checked_mul(1000)?
+        .checked_add(millis)?;
+    Some(Duration::from_millis(millis))
+}
+fn apply_offset(time: Duration, offset: i64) -> Duration {
+    let offset_dur = Duration::from_millis(offset.unsigned_abs());
+    if offset >= 0 { time.saturating_sub(offset_dur) }
+    else { time.saturating_add(offset_dur) }
+}
+
+fn read_lrc_file(path: &Path) -> Option<Lyrics> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|content| Lyrics::parse(&content))
+        .filter(|l| !l.lines.is_empty())
+}
+/// Read lyrics from the tags
+/// Synchronized SYLT frames are only supported in MP3 files
+fn read_embedded(path: &Path) -> Option<Lyrics> {
+    if let Some(lyrics) = read_sylt(path) {
+        return Some(lyrics);
+    }
+
+    let tagged = lofty::read_from_path(path).ok()?;
+    let tag = tagged.primary_tag().or(tagged.first_tag())?;
+
+    // Unsynced lyrics sometimes contain LRC timestamps
+    tag.get_string(&ItemKey::Lyrics)
+        .map(Lyrics::parse)
+        .filter(|l| !l.lines.is_empty())
+}
+fn read_sylt(path: &Path) -> Option<Lyrics> {
+    if !path.extension().is_some_and(|e| e.eq_ignore_ascii_case("mp3")) {
+        return None;
+    }
+
+    let mut file = fs::File::open(path).ok()?;
+    let mpeg = MpegFile::read_from(&mut file, ParseOptions::new()).ok()?;
+    let frame = mpeg.id3v2()?.get(&FrameId::Valid(Cow::Borrowed("SYLT")))?;
+
+    let Frame::Binary(binary) = frame else {
+        return None;
+    };
+    let sylt = SynchronizedTextFrame::parse(&binary.data, FrameFlags::default()).ok()?;
+    if sylt.timestamp_format != TimestampFormat::MS {
+        return None;
+    }
+
+    let lines = sylt.content
+        .into_iter()
+        .map(|(time, text)| LyricsLine {
+            time: Some(Duration::from_millis(time as u64)),
+            // Lines often start with a line break
+            text: text.trim().to_string(),
+        })
+        .collect();
+
+    Some(Lyrics { lines }).filter(|l| !l.lines.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(millis: u64, text: &str) -> LyricsLine {
+        LyricsLine { time: Some(Duration::from_millis(millis)), text: text.to_string() }
+    }
+    fn unsynced(text: &str) -> LyricsLine {
+        LyricsLine { time: None, text: text.to_string() }
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(parse_timestamp("01:23"), Some(Duration::from_secs(83)));
+        assert_eq!(parse_timestamp("01:23.45"), Some(Duration::from_millis(83_450)));
+        assert_eq!(parse_timestamp("01:23:5"), Some(Duration::from_millis(83_500)));
+        assert_eq!(parse_timestamp("00:01.005"), Some(Duration::from_millis(1005)));
+        assert_eq!(parse_timestamp("ar:Low Roar"), None);
+        assert_eq!(parse_timestamp("00:xx"), None);
+        // Too large to be a time
+        assert_eq!(parse_timestamp(&format!("{}:00", u64::MAX)), None);
+        assert_eq!(parse_timestamp(&format!("{}:00", u64::MAX / 60)), None);
+    }
+
+    #[test]
+    fn parse_synced() {
+        let lyrics = Lyrics::parse("\
+            [ar:Low Roar]\n\
+            [ti:Rolling Over]\n\
+            [00:12.00][01:30.00]Chorus\n\
+            [00:05.50] First line \n\
+            Stray unsynced line\n\
+            [00:20.00]\n\
+        ");
+
+        assert!(lyrics.is_synced());
+        assert_eq!(lyrics.lines, vec![
+            line(5500, "First line"),
+            line(12_000, "Chorus"),
+            line(20_000, ""),
+            line(90_000, "Chorus"),
+        ]);
+        assert_eq!(lyrics.line_at(Duration::from_secs(1)), None);
+        assert_eq!(lyrics.line_at(Duration::from_secs(12)), Some(1));
+        assert_eq!(lyrics.line_at(Duration::from_secs(600)), Some(3));
+    }
+
+    #[test]
+    fn parse_offset() {
+        let sooner = Lyrics::parse("[offset:+500]\n[00:01.00]A\n[00:00.20]B");
+        assert_eq!(sooner.lines, vec![line(0, "B"), line(500, "A")]);
+
+        let later = Lyrics::parse("[offset:-250]\n[00:01.00]A");
+        assert_eq!(later.lines, vec![line(1250, "A")]);
+    }
+
+    #[test]
+    fn parse_unsynced() {
+        let lyrics = Lyrics::parse("\n[ar:Low Roar]\n\nFirst\n\nSecond\n\n");
+
+        assert!(!lyrics.is_synced());
+        assert_eq!(lyrics.lines, vec![unsynced("First"), unsynced(""), unsynced("Second")]);
+        assert_eq!(lyrics.line_at(Duration::ZERO), None);
+        assert_eq!(Lyrics::parse(""), Lyrics::default());
+    }
+}
@@ -1,7 +1,10 @@
 mod app;
 mod cache;
+mod query;
+mod library;
 mod config;
 mod keys;
+mod keyseq;
 mod player;
 mod playlist;
 mod track;
@@ -9,23 +12,48 @@ mod traits;
 mod view;
 mod widget;
 mod cmdline;
+mod searchline;
+mod search;
 mod commands;
+mod cmdargs;
+mod completion;
+mod history;
+mod stats;
+mod rating;
+mod tageditor;
+mod cover;
+mod format;
+mod lyrics;
+mod visualizer;
+mod waveform;
 mod cli;
 mod server;
+mod ipc;
+mod scrobble;
+mod scan;
+mod watch;
 
-use std::{io::{self, Read}, ops::BitOr, sync::mpsc, thread};
+use std::{io::{self, Write}, ops::BitOr, path::PathBuf, sync::mpsc::{self, RecvError, RecvTimeoutError}, thread};
 
-use app::{App, AppContext, Mode, State, View};
+use app::{App, AppContext, Mode, Notif, State, View};
 use cache::Cache;
 use cli::{print_help, print_version, Cli};
-use commands::Commands;
+use commands::{exec_script, Commands};
 use config::{default_config_path, Config, ConfigError};
+use cover::{Cover, CoverLoader};
+use library::Library;
 use player::Player;
 use playlist::{playlists_form_config, LoadPlaylistsError};
 use rodio::OutputStream;
+use scan::{scan_music_dirs, ScanEvent};
+use scrobble::Scrobbler;
 use server::ServerAction;
+use stats::Stats;
 use thiserror::Error;
+use traits::Expand;
 use tuich::{backend::{crossterm::CrosstermBackend, BackendEvent, BackendEventReader}, event::Event, terminal::Terminal};
+use watch::watch_config_paths;
+use waveform::{Waveform, WaveformLoader};
 use widget::ListEvent;
 
 // Errors
@@ -66,7 +94,20 @@ pub type Term = Terminal<CrosstermBackend<io::Stdout>>;
 enum UpdateKind {
     Tick,
     Event(Event),
-    Server(ServerAction)
+    Server(ServerAction),
+    Scan(ScanEvent),
+    /// Some watched files were changed
+    Fs(Vec<PathBuf>),
+    /// Command received over the IPC socket
+    Ipc(String),
+    /// Notification from a background thread
+    Notif(Notif),
+    /// Time to draw the next frame of the visualizer
+    Frame,
+    /// Envelope of a track was computed in the background
+    Waveform(PathBuf, Waveform),
+    /// Cover of a track was loaded in the background, `None` if it has no cover
+    Cover(PathBuf, Option<Cover>)
 }
 
 /// App action
@@ -100,6 +141,8 @@ async fn main() -> Result<(), AppError> {
         let mut stdout = io::stdout();
 
         crossterm::terminal::disable_raw_mode().unwrap();
+        let _ = crossterm::execute!(stdout, crossterm::event::DisableMouseCapture);
+        let _ = pop_window_title(&mut stdout);
         crossterm::execute!(stdout, crossterm::terminal::LeaveAlternateScreen).unwrap();
         crossterm::execute!(stdout, crossterm::cursor::MoveTo(0, 0)).unwrap();
         eprintln!("VORU panicked: {}", msg);
@@ -124,20 +167,18 @@ async fn main() -> Result<(), AppError> {
         // Print current version and exit
         print_version();
         return Ok(())
+    } else if let Some(command) = cli.send_cmd {
+        // Send the command to the running VORU and exit
+        ipc::send(&command)?;
+        return Ok(())
     }
 
     // Trying to load a config
     let config_path = cli.config_path.unwrap_or(default_config_path().map_err(AppError::Config)?);
-    let config = match Config::from_path(&config_path) {
-        Ok(config) => config,
-        Err(e) => {
-            eprintln!("Unable to load the config {:?}:", config_path);
-            eprintln!("{}\n", e);
-            eprintln!("Press <Enter> to continue with the default config...");
-            let _ = io::stdin().read(&mut []);
-
-            Config::default()
-        }
+    // Continue with the default config on error, it will be shown as a notification
+    let (config, config_error) = match Config::from_path(&config_path) {
+        Ok(config) => (config, None),
+        Err(e) => (Config::default(), Some(e))
     };
 
     let (sender, receiver) = mpsc::channel::<UpdateKind>();
@@ -149,34 +190,87 @@ async fn main() -> Result<(), AppError> {
     // Init cache
     let mut cache = Cache::new();
 
+    // Load play statistics
+    // Continue without saving them on error, so the broken file isn't overwritten
+    let (mut stats, stats_error) = match Stats::load() {
+        Ok(stats) => (stats, None),
+        Err(e) => (Stats::default(), Some(e))
+    };
+    stats.record = config.stats;
+
     // Load playlists
-    let playlists = playlists_form_config(&mut cache, &config)
+    let playlists = playlists_form_config(&mut cache, &stats, &config)
         .map_err(AppError::LoadPlaylists)?;
     let player = Player::new(stream_handle, playlists, sender.clone()).await?;
+    let library = Library::from_cache(&cache);
 
     // Init state
     let mut state = State {
         mode: Mode::Normal,
         view: View::default(),
-        notif: None
+        notif: None,
+        open: None
     };
 
     // Echo on startup
     if let Some(echo_msg) = cli.echo_msg {
         state.notify(echo_msg);
     }
+    if let Some(e) = config_error {
+        state.notify(Notif::Error(format!("Unable to load the config {:?}: {}", config_path, e)));
+    }
+    if let Some(e) = stats_error {
+        state.notify(Notif::Error(format!("Unable to load play statistics: {}", e)));
+    }
+
+    // Add user-defined commands
+    let commands = Commands::with_custom(&config.commands);
+
+    // Submit listens in the background
+    let scrobbler = Scrobbler::new(&config.scrobble, sender.clone());
+    // Compute envelopes of the tracks for the waveform player in the background
+    let waveform = WaveformLoader::new(sender.clone());
+    // Decode covers in the background
+    let covers = CoverLoader::new(sender.clone());
 
     // Init app context
     let mut ctx = AppContext {
         config,
+        config_path,
         state,
         player,
         cache,
-        commands
+        library,
+        commands,
+        stats,
+        scrobbler,
+        waveform,
+        covers,
+        watcher: None,
+        sender: sender.clone()
+    };
+
+    // Run the startup script
+    let script_path = match &ctx.config.startup_script {
+        Some(path) => Some(path.expand().unwrap_or(path.clone())),
+        None => Some(ctx.config_path.with_file_name("voru.rc"))
+            .filter(|p| p.exists())
     };
+    if let Some(script_path) = script_path {
+        match exec_script(&mut ctx, &script_path) {
+            Ok(Action::Quit) => return Ok(()),
+            Ok(_) => (),
+            Err(e) => ctx.state.notify(Notif::Error(format!("Startup script {:?}: {}", script_path, e)))
+        }
+    }
 
     // Init terminal
     let mut term: Term = Terminal::classic(CrosstermBackend::default())?;
+    // The title is changed by the app, restore it on exit
+    push_window_title(&mut io::stdout())?;
+    if ctx.config.mouse {
+        crossterm::execute!(io::stdout(), crossterm::event::EnableMouseCapture)?;
+    }
     // Init app
     let mut app = App::new();
 
@@ -184,24 +278,69 @@ async fn main() -> Result<(), AppError> {
     handle_events(&term, sender.clone());
     handle_tick(sender.clone());
 
+    // Scan music directories in the background
+    if !ctx.config.music_dirs.is_empty() {
+        scan_music_dirs(ctx.config.music_dirs.clone(), sender.clone());
+    }
+
+    // Watch playlists and music directories for changes
+    if ctx.config.watch || ctx.config.watch_config {
+        match watch_config_paths(&ctx.config, &ctx.config_path, sender.clone()) {
+            Ok(watcher) => ctx.watcher = Some(watcher),
+            Err(e) => ctx.state.notify(Notif::Error(format!("Unable to watch files: {}", e)))
+        }
+    }
+
+    // Receive commands from other processes
+    let is_listening = ctx.config.ipc && match ipc::listen(sender.clone()) {
+        Ok(()) => true,
+        Err(e) => {
+            ctx.state.notify(Notif::Error(format!("Unable to listen to the IPC socket: {}", e)));
+            false
+        }
+    };
+
     draw(&ctx, &mut term, &mut app)?;
 
     loop {
-        let action = match receiver.recv() {
+        // Wake up to animate the visualizer between the other updates
+        let update = match app.frame_interval(&ctx) {
+            Some(interval) => match receiver.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => Ok(UpdateKind::Frame),
+                update => update.map_err(|_| RecvError)
+            },
+            None => receiver.recv()
+        };
+
+        let action = match update {
             Ok(UpdateKind::Tick) => {
                 ctx.player.handle_tick();
-                Action::Draw
+                app.handle_tick(&mut ctx) | Action::Draw
             }
             Ok(UpdateKind::Event(event)) => {
                 match event {
                     Event::Key(key, _) => {
                         app.handle_key(&mut ctx, key)
                     },
+                    Event::Mouse(mouse) => app.handle_mouse(&mut ctx, mouse),
                     Event::Resize(w, h) => Action::Resize(w, h),
                     _ => Action::Nope
                 }
             }
             Ok(UpdateKind::Server(action)) => app.handle_server_action(&mut ctx, action),
+            Ok(UpdateKind::Scan(event)) => app.handle_scan_event(&mut ctx, event),
+            Ok(UpdateKind::Fs(paths)) => app.handle_fs_change(&mut ctx, paths),
+            Ok(UpdateKind::Ipc(command)) => app.handle_ipc_command(&mut ctx, command),
+            Ok(UpdateKind::Notif(notif)) => {
+                ctx.state.notify(notif);
+                Action::Draw
+            }
+            Ok(UpdateKind::Frame) => Action::Draw,
+            Ok(UpdateKind::Waveform(path, waveform)) => {
+                ctx.waveform.set(path, waveform);
+                Action::Draw
+            }
+            Ok(UpdateKind::Cover(path, cover)) => app.set_cover(&mut ctx, path, cover),
             Err(_) => Action::Nope
         };
 
@@ -209,7 +348,18 @@ async fn main() -> Result<(), AppError> {
             Action::Nope => continue,
             Action::Draw => (),
             Action::Resize(w, h) => term.resize(w, h)?,
-            Action::Quit => break Ok(())
+            Action::Quit => {
+                // Save listening time of the current track before anything can fail
+                let _ = ctx.stats.finish(false);
+                if is_listening {
+                    ipc::cleanup();
+                }
+                if ctx.config.mouse {
+                    let _ = crossterm::execute!(io::stdout(), crossterm::event::DisableMouseCapture);
+                }
+                let _ = pop_window_title(&mut io::stdout());
+                break Ok(())
+            }
         }
 
         draw(&ctx, &mut term, &mut app)?;
@@ -224,7 +374,19 @@ fn draw(ctx: &AppContext, term: &mut Term, app: &mut App) -> io::Result<()> {
         &mut term.buffer,
         rect,
     );
-    term.draw()
+    term.draw()?;
+    app.draw_images(ctx, &mut io::stdout())?;
+    app.update_window_title(ctx, &mut io::stdout())
+}
+/// Save title of the terminal window to the stack of the terminal
+fn push_window_title<W: Write>(out: &mut W) -> io::Result<()> {
+    write!(out, "\x1b[22;0t")?;
+    out.flush()
+}
+/// Restore title of the terminal window saved by [push_window_title]
+fn pop_window_title<W: Write>(out: &mut W) -> io::Result<()> {
+    write!(out, "\x1b[23;0t")?;
+    out.flush()
 }
 
 //
@@ -16,10 +16,13 @@ use rodio::{Decoder, OutputStreamHandle, Sink, Source};
 use thiserror::Error;
 
 use crate::{
+    cache::Cache,
     playlist::Playlist,
     server::Server,
-    track::{Id, Track},
+    stats::Stats,
+    track::{Id, Track, TrackData},
     traits::{Cycle, MoveTo, Shuffle},
+    visualizer::Visualizer,
     AppError,
     UpdateKind
 };
@@ -47,7 +50,9 @@ pub enum PlaybackError {
     #[error("No more tracks to play")]
     NoMore,
     #[error("Queue is empty")]
-    EmptyQueue
+    EmptyQueue,
+    #[error("There is no previous queue")]
+    NoPrevQueue
 }
 pub type PlaybackResult = Result<(), PlaybackError>;
 
@@ -113,7 +118,8 @@ pub struct Playback {
     stream_handle: OutputStreamHandle,
 
     sink: Option<Arc<Sink>>,
-    duration: Option<Duration>
+    duration: Option<Duration>,
+    visualizer: Visualizer
 }
 impl Playback {
     fn play_path<P: AsRef<Path>>(&mut self, path: P, duration: Option<Duration>) -> PlaybackResult {
@@ -138,7 +144,7 @@ impl Playback {
 
         self.duration = duration.or(source.total_duration());
 
-        sink.append(source);
+        sink.append(self.visualizer.tap(source));
 
         self.sink = Some(sink);
 
@@ -172,6 +178,7 @@ impl Playback {
 
         sink.stop();
         self.sink = None;
+        self.visualizer.clear();
         Ok(())
     }
     fn seek(&mut self, pos: Duration) -> PlaybackResult {
@@ -205,7 +212,17 @@ impl Playback {
 #[derive(Debug)]
 pub enum QueueTrack {
     Signle(Rc<Track>),
-    Playlist(Rc<Track>, usize)
+    /// Track from a playlist with the id
+    Playlist(Rc<Track>, Id)
+}
+impl QueueTrack {
+    /// Returns the same queue track with different track data
+    pub fn with_data(&self, data: Rc<TrackData>) -> Self {
+        match self {
+            Self::Signle(track) => Self::Signle(Rc::new(track.with_data(data))),
+            Self::Playlist(track, id) => Self::Playlist(Rc::new(track.with_data(data)), *id),
+        }
+    }
 }
 impl Deref for QueueTrack {
     type Target = Rc<Track>;
@@ -218,6 +235,14 @@ impl Deref for QueueTrack {
     }
 }
 
+/// Queue replaced by another one
+#[derive(Debug, Clone)]
+struct PrevQueue {
+    tracks: Vec<Rc<QueueTrack>>,
+    /// Index of the track that was playing
+    index: Option<usize>,
+}
+
 /// Player state
 #[derive(Debug, Clone)]
 pub struct PlayerState {
@@ -234,7 +259,10 @@ pub struct Player {
     playback: Playback,
 
     pub queue: Vec<Rc<QueueTrack>>,
+    prev_queue: Option<PrevQueue>,
     pub playlists: Vec<Rc<RefCell<Playlist>>>,
+    /// Id of the * playlist containing the tracks of all the other playlists
+    all_playlist_id: Id,
     pub queue_dur: Duration,
     pub elapsed: Duration,
 
@@ -256,12 +284,9 @@ impl Player {
         sender: mpsc::Sender<UpdateKind>,
     ) -> Result<Self, AppError> {
         // Collect all the tracks from the playlists and put them into the * playlist
-        let mut all_tracks = vec![];
-        for playlist in &playlists {
-            // Clonning the vector of the Rc's
-            all_tracks.extend(playlist.borrow().tracks.clone());
-        }
-        playlists.insert(0, Rc::new(RefCell::new(Playlist::new("*", all_tracks))));
+        let all_playlist = Playlist::new("*", collect_all_tracks(&playlists, None));
+        let all_playlist_id = all_playlist.id;
+        playlists.insert(0, Rc::new(RefCell::new(all_playlist)));
 
         let state = Arc::new(Mutex::new(PlayerState {
             metadata: mpris::Metadata::default(),
@@ -290,11 +315,14 @@ impl Player {
             playback: Playback {
                 stream_handle,
                 sink: None,
-                duration: None
+                duration: None,
+                visualizer: Visualizer::new()
             },
 
             queue: vec![],
+            prev_queue: None,
             playlists,
+            all_playlist_id,
             queue_dur: Duration::default(),
             elapsed: Duration::default(),
 
@@ -410,10 +438,25 @@ impl Player {
         self.calculate_elapsed();
         Ok(())
     }
+    /// Set url of the current track cover reported over MPRIS
+    pub fn set_art_url(&mut self, url: Option<String>) {
+        if let Ok(mut state) = self.state.try_lock() {
+            state.metadata.set_art_url(url);
+
+            let _ = async_std::task::block_on(self.server.properties_changed([
+                mpris::Property::Metadata(state.metadata.clone()),
+            ]));
+        }
+    }
     pub fn play_playlist(&mut self, playlist_index: usize, track_index: usize) -> PlaybackResult {
         self.queue_set_playlist(playlist_index)?;
         self.play(track_index)
     }
+    /// Replace the queue with the tracks and play one of them
+    pub fn play_tracks(&mut self, tracks: Vec<Rc<Track>>, track_index: usize) -> PlaybackResult {
+        self.queue_set_tracks(tracks);
+        self.play(track_index)
+    }
     /// Play the first track in the queue
     pub fn replay(&mut self) -> PlaybackResult {
         self.play(0)
@@ -435,11 +478,16 @@ impl Player {
             self.play(index + 1)
         }
     }
+    /// Play previous track in the queue
+    /// Goes back to the previous queue if the current track is the first one
     pub fn play_prev(&mut self) -> PlaybackResult {
         let index = self.cur_track_index
             .ok_or(PlaybackError::NotPlaying)?;
         if index == 0 {
-            return Err(PlaybackError::NoMore);
+            return match self.prev_queue {
+                Some(_) => self.queue_restore_prev(),
+                None => Err(PlaybackError::NoMore)
+            };
         }
 
         self.play(index - 1)
@@ -559,19 +607,27 @@ impl Player {
     pub fn loopstate(&self) -> &LoopState {
         &self.loopstate
     }
+    pub fn visualizer(&self) -> &Visualizer {
+        &self.playback.visualizer
+    }
     pub fn is_track_current(&self, track_id: &Id) -> bool {
         self.cur_track
             .as_ref()
             .is_some_and(|t| t.id.eq(track_id))
     }
+    pub fn is_track_path_current(&self, path: &Path) -> bool {
+        self.cur_track
+            .as_ref()
+            .is_some_and(|t| t.path.as_path() == path)
+    }
     pub fn is_track_index_current(&self, track_index: &usize) -> bool {
         self.cur_track_index
             .as_ref()
             .is_some_and(|i| i.eq(track_index))
     }
-    pub fn is_playlist_index_current(&self, playlist_index: &usize) -> bool {
+    pub fn is_playlist_current(&self, playlist_id: &Id) -> bool {
         match self.cur_track.as_deref() {
-            Some(QueueTrack::Playlist(_, index)) => index.eq(playlist_index),
+            Some(QueueTrack::Playlist(_, id)) => id.eq(playlist_id),
             _ => false
         }
     }
@@ -592,12 +648,156 @@ impl Player {
             .map(|p| RefCell::borrow_mut(p))
     }
 
+    /// Replace a playlist keeping its id
+    pub fn playlist_set(&mut self, index: usize, mut playlist: Playlist) -> PlaybackResult {
+        let mut old = self.playlist_get_mut(index)
+            .ok_or(PlaybackError::NoPlaylist)?;
+
+        playlist.id = old.id;
+        *old = playlist;
+        Ok(())
+    }
+    /// Add a playlist to the end of the playlists list
+    pub fn playlist_add(&mut self, playlist: Playlist) {
+        self.playlists.push(Rc::new(RefCell::new(playlist)));
+    }
+    /// Remove a playlist
+    pub fn playlist_remove(&mut self, index: usize) -> PlaybackResult {
+        if index >= self.playlists.len() {
+            return Err(PlaybackError::NoPlaylist);
+        }
+
+        self.playlists.remove(index);
+        Ok(())
+    }
+    /// Returns index of the playlist loaded from the path
+    pub fn playlist_find_by_path<P: AsRef<Path>>(&self, path: P) -> Option<usize> {
+        self.playlists
+            .iter()
+            .position(|p| p.borrow().path.as_deref() == Some(path.as_ref()))
+    }
+    /// Replace all the playlists except the * one, for example after the playlists paths were changed in the config
+    pub fn replace_playlists(&mut self, playlists: Vec<Rc<RefCell<Playlist>>>) {
+        self.playlists.retain(|p| p.borrow().id == self.all_playlist_id);
+        self.playlists.extend(playlists);
+
+        self.refresh_all_playlist();
+    }
+    /// Replace the smart playlists declared in the config, for example after the config was reloaded
+    pub fn replace_config_playlists(&mut self, playlists: Vec<Playlist>) {
+        self.playlists.retain(|p| !p.borrow().is_from_config());
+        for playlist in playlists {
+            self.playlist_add(playlist);
+        }
+
+        self.refresh_all_playlist();
+    }
+    /// Select tracks of the smart playlists again, for example after the library was changed
+    pub fn refresh_smart_playlists(&mut self, cache: &Cache, stats: &Stats) {
+        for playlist in &self.playlists {
+            playlist.borrow_mut().refresh_rule(cache, stats);
+        }
+    }
+    /// Select tracks of the smart playlists depending on the play statistics or the ratings again,
+    /// the other ones can't change after a play or a rating
+    pub fn refresh_stats_playlists(&mut self, cache: &Cache, stats: &Stats) {
+        for playlist in &self.playlists {
+            let mut playlist = playlist.borrow_mut();
+            if playlist.uses_stats() {
+                playlist.refresh_rule(cache, stats);
+            }
+        }
+    }
+    /// Collect all the tracks from the other playlists into the * playlist
+    pub fn refresh_all_playlist(&mut self) {
+        let all_tracks = collect_all_tracks(&self.playlists, Some(self.all_playlist_id));
+
+        let all = self.playlists
+            .iter()
+            .find(|p| p.borrow().id == self.all_playlist_id);
+        if let Some(all) = all {
+            let mut all = all.borrow_mut();
+            all.tracks = all_tracks;
+            all.calculate_duration();
+        }
+    }
+
+    // Tracks
+    /// Replace data of all the tracks with the path in the playlists and the queue
+    pub fn refresh_track(&mut self, path: &Path, data: Rc<TrackData>) {
+        for playlist in &self.playlists {
+            let mut playlist = playlist.borrow_mut();
+            let mut changed = false;
+
+            for track in playlist.tracks.iter_mut() {
+                if track.path.as_path() == path {
+                    *track = Rc::new(track.with_data(Rc::clone(&data)));
+                    changed = true;
+                }
+            }
+
+            if changed {
+                playlist.calculate_duration();
+            }
+        }
+
+        for track in self.queue.iter_mut() {
+            if track.path.as_path() == path {
+                *track = Rc::new(track.with_data(Rc::clone(&data)));
+            }
+        }
+
+        if self.cur_track.as_ref().is_some_and(|t| t.path.as_path() == path) {
+            self.cur_track = self.cur_track_index
+                .and_then(|i| self.queue.get(i))
+                .cloned();
+        }
+
+        self.calculate_queue_dur();
+        self.calculate_elapsed();
+    }
+
+    /// Remove all the tracks with the path from the playlists and the queue, for example after the file was deleted
+    /// The current track is kept in the queue until something else is played
+    pub fn remove_track(&mut self, path: &Path) {
+        for playlist in &self.playlists {
+            let mut playlist = playlist.borrow_mut();
+            let len = playlist.tracks.len();
+
+            playlist.tracks.retain(|t| t.path.as_path() != path);
+            if playlist.tracks.len() != len {
+                playlist.calculate_duration();
+            }
+        }
+
+        let cur_index = self.cur_track_index;
+        let mut removed_before_cur = 0;
+        let mut index = 0;
+        self.queue.retain(|t| {
+            let keep = t.path.as_path() != path || Some(index) == cur_index;
+            if !keep && cur_index.is_some_and(|i| index < i) {
+                removed_before_cur += 1;
+            }
+            index += 1;
+            keep
+        });
+        self.cur_track_index = cur_index.map(|i| i - removed_before_cur);
+        self.last_track_index = None;
+
+        self.calculate_queue_dur();
+        self.calculate_elapsed();
+    }
+
     // Queue
     /// Add a track to the end of the queue
     pub fn queue_add(&mut self, track: Rc<QueueTrack>) {
         self.queue.push(track);
         self.calculate_queue_dur();
     }
+    /// Add a single track to the end of the queue
+    pub fn queue_add_track(&mut self, track: Rc<Track>) {
+        self.queue_add(QueueTrack::Signle(track).into());
+    }
     /// Add tracks to the end of the queue
     pub fn queue_add_tracks(&mut self, tracks: Vec<Rc<QueueTrack>>) {
         self.queue.extend(tracks);
@@ -609,11 +809,15 @@ impl Player {
             .get(playlist_index)
             .ok_or(PlaybackError::NoPlaylist)?;
 
+        let playlist = playlist.borrow();
+
         let mut tracks = vec![];
-        for track in &playlist.borrow().tracks {
-            tracks.push(Rc::new(QueueTrack::Playlist(Rc::clone(track), playlist_index)))
+        for track in &playlist.tracks {
+            tracks.push(Rc::new(QueueTrack::Playlist(Rc::clone(track), playlist.id)))
         }
 
+        drop(playlist);
+
         self.queue_add_tracks(tracks);
         Ok(())
     }
@@ -626,24 +830,66 @@ impl Player {
             .get(track_index)
             .ok_or(PlaybackError::NoTrack)?;
         let track = Rc::clone(track);
+        let playlist_id = playlist.id;
 
         drop(playlist);
 
-        self.queue_add(QueueTrack::Playlist(track, playlist_index).into());
+        self.queue_add(QueueTrack::Playlist(track, playlist_id).into());
         Ok(())
     }
+    /// Remember the current queue, so it can be restored after it is replaced
+    fn queue_save(&mut self) {
+        if self.queue.is_empty() { return; }
+
+        self.prev_queue = Some(PrevQueue {
+            tracks: self.queue.clone(),
+            index: self.cur_track_index,
+        });
+    }
+    /// Replace the queue with the previous one and play the track that was playing in it
+    /// The current queue becomes the previous one
+    pub fn queue_restore_prev(&mut self) -> PlaybackResult {
+        let prev = self.prev_queue
+            .take()
+            .ok_or(PlaybackError::NoPrevQueue)?;
+
+        self.queue_save();
+        self.queue = prev.tracks;
+        self.calculate_queue_dur();
+
+        match prev.index {
+            Some(index) => self.play(index),
+            None => self.stop()
+        }
+    }
     /// Clear and add tracks to the queue
     pub fn queue_set(&mut self, tracks: Vec<Rc<QueueTrack>>) -> PlaybackResult {
+        self.queue_save();
         self.queue = tracks;
         self.calculate_queue_dur();
         self.stop()
     }
+    /// Replace the queue with the tracks without stopping playback
+    pub fn queue_set_tracks(&mut self, tracks: Vec<Rc<Track>>) {
+        self.queue_save();
+        self.queue = tracks
+            .into_iter()
+            .map(|t| Rc::new(QueueTrack::Signle(t)))
+            .collect();
+        self.calculate_queue_dur();
+    }
     pub fn queue_set_playlist(&mut self, playlist_index: usize) -> PlaybackResult {
+        if playlist_index >= self.playlists.len() {
+            return Err(PlaybackError::NoPlaylist);
+        }
+
+        self.queue_save();
         self.queue.clear();
         self.queue_add_playlist(playlist_index)
     }
     /// Clear queue
     pub fn queue_clear(&mut self) -> PlaybackResult {
+        self.queue_save();
         self.queue.clear();
         self.calculate_queue_dur();
         self.stop()
@@ -659,11 +905,25 @@ impl Player {
             }
         }
     }
+    /// Swap the current track with the first one in the queue
+    /// Does nothing if nothing is playing
+    pub fn queue_move_current_to_front(&mut self) {
+        let Some(cur_index) = self.cur_track_index.filter(|i| *i < self.queue.len()) else {
+            return;
+        };
+
+        self.queue.swap(cur_index, 0);
+        self.cur_track_index = Some(0);
+        self.calculate_elapsed();
+    }
     /// Remove a track from the queue
     pub fn queue_remove(&mut self, index: usize) -> PlaybackResult {
         if self.queue.is_empty() {
             return Err(PlaybackError::EmptyQueue)
         }
+        if index >= self.queue.len() {
+            return Err(PlaybackError::NoTrack)
+        }
 
         if self.is_track_index_current(&index) {
             self.queue.remove(index);
@@ -707,3 +967,15 @@ impl Player {
         Ok(())
     }
 }
+
+/// Returns tracks of the playlists loaded from files, skipping the * playlist itself
+/// Smart playlists are skipped too, their tracks are selected from the known tracks anyway
+fn collect_all_tracks(playlists: &[Rc<RefCell<Playlist>>], all_playlist_id: Option<Id>) -> Vec<Rc<Track>> {
+    playlists
+        .iter()
+        .map(|p| p.borrow())
+        .filter(|p| p.rule.is_none() && Some(p.id) != all_playlist_id)
+        // Clonning the vector of the Rc's
+        .flat_map(|p| p.tracks.clone())
+        .collect()
+}
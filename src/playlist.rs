@@ -1,8 +1,23 @@
-use std::{cell::RefCell, fs, io, path::{Path, PathBuf}, rc::Rc, sync::atomic::{AtomicUsize, Ordering}, time::Duration};
+use std::{cell::RefCell, collections::HashMap, fs, io, path::{Path, PathBuf}, rc::Rc, sync::atomic::{AtomicUsize, Ordering}, time::Duration};
 
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::{cache::Cache, config::Config, track::{Id, Track, TrackDataError}, traits::Expand};
+use crate::{
+    cache::Cache,
+    config::{Config, ConfigSmartPlaylist},
+    query::{Query, QueryError},
+    scan::is_audio_file,
+    stats::Stats,
+    track::{Id, Track, TrackDataError},
+    traits::{Expand, Shuffle},
+};
+
+// Consts
+/// Extension of the smart playlist files
+const SMART_PLAYLIST_EXTENSION: &str = "query";
+/// Extensions of the Vim swap files
+const SWAP_EXTENSIONS: [&str; 2] = ["swp", "swx"];
 
 // Static
 static PLAYLIST_ID: AtomicUsize = AtomicUsize::new(0);
@@ -13,7 +28,9 @@ pub enum PlaylistError {
     #[error("I/O error: {0}")]
     Io(io::Error),
     #[error("Unable to load a track: {0}")]
-    Track(TrackDataError)
+    Track(TrackDataError),
+    #[error("Invalid query: {0}")]
+    Query(QueryError)
 }
 #[derive(Debug, Error)]
 pub enum LoadPlaylistsError {
@@ -31,6 +48,7 @@ pub enum LoadPlaylistsError {
 /// Returns playlists and track paths that failed to load
 pub fn playlists_form_config(
     cache: &mut Cache,
+    stats: &Stats,
     config: &Config,
 ) -> Result<Vec<Rc<RefCell<Playlist>>>, LoadPlaylistsError> {
     let mut playlists = vec![];
@@ -52,15 +70,16 @@ pub fn playlists_form_config(
                 let entry = entry
                     .map_err(LoadPlaylistsError::Io)?;
                 let path = entry.path();
-                if path.is_dir() { continue; }
+                // Audio files are not playlists, use `music_dirs` for them
+                if path.is_dir() || is_audio_file(&path) { continue; }
 
-                let playlist = Playlist::from_path(cache, path)
+                let playlist = Playlist::from_path(cache, stats, path)
                     .map_err(LoadPlaylistsError::Playlist)?;
                 playlists.push(Rc::new(RefCell::new(playlist)));
             }
         } else if path.is_file() {
             // Try load from path
-            let playlist = Playlist::from_path(cache, path)
+            let playlist = Playlist::from_path(cache, stats, path)
                 .map_err(LoadPlaylistsError::Playlist)?;
             playlists.push(Rc::new(RefCell::new(playlist)));
         } else {
@@ -69,30 +88,180 @@ pub fn playlists_form_config(
         }
     }
 
+    for smart in &config.smart_playlists {
+        let playlist = Playlist::from_config(cache, stats, smart)
+            .map_err(LoadPlaylistsError::Playlist)?;
+        playlists.push(Rc::new(RefCell::new(playlist)));
+    }
+
     Ok(playlists)
 }
 
+/// Returns whether the path points to a playlist from the config
+/// (a playlist file itself or a file in a directory of playlists)
+pub fn is_playlist_path<P: AsRef<Path>>(config: &Config, path: P) -> bool {
+    let path = path.as_ref();
+    if is_audio_file(path) || is_temp_file(path) {
+        return false;
+    }
+
+    config.playlists
+        .iter()
+        .map(|p| p.expand().unwrap_or(p.clone()))
+        .any(|p| p == path || path.parent().is_some_and(|parent| parent == p))
+}
+
+/// Returns whether the file looks like a swap, backup or lock file of Vim or Emacs
+/// Only the watcher events are filtered, such names can still be loaded as playlists
+fn is_temp_file(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    let is_swap = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| SWAP_EXTENSIONS.contains(&e));
+
+    // Vim creates "4913" to check whether the dir is writable
+    name == "4913" ||
+        name.ends_with('~') ||
+        name.starts_with(".#") ||
+        is_swap
+}
+
+/// Sort order of a smart playlist
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub enum SmartSort {
+    #[default]
+    Path,
+    Title,
+    Artist,
+    Album,
+    Year,
+    Duration,
+    /// Recently added (modified) files first
+    Added,
+    /// Most played tracks first
+    Plays,
+    /// Recently played tracks first
+    LastPlayed,
+    /// Highest rated tracks first
+    Rating,
+    Random,
+}
+impl SmartSort {
+    /// Returns whether the order depends on the play statistics or the ratings
+    fn uses_stats(&self) -> bool {
+        matches!(self, Self::Plays | Self::LastPlayed | Self::Rating)
+    }
+}
+
+/// Rule selecting tracks of a smart playlist from all the known tracks
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmartRule {
+    pub query: Query,
+    pub sort: SmartSort,
+    pub reverse: bool,
+    pub limit: Option<usize>,
+}
+impl SmartRule {
+    pub fn new(query: Query) -> Self {
+        Self {
+            query,
+            sort: SmartSort::default(),
+            reverse: false,
+            limit: None,
+        }
+    }
+
+    /// Returns whether the selected tracks or their order can change after a play
+    pub fn uses_stats(&self) -> bool {
+        self.query.uses_stats() || self.sort.uses_stats()
+    }
+
+    /// Returns tracks matching the rule
+    /// `shuffled` is the order of the tracks sorted randomly last time,
+    /// it's kept while the same tracks match, so the order doesn't change on every refresh
+    pub fn select(&self, cache: &Cache, stats: &Stats, shuffled: &mut Vec<PathBuf>) -> Vec<Rc<Track>> {
+        // Tracks are already sorted by path
+        let mut tracks = cache.query(&self.query, stats);
+
+        match self.sort {
+            SmartSort::Path => (),
+            SmartSort::Title => tracks.sort_by_cached_key(|t| t.title().to_lowercase()),
+            SmartSort::Artist => tracks.sort_by_cached_key(|t| t.try_artist().map(str::to_lowercase)),
+            SmartSort::Album => tracks.sort_by_cached_key(|t| {
+                (t.try_album().map(str::to_lowercase), t.try_disc_number(), t.try_track_number())
+            }),
+            SmartSort::Year => tracks.sort_by_key(|t| t.try_year()),
+            SmartSort::Duration => tracks.sort_by_key(|t| t.duration()),
+            SmartSort::Added => tracks.sort_by_key(|t| std::cmp::Reverse(t.try_modified())),
+            SmartSort::Plays => tracks.sort_by_key(|t| {
+                std::cmp::Reverse(stats.get(&t.path).map(|s| s.plays))
+            }),
+            SmartSort::LastPlayed => tracks.sort_by_key(|t| {
+                std::cmp::Reverse(stats.get(&t.path).and_then(|s| s.last_played))
+            }),
+            SmartSort::Rating => tracks.sort_by_key(|t| std::cmp::Reverse(t.try_rating(stats))),
+            SmartSort::Random => {
+                let mut sorted: Vec<&PathBuf> = shuffled.iter().collect();
+                sorted.sort();
+                let is_same = sorted.into_iter().eq(tracks.iter().map(|t| &t.path));
+
+                if is_same {
+                    let order: HashMap<&PathBuf, usize> = shuffled
+                        .iter()
+                        .enumerate()
+                        .map(|(index, path)| (path, index))
+                        .collect();
+                    tracks.sort_by_key(|t| order.get(&t.path).copied());
+                } else {
+                    tracks.shuffle();
+                    *shuffled = tracks.iter().map(|t| t.path.clone()).collect();
+                }
+            }
+        }
+
+        if self.reverse {
+            tracks.reverse();
+        }
+        if let Some(limit) = self.limit {
+            tracks.truncate(limit);
+        }
+
+        tracks
+    }
+}
+
 /// Playlist
 #[derive(Debug)]
 pub struct Playlist {
-    #[allow(unused)]
     pub id: Id,
+    /// Path to the playlist file, `None` if the playlist was not loaded from a file
+    pub path: Option<PathBuf>,
     pub name: String,
     pub tracks: Vec<Rc<Track>>,
-    pub duration: Duration
+    pub duration: Duration,
+    /// Rule of a smart playlist, its tracks are selected from all the known tracks
+    pub rule: Option<SmartRule>,
+    /// Paths of the tracks of a randomly sorted smart playlist in the shuffled order
+    shuffled: Vec<PathBuf>
 }
 impl Playlist {
     pub fn new<S: ToString>(name: S, tracks: Vec<Rc<Track>>) -> Self {
-        let duration = tracks
-            .iter()
-            .fold(Duration::default(), |acc, t| acc + t.duration());
-
-        Self {
+        let mut playlist = Self {
             id: PLAYLIST_ID.fetch_add(1, Ordering::Relaxed).into(),
+            path: None,
             name: name.to_string(),
             tracks,
-            duration
-        }
+            duration: Duration::default(),
+            rule: None,
+            shuffled: vec![]
+        };
+
+        playlist.calculate_duration();
+        playlist
     }
     /// Create a playlist from a file containing track paths
     /// Every line in the file is a absolute or relative
@@ -105,10 +274,15 @@ impl Playlist {
     /// - The playlist file was not found, or couldn't be read -> [PlaylistError::Io]
     /// - Unable to load a track -> [PlaylistError::Track]
     ///   (see: [Track::from_path], [TrackData::from_path])
-    pub fn from_path<P: AsRef<Path>>(cache: &mut Cache, path: P) -> Result<Self, PlaylistError> {
+    pub fn from_path<P: AsRef<Path>>(cache: &mut Cache, stats: &Stats, path: P) -> Result<Self, PlaylistError> {
         let path = path.as_ref();
         let content = fs::read_to_string(path)
             .map_err(PlaylistError::Io)?;
+
+        if path.extension().is_some_and(|e| e == SMART_PLAYLIST_EXTENSION) {
+            return Self::smart_from_str(cache, stats, path, &content);
+        }
+
         let mut duration = Duration::default();
 
         let mut tracks: Vec<Rc<Track>> = vec![];
@@ -131,11 +305,134 @@ impl Playlist {
 
         Ok(Self {
             id: PLAYLIST_ID.fetch_add(1, Ordering::Relaxed).into(),
-            name: path
-                .file_name().map(|s| s.to_string_lossy().to_string())
-                .unwrap_or("<no name>".into()),
+            path: Some(path.into()),
+            name: playlist_name(path),
             tracks,
-            duration
+            duration,
+            rule: None,
+            shuffled: vec![]
         })
     }
+    /// Create a smart playlist from a file containing a query (see [Query])
+    /// Lines starting with `#` are skipped, other lines are joined into a single query
+    fn smart_from_str(cache: &Cache, stats: &Stats, path: &Path, content: &str) -> Result<Self, PlaylistError> {
+        let source = content
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.starts_with('#'))
+            .collect::<Vec<&str>>()
+            .join(" ");
+        let query = Query::parse(&source)
+            .map_err(PlaylistError::Query)?;
+
+        let mut playlist = Self::smart(playlist_name(path), cache, stats, SmartRule::new(query));
+        playlist.path = Some(path.into());
+        Ok(playlist)
+    }
+    /// Create a smart playlist declared in the config
+    ///
+    /// # Errors
+    ///
+    /// Returns [PlaylistError::Query] if the query is invalid
+    pub fn from_config(cache: &Cache, stats: &Stats, config: &ConfigSmartPlaylist) -> Result<Self, PlaylistError> {
+        let query = Query::parse(&config.query)
+            .map_err(PlaylistError::Query)?;
+
+        Ok(Self::smart(&config.name, cache, stats, SmartRule {
+            query,
+            sort: config.sort,
+            reverse: config.reverse,
+            limit: config.limit
+        }))
+    }
+    fn smart<S: ToString>(name: S, cache: &Cache, stats: &Stats, rule: SmartRule) -> Self {
+        let mut shuffled = vec![];
+        let mut playlist = Self::new(name, rule.select(cache, stats, &mut shuffled));
+        playlist.rule = Some(rule);
+        playlist.shuffled = shuffled;
+        playlist
+    }
+
+    /// Select tracks of a smart playlist again
+    /// The tracks which are still there are kept, so they stay highlighted while playing
+    /// Returns whether the playlist is a smart playlist
+    pub fn refresh_rule(&mut self, cache: &Cache, stats: &Stats) -> bool {
+        let Some(rule) = &self.rule else {
+            return false;
+        };
+
+        let selected = rule.select(cache, stats, &mut self.shuffled);
+        let prev: HashMap<PathBuf, Rc<Track>> = std::mem::take(&mut self.tracks)
+            .into_iter()
+            .map(|t| (t.path.clone(), t))
+            .collect();
+
+        self.tracks = selected
+            .into_iter()
+            .map(|track| {
+                let Some(prev) = prev.get(&track.path) else {
+                    return track;
+                };
+
+                match (&prev.data, &track.data) {
+                    // The tags were changed, but it's still the same track
+                    (Some(prev_data), Some(data)) if !Rc::ptr_eq(prev_data, data) => {
+                        Rc::new(prev.with_data(Rc::clone(data)))
+                    }
+                    _ => Rc::clone(prev)
+                }
+            })
+            .collect();
+        self.calculate_duration();
+        true
+    }
+    /// Returns whether the playlist is a smart playlist depending on the play statistics or the ratings
+    pub fn uses_stats(&self) -> bool {
+        self.rule.as_ref().is_some_and(SmartRule::uses_stats)
+    }
+    /// Returns whether the playlist is declared in the config
+    pub fn is_from_config(&self) -> bool {
+        self.rule.is_some() && self.path.is_none()
+    }
+
+    /// Calculates the entire playlist duration
+    pub fn calculate_duration(&mut self) {
+        self.duration = self.tracks
+            .iter()
+            .fold(Duration::default(), |acc, t| acc + t.duration());
+    }
+}
+
+/// Returns playlist name from its path
+fn playlist_name(path: &Path) -> String {
+    path.file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or("<no name>".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temp_files() {
+        for name in ["4913", "chill.m3u.swp", ".chill.m3u.swx", "chill.m3u~", ".#chill.m3u"] {
+            assert!(is_temp_file(Path::new(name)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn playlist_files() {
+        for name in ["2024", "1999.m3u", ".chill", "#chill", "chill.tmp", "chill.bak", "chill.m3u"] {
+            assert!(!is_temp_file(Path::new(name)), "{}", name);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_files() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        assert!(!is_temp_file(Path::new(OsStr::from_bytes(b"chill\xff.m3u"))));
+    }
 }
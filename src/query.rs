@@ -0,0 +1,367 @@
+use std::{fmt::Display, path::Path, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{
+    cmdargs::{parse_duration, tokenize},
+    rating::track_rating,
+    search::matches_fields,
+    stats::TrackStats,
+    track::TrackData,
+};
+
+// Errors
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum QueryError {
+    #[error("Unknown field \"{0}\"")]
+    UnknownField(String),
+    #[error("Invalid value \"{0}\"")]
+    InvalidValue(String),
+    #[error("Unclosed quote")]
+    UnclosedQuote,
+}
+
+/// Track field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    Path,
+    Year,
+    Track,
+    Disc,
+    Duration,
+    Plays,
+    Skips,
+    /// Days since the last play
+    Played,
+    Rating,
+    /// `1` if the track is loved, `0` otherwise
+    Loved,
+}
+impl Field {
+    fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Self::Year | Self::Track | Self::Disc | Self::Duration |
+            Self::Plays | Self::Skips | Self::Played | Self::Rating | Self::Loved
+        )
+    }
+    /// Returns whether the field is taken from the play statistics or the rating
+    fn is_stat(&self) -> bool {
+        matches!(self, Self::Plays | Self::Skips | Self::Played | Self::Rating | Self::Loved)
+    }
+}
+impl FromStr for Field {
+    type Err = QueryError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "title" => Self::Title,
+            "artist" => Self::Artist,
+            "album" => Self::Album,
+            "albumartist" | "album_artist" => Self::AlbumArtist,
+            "genre" => Self::Genre,
+            "path" | "file" => Self::Path,
+            "year" => Self::Year,
+            "track" => Self::Track,
+            "disc" => Self::Disc,
+            "duration" | "length" => Self::Duration,
+            "plays" => Self::Plays,
+            "skips" => Self::Skips,
+            "played" => Self::Played,
+            "rating" => Self::Rating,
+            "loved" => Self::Loved,
+            _ => return Err(QueryError::UnknownField(s.to_string()))
+        })
+    }
+}
+
+/// Comparison operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    /// Text contains the value, or number equals the value
+    Contains,
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Query value
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    /// Lowercased text
+    Text(String),
+    /// Number or duration in seconds
+    Number(u64),
+}
+
+/// Query term
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Term {
+    /// Compare the field with the value: `year:>2014`
+    Field(Field, Op, Value),
+    /// Fuzzy match any of the text fields
+    Any(String),
+}
+
+/// Track query
+/// Terms separated with whitespaces must all match, `-` before a term negates it:
+/// `artist:"Low Roar" year:>2014 duration:<5m genre:ambient -title:live`
+/// Play statistics can be queried too: `plays:0`, `plays:>10`, `played:<7` (days ago), `rating:>=4`, `loved:yes`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    terms: Vec<(bool, Term)>,
+    source: String,
+}
+impl Query {
+    /// Parse a query
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a field is unknown, a value is invalid or a quote is not closed
+    pub fn parse(source: &str) -> Result<Self, QueryError> {
+        let words = tokenize(source)
+            .map_err(|_| QueryError::UnclosedQuote)?;
+        let mut terms = vec![];
+
+        for word in words {
+            let (negated, word) = match word.strip_prefix('-') {
+                Some(w) if !w.is_empty() => (true, w),
+                _ => (false, word.as_str())
+            };
+
+            let term = match word.split_once(':') {
+                Some((field, value)) => parse_field_term(field, value)?,
+                None => Term::Any(word.to_string())
+            };
+
+            terms.push((negated, term));
+        }
+
+        Ok(Self { terms, source: source.to_string() })
+    }
+
+    /// Returns whether the track data and its play statistics match the query
+    pub fn matches(&self, path: &Path, data: &TrackData, stats: Option<&TrackStats>) -> bool {
+        self.terms
+            .iter()
+            .all(|(negated, term)| term_matches(term, path, data, stats) != *negated)
+    }
+    /// Returns whether the query depends on the play statistics or the ratings,
+    /// so the matching tracks can change after a play
+    pub fn uses_stats(&self) -> bool {
+        self.terms
+            .iter()
+            .any(|(_, term)| matches!(term, Term::Field(field, _, _) if field.is_stat()))
+    }
+}
+impl Display for Query {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+fn parse_field_term(field: &str, value: &str) -> Result<Term, QueryError> {
+    let field = field.to_lowercase().parse::<Field>()?;
+
+    let (op, value) =
+        if let Some(v) = value.strip_prefix(">=") { (Op::Ge, v) }
+        else if let Some(v) = value.strip_prefix("<=") { (Op::Le, v) }
+        else if let Some(v) = value.strip_prefix('>') { (Op::Gt, v) }
+        else if let Some(v) = value.strip_prefix('<') { (Op::Lt, v) }
+        else if let Some(v) = value.strip_prefix('=') { (Op::Eq, v) }
+        else { (Op::Contains, value) };
+
+    let invalid = || QueryError::InvalidValue(value.to_string());
+
+    let value = match field {
+        Field::Duration => Value::Number(parse_duration(value).map_err(|_| invalid())?.as_secs()),
+        Field::Loved => match value {
+            "yes" | "true" | "1" => Value::Number(1),
+            "no" | "false" | "0" => Value::Number(0),
+            _ => return Err(invalid())
+        },
+        _ if field.is_numeric() => Value::Number(value.parse().map_err(|_| invalid())?),
+        // Text can't be greater or less
+        _ if !matches!(op, Op::Contains | Op::Eq) => return Err(invalid()),
+        _ => Value::Text(value.to_lowercase())
+    };
+
+    Ok(Term::Field(field, op, value))
+}
+
+fn term_matches(term: &Term, path: &Path, data: &TrackData, stats: Option<&TrackStats>) -> bool {
+    let path_str = path.to_string_lossy();
+
+    match term {
+        Term::Any(word) => {
+            let fields = [
+                data.title.as_deref(),
+                data.artist.as_deref(),
+                data.album.as_deref(),
+                path.file_name().and_then(|f| f.to_str())
+            ];
+            matches_fields(word, fields.into_iter().flatten())
+        }
+        Term::Field(field, op, Value::Text(value)) => {
+            let text = match field {
+                Field::Title => data.title.as_deref(),
+                Field::Artist => data.artist.as_deref(),
+                Field::Album => data.album.as_deref(),
+                Field::AlbumArtist => data.album_artist.as_deref(),
+                Field::Genre => data.genre.as_deref(),
+                Field::Path => Some(path_str.as_ref()),
+                _ => None
+            };
+            let Some(text) = text.map(|t| t.to_lowercase()) else {
+                return false;
+            };
+
+            match op {
+                Op::Eq => text == *value,
+                _ => text.contains(value.as_str())
+            }
+        }
+        Term::Field(field, op, Value::Number(value)) => {
+            let number = match field {
+                Field::Year => data.year.map(u64::from),
+                Field::Track => data.track_number.map(u64::from),
+                Field::Disc => data.disc_number.map(u64::from),
+                Field::Duration => Some(data.duration.as_secs()),
+                // Tracks without statistics were never played
+                Field::Plays => Some(stats.map(|s| u64::from(s.plays)).unwrap_or_default()),
+                Field::Skips => Some(stats.map(|s| u64::from(s.skips)).unwrap_or_default()),
+                Field::Played => stats.and_then(TrackStats::days_since_played),
+                // Unrated tracks are rated zero stars
+                Field::Rating => Some(track_rating(Some(data), stats).unwrap_or_default().into()),
+                Field::Loved => Some(stats.is_some_and(|s| s.loved).into()),
+                _ => None
+            };
+            let Some(number) = number else {
+                return false;
+            };
+
+            match op {
+                Op::Contains |
+                Op::Eq => number == *value,
+                Op::Lt => number < *value,
+                Op::Le => number <= *value,
+                Op::Gt => number > *value,
+                Op::Ge => number >= *value,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn track() -> TrackData {
+        TrackData {
+            title: Some("Rolling Over".to_string()),
+            artist: Some("Low Roar".to_string()),
+            album: Some("Once in a Long, Long While...".to_string()),
+            genre: Some("Ambient".to_string()),
+            year: Some(2017),
+            track_number: Some(3),
+            duration: Duration::from_secs(250),
+            ..Default::default()
+        }
+    }
+    fn matches(query: &str) -> bool {
+        Query::parse(query)
+            .unwrap()
+            .matches(Path::new("/music/low roar/03 rolling over.mp3"), &track(), None)
+    }
+
+    #[test]
+    fn parse_terms() {
+        let query = Query::parse(r#"artist:"Low Roar" -title:live year:>=2014 roar"#).unwrap();
+
+        assert_eq!(query.terms, vec![
+            (false, Term::Field(Field::Artist, Op::Contains, Value::Text("low roar".to_string()))),
+            (true, Term::Field(Field::Title, Op::Contains, Value::Text("live".to_string()))),
+            (false, Term::Field(Field::Year, Op::Ge, Value::Number(2014))),
+            (false, Term::Any("roar".to_string())),
+        ]);
+        assert_eq!(query.to_string(), r#"artist:"Low Roar" -title:live year:>=2014 roar"#);
+        assert_eq!(Query::parse("").unwrap().terms, vec![]);
+    }
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(
+            Query::parse("duration:<5m").unwrap().terms,
+            vec![(false, Term::Field(Field::Duration, Op::Lt, Value::Number(300)))]
+        );
+        assert_eq!(
+            Query::parse("loved:yes").unwrap().terms,
+            vec![(false, Term::Field(Field::Loved, Op::Contains, Value::Number(1)))]
+        );
+        assert_eq!(
+            Query::parse("Album_Artist:=X").unwrap().terms,
+            vec![(false, Term::Field(Field::AlbumArtist, Op::Eq, Value::Text("x".to_string())))]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Query::parse("foo:bar"), Err(QueryError::UnknownField("foo".to_string())));
+        assert_eq!(Query::parse("year:new"), Err(QueryError::InvalidValue("new".to_string())));
+        assert_eq!(Query::parse("title:>a"), Err(QueryError::InvalidValue("a".to_string())));
+        assert_eq!(Query::parse("loved:maybe"), Err(QueryError::InvalidValue("maybe".to_string())));
+        assert_eq!(Query::parse("artist:\"Low"), Err(QueryError::UnclosedQuote));
+    }
+
+    #[test]
+    fn match_fields() {
+        assert!(matches("artist:roar"));
+        assert!(matches("artist:=\"low roar\""));
+        assert!(!matches("artist:=roar"));
+        assert!(matches("genre:ambient year:2017 track:3"));
+        assert!(matches("year:>2014 year:<=2017 duration:>4m duration:<5m"));
+        assert!(!matches("year:<2017"));
+        assert!(matches("path:\"low roar/\""));
+        // Missing fields never match
+        assert!(!matches("album_artist:roar"));
+        assert!(!matches("disc:1"));
+    }
+
+    #[test]
+    fn match_any_and_negation() {
+        assert!(matches("rolling"));
+        assert!(matches("rlov"));
+        assert!(!matches("remix"));
+        assert!(matches("-remix"));
+        assert!(!matches("-artist:roar"));
+    }
+
+    #[test]
+    fn match_stats() {
+        let stats = TrackStats { plays: 12, rating: Some(4), loved: true, ..Default::default() };
+        let matches = |query: &str, stats: Option<&TrackStats>| {
+            Query::parse(query).unwrap().matches(Path::new("a.mp3"), &track(), stats)
+        };
+
+        assert!(matches("plays:0", None));
+        assert!(matches("plays:>10 rating:>=4 loved:yes", Some(&stats)));
+        assert!(!matches("loved:yes", None));
+        // Tracks which were never played have no days since the last play
+        assert!(!matches("played:<7", Some(&stats)));
+    }
+
+    #[test]
+    fn uses_stats() {
+        assert!(Query::parse("genre:ambient -plays:0").unwrap().uses_stats());
+        assert!(Query::parse("loved:yes").unwrap().uses_stats());
+        assert!(!Query::parse("artist:\"Low Roar\" year:>2014 roar").unwrap().uses_stats());
+    }
+}
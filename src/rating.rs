@@ -0,0 +1,183 @@
+use std::path::Path;
+
+use lofty::{config::WriteOptions, file::TaggedFileExt, tag::{ItemKey, ItemValue, Tag, TagExt, TagItem, TagType}};
+
+use crate::{app::{AppContext, UpdateError}, library::Library, stats::TrackStats, track::TrackData, watch::reload_track};
+
+// Consts
+pub const MAX_RATING: u8 = 5;
+/// Tag used by Vorbis comments and APE tags
+const RATING_KEY: &str = "RATING";
+/// Email written to the ID3v2 popularimeter frame
+const POPM_EMAIL: &str = "voru";
+/// Popularimeter ratings of the stars, the same ones are used by the most of the players
+const POPM_RATINGS: [u8; MAX_RATING as usize + 1] = [0, 1, 64, 128, 196, 255];
+
+/// Read rating from the tag
+/// ID3v2 tags keep it in the popularimeter frame, other tags have a text field
+/// with stars `1`-`5`, a percentage `6`-`100` or a fraction `0.0`-`1.0`
+pub fn read_rating(tag: &Tag) -> Option<u8> {
+    if tag.tag_type() == TagType::Id3v2 {
+        return match tag.get(&ItemKey::Popularimeter)?.value() {
+            ItemValue::Binary(popm) => parse_popm_rating(popm),
+            _ => None
+        };
+    }
+
+    let value = tag.get_string(&ItemKey::Unknown(RATING_KEY.to_string()))?;
+    parse_rating(value)
+}
+fn parse_rating(value: &str) -> Option<u8> {
+    let value = value.trim();
+
+    let stars = if value.contains('.') {
+        (value.parse::<f32>().ok()? * MAX_RATING as f32).round() as u8
+    } else {
+        match value.parse::<u8>().ok()? {
+            stars @ 0..=MAX_RATING => stars,
+            percent => ((percent.min(100) as f32 / 100.0) * MAX_RATING as f32).round() as u8
+        }
+    };
+
+    Some(stars.min(MAX_RATING)).filter(|s| *s > 0)
+}
+/// Parse a popularimeter frame: email, `0`, rating `1`-`255` and an optional play counter
+fn parse_popm_rating(popm: &[u8]) -> Option<u8> {
+    let email_end = popm.iter().position(|b| *b == 0)?;
+    let stars = match *popm.get(email_end + 1)? {
+        0 => 0,
+        1..=31 => 1,
+        32..=95 => 2,
+        96..=159 => 3,
+        160..=223 => 4,
+        _ => 5
+    };
+
+    Some(stars).filter(|s| *s > 0)
+}
+/// Returns a popularimeter frame with the rating and no play counter
+fn popm_frame(rating: u8) -> Vec<u8> {
+    let mut popm = POPM_EMAIL.as_bytes().to_vec();
+    popm.push(0);
+    popm.push(POPM_RATINGS[rating.min(MAX_RATING) as usize]);
+    popm
+}
+
+/// Returns rating of the track
+/// Rating from the statistics takes precedence over the one from the tags
+pub fn track_rating(data: Option<&TrackData>, stats: Option<&TrackStats>) -> Option<u8> {
+    match stats.and_then(|s| s.rating) {
+        // The rating was removed, but the tags are read-only
+        Some(0) => None,
+        Some(rating) => Some(rating),
+        None => data.and_then(|d| d.rating)
+    }
+}
+
+/// Set rating of the track, `0` removes it
+/// The rating is written to the tags if possible, otherwise it is kept in the statistics
+///
+/// # Errors
+///
+/// Returns an error if the statistics couldn't be saved
+pub fn set_rating(ctx: &mut AppContext, path: &Path, rating: u8) -> Result<(), UpdateError> {
+    let rating = rating.min(MAX_RATING);
+
+    if write_rating(path, rating) {
+        ctx.stats.set_rating(path, None)?;
+
+        if reload_track(ctx, path) {
+            ctx.library = Library::from_cache(&ctx.cache);
+        }
+    } else {
+        ctx.stats.set_rating(path, Some(rating))?;
+    }
+
+    ctx.player.refresh_stats_playlists(&ctx.cache, &ctx.stats);
+    Ok(())
+}
+/// Add stars to the rating of the track, a negative number removes them
+///
+/// # Errors
+///
+/// See [set_rating]
+pub fn add_rating(ctx: &mut AppContext, path: &Path, stars: i8) -> Result<(), UpdateError> {
+    let data = ctx.cache.get(path).map(|d| d.as_ref());
+    let rating = track_rating(data, ctx.stats.get(path)).unwrap_or_default() as i8 + stars;
+    set_rating(ctx, path, rating.clamp(0, MAX_RATING as i8) as u8)
+}
+/// Love or unlove the track
+/// Returns whether the track is loved now
+///
+/// # Errors
+///
+/// Returns an error if the statistics couldn't be saved
+pub fn toggle_loved(ctx: &mut AppContext, path: &Path) -> Result<bool, UpdateError> {
+    let loved = ctx.stats.toggle_loved(path)?;
+    ctx.player.refresh_stats_playlists(&ctx.cache, &ctx.stats);
+    Ok(loved)
+}
+/// Write rating to the tags
+/// Returns `false` if the tags don't support ratings or the file is read-only
+fn write_rating(path: &Path, rating: u8) -> bool {
+    let Ok(mut tagged) = lofty::read_from_path(path) else {
+        return false;
+    };
+    let Some(tag) = tagged.primary_tag_mut() else {
+        return false;
+    };
+
+    match tag.tag_type() {
+        TagType::Id3v2 if rating == 0 => tag.remove_key(&ItemKey::Popularimeter),
+        // Ratings of the other players are replaced too, otherwise one of them could be read back
+        TagType::Id3v2 => {
+            tag.insert(TagItem::new(ItemKey::Popularimeter, ItemValue::Binary(popm_frame(rating))));
+        }
+        TagType::VorbisComments | TagType::Ape => {
+            let key = ItemKey::Unknown(RATING_KEY.to_string());
+            if rating == 0 {
+                tag.remove_key(&key);
+            } else {
+                tag.insert_text(key, rating.to_string());
+            }
+        }
+        _ => return false
+    }
+
+    tag.save_to_path(path, WriteOptions::default()).is_ok()
+}
+
+/// Returns rating of the track in stars: `★★★`
+pub fn format_rating(rating: u8) -> String {
+    "★".repeat(rating.min(MAX_RATING) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_text() {
+        assert_eq!(parse_rating("3"), Some(3));
+        assert_eq!(parse_rating(" 5 "), Some(5));
+        assert_eq!(parse_rating("60"), Some(3));
+        assert_eq!(parse_rating("255"), Some(5));
+        assert_eq!(parse_rating("0.8"), Some(4));
+        assert_eq!(parse_rating("0"), None);
+        assert_eq!(parse_rating("good"), None);
+    }
+
+    #[test]
+    fn popm() {
+        assert_eq!(parse_popm_rating(b"Windows Media Player 9 Series\0\xc4"), Some(4));
+        assert_eq!(parse_popm_rating(b"foo@bar.com\0\x40\0\0\0\x07"), Some(2));
+        assert_eq!(parse_popm_rating(b"\0\x01"), Some(1));
+        assert_eq!(parse_popm_rating(b"\0\0"), None);
+        assert_eq!(parse_popm_rating(b"no rating"), None);
+
+        for stars in 1..=MAX_RATING {
+            assert_eq!(parse_popm_rating(&popm_frame(stars)), Some(stars));
+        }
+        assert_eq!(popm_frame(3), b"voru\0\x80");
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::cache_dir, track::TrackData, traits::Expand, UpdateKind};
+
+// Consts
+/// Extensions of the audio files that can be played
+pub const AUDIO_EXTENSIONS: [&str; 5] = ["mp3", "flac", "ogg", "oga", "wav"];
+/// How many tracks to send to the main loop at once
+const BATCH_SIZE: usize = 200;
+
+/// Scan event
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanEvent {
+    /// Some tracks were scanned
+    Tracks {
+        tracks: Vec<(PathBuf, TrackData)>,
+        done: usize,
+        total: usize
+    },
+    /// Scanning is finished
+    Done {
+        total: usize,
+        failed: usize
+    }
+}
+
+/// Cached track data
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedTrack {
+    /// File modification time in seconds since the unix epoch
+    modified: u64,
+    data: TrackData
+}
+
+/// Scan cache
+/// Tracks data from the previous scan, so unchanged files don't have to be read again
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct ScanCache {
+    tracks: HashMap<PathBuf, CachedTrack>
+}
+impl ScanCache {
+    fn from_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        toml::from_str(&content)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+    fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let content = toml::to_string(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)
+    }
+}
+
+/// Scan the music directories in the background
+/// Scanned tracks are sent to the main loop in batches as [UpdateKind::Scan]
+pub fn scan_music_dirs(dirs: Vec<PathBuf>, sender: mpsc::Sender<UpdateKind>) {
+    thread::spawn(move || {
+        let cache_path = cache_dir()
+            .map(|d| d.join("tracks.toml"))
+            .ok();
+        let old_cache = cache_path
+            .as_ref()
+            .and_then(|p| ScanCache::from_path(p).ok())
+            .unwrap_or_default();
+
+        // Collect all the audio files
+        let mut files = vec![];
+        for dir in &dirs {
+            let dir = dir.expand()
+                .unwrap_or(dir.clone());
+            collect_audio_files(&dir, &mut files);
+        }
+
+        let total = files.len();
+        let mut new_cache = ScanCache::default();
+
+        // Reuse data of the unchanged files
+        let mut unchanged = vec![];
+        let mut changed = vec![];
+        for path in files {
+            let modified = modified_secs(&path);
+
+            match old_cache.tracks.get(&path) {
+                Some(cached) if modified == Some(cached.modified) => {
+                    // Caches of the older versions don't have the time in the data
+                    let mut data = cached.data.clone();
+                    data.modified.get_or_insert(cached.modified);
+
+                    new_cache.tracks.insert(path.clone(), CachedTrack {
+                        modified: cached.modified,
+                        data: data.clone()
+                    });
+                    unchanged.push((path, data));
+                }
+                _ => changed.push((path, modified))
+            }
+        }
+
+        let mut done = unchanged.len();
+        let mut failed = 0_usize;
+
+        for chunk in unchanged.chunks(BATCH_SIZE) {
+            let _ = sender.send(UpdateKind::Scan(ScanEvent::Tracks {
+                tracks: chunk.to_vec(),
+                done,
+                total
+            }));
+        }
+
+        // Read the changed files in parallel
+        let queue = Arc::new(Mutex::new(changed));
+        let (result_sender, result_receiver) = mpsc::channel();
+        let workers = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4);
+
+        for _ in 0..workers {
+            let queue = Arc::clone(&queue);
+            let result_sender = result_sender.clone();
+
+            thread::spawn(move || {
+                loop {
+                    let Some((path, modified)) = queue.lock().unwrap().pop() else {
+                        break;
+                    };
+                    let data = TrackData::from_path(&path).ok();
+
+                    if result_sender.send((path, modified, data)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(result_sender);
+
+        let mut batch = vec![];
+        for (path, modified, data) in result_receiver {
+            done += 1;
+
+            let Some(data) = data else {
+                failed += 1;
+                continue;
+            };
+
+            if let Some(modified) = modified {
+                new_cache.tracks.insert(path.clone(), CachedTrack { modified, data: data.clone() });
+            }
+            batch.push((path, data));
+
+            if batch.len() >= BATCH_SIZE {
+                let _ = sender.send(UpdateKind::Scan(ScanEvent::Tracks {
+                    tracks: std::mem::take(&mut batch),
+                    done,
+                    total
+                }));
+            }
+        }
+
+        if !batch.is_empty() {
+            let _ = sender.send(UpdateKind::Scan(ScanEvent::Tracks {
+                tracks: batch,
+                done,
+                total
+            }));
+        }
+        let _ = sender.send(UpdateKind::Scan(ScanEvent::Done { total, failed }));
+
+        // Remember the scanned tracks for the next time
+        if let Some(cache_path) = cache_path {
+            let _ = new_cache.save(cache_path);
+        }
+    });
+}
+
+/// Collect paths to the audio files in the directory and its subdirectories
+/// Symlinked directories are skipped, so a symlink loop can't make it walk forever
+fn collect_audio_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let mut dirs = vec![dir.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else if file_type.is_symlink() && path.is_dir() {
+                continue;
+            } else if is_audio_file(&path) {
+                files.push(path);
+            }
+        }
+    }
+}
+
+/// Returns whether the file has one of the supported audio extensions
+pub fn is_audio_file<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| AUDIO_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+/// Returns file modification time in seconds since the unix epoch
+fn modified_secs<P: AsRef<Path>>(path: P) -> Option<u64> {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+}
@@ -0,0 +1,488 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{app::Notif, config::{data_dir, ConfigScrobble}, track::Track, UpdateKind};
+
+// Consts
+/// How often to retry submitting the listens saved while offline
+const RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Max number of listens submitted at once
+const MAX_BATCH: usize = 100;
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Errors
+#[derive(Debug, Error)]
+pub enum ScrobbleError {
+    #[error("I/O error: {0}")]
+    Io(io::Error),
+    #[error("Request error: {0}")]
+    Request(Box<ureq::Error>),
+    #[error("Invalid token")]
+    Unauthorized,
+    #[error("Listens were rejected with status {0}, they are kept in scrobbles-rejected.toml")]
+    Rejected(u16),
+    #[error("Bad syntax: {0}")]
+    Parse(toml::de::Error),
+    #[error("Unable to serialize: {0}")]
+    Serialize(toml::ser::Error),
+}
+impl From<io::Error> for ScrobbleError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+impl From<ureq::Error> for ScrobbleError {
+    fn from(value: ureq::Error) -> Self {
+        match value {
+            ureq::Error::Status(401, _) => Self::Unauthorized,
+            // Submitting the same listens again won't help, unless there were too many requests
+            ureq::Error::Status(status @ 400..=499, _) if status != 429 => Self::Rejected(status),
+            e => Self::Request(Box::new(e))
+        }
+    }
+}
+
+/// Additional info about a track
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct AdditionalInfo {
+    media_player: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tracknumber: Option<u32>,
+}
+
+/// Track metadata submitted to the server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct TrackMetadata {
+    artist_name: String,
+    track_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    release_name: Option<String>,
+    additional_info: AdditionalInfo,
+}
+
+/// Listen of a track
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Listen {
+    /// Unix timestamp, "now playing" listens don't have it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    listened_at: Option<u64>,
+    track_metadata: TrackMetadata,
+}
+impl Listen {
+    /// Returns `None` if the track has no artist or title, the server requires them
+    pub fn from_track(track: &Track, listened_at: Option<u64>) -> Option<Self> {
+        let artist = track.try_artist()?;
+        let title = track.try_title()?;
+
+        Some(Self {
+            listened_at,
+            track_metadata: TrackMetadata {
+                artist_name: artist.to_string(),
+                track_name: title.to_string(),
+                release_name: track.try_album().map(String::from),
+                additional_info: AdditionalInfo {
+                    media_player: "voru".to_string(),
+                    duration_ms: track.try_duration().map(|d| d.as_millis() as u64),
+                    tracknumber: track.try_track_number(),
+                }
+            }
+        })
+    }
+}
+
+/// Listens submitted at once
+#[derive(Debug, Serialize)]
+struct Submission<'a> {
+    listen_type: &'static str,
+    payload: &'a [Listen],
+}
+
+/// Listens that failed to submit, saved to a file in the data dir
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Pending {
+    listens: Vec<Listen>,
+}
+
+/// Message to the scrobbling thread
+#[derive(Debug)]
+enum Msg {
+    NowPlaying(Listen),
+    Scrobble(Listen),
+    Configure(ConfigScrobble),
+}
+
+/// Scrobbler
+/// Submits listens to a ListenBrainz compatible server in the background
+/// Listens that failed to submit are saved and submitted again later
+#[derive(Debug)]
+pub struct Scrobbler {
+    sender: mpsc::Sender<Msg>,
+}
+impl Scrobbler {
+    pub fn new(config: &ConfigScrobble, notifier: mpsc::Sender<UpdateKind>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let mut worker = Worker {
+            config: config.clone(),
+            path: data_dir().map(|d| d.join("scrobbles.toml")).ok(),
+            notifier,
+            unauthorized: false,
+        };
+
+        thread::spawn(move || worker.run(receiver));
+
+        Self { sender }
+    }
+
+    /// Tell the server what is playing right now
+    pub fn now_playing(&self, track: &Track) {
+        if let Some(listen) = Listen::from_track(track, None) {
+            let _ = self.sender.send(Msg::NowPlaying(listen));
+        }
+    }
+    /// Submit a listen of the track started at `listened_at` (unix timestamp)
+    pub fn scrobble(&self, track: &Track, listened_at: u64) {
+        if let Some(listen) = Listen::from_track(track, Some(listened_at)) {
+            let _ = self.sender.send(Msg::Scrobble(listen));
+        }
+    }
+    /// Use the new server url and token, for example after the config was reloaded
+    pub fn configure(&self, config: &ConfigScrobble) {
+        let _ = self.sender.send(Msg::Configure(config.clone()));
+    }
+}
+
+/// Scrobbling thread
+struct Worker {
+    config: ConfigScrobble,
+    /// Nothing is saved if there is no path
+    path: Option<PathBuf>,
+    notifier: mpsc::Sender<UpdateKind>,
+    /// Whether the server rejected the token, so it isn't reported again
+    unauthorized: bool,
+}
+impl Worker {
+    fn run(&mut self, receiver: mpsc::Receiver<Msg>) {
+        let result = self.retry();
+        self.report(result);
+
+        loop {
+            let result = match receiver.recv_timeout(RETRY_INTERVAL) {
+                Ok(Msg::NowPlaying(_)) |
+                Ok(Msg::Scrobble(_)) if !self.config.enabled => Ok(()),
+                Ok(Msg::NowPlaying(listen)) => self.submit("playing_now", &[listen]),
+                Ok(Msg::Scrobble(listen)) => {
+                    // Keep the listen if it couldn't be submitted
+                    match self.submit("single", std::slice::from_ref(&listen)) {
+                        Ok(()) => self.retry(),
+                        Err(e @ ScrobbleError::Rejected(_)) => self.save_rejected(&[listen]).and(Err(e)),
+                        Err(e) => self.save_pending(listen).and(Err(e))
+                    }
+                }
+                Ok(Msg::Configure(config)) => {
+                    self.config = config;
+                    self.unauthorized = false;
+                    self.retry()
+                }
+                Err(RecvTimeoutError::Timeout) => self.retry(),
+                // The app was closed
+                Err(RecvTimeoutError::Disconnected) => break
+            };
+
+            self.report(result);
+        }
+    }
+    fn report(&mut self, result: Result<(), ScrobbleError>) {
+        match result {
+            Err(ScrobbleError::Unauthorized) if !self.unauthorized => {
+                self.unauthorized = true;
+                self.notify(ScrobbleError::Unauthorized);
+            }
+            // Network errors are expected while offline
+            Err(ScrobbleError::Request(_)) |
+            Err(ScrobbleError::Unauthorized) |
+            Ok(()) => (),
+            Err(e) => self.notify(e)
+        }
+    }
+
+    fn submit(&self, listen_type: &'static str, listens: &[Listen]) -> Result<(), ScrobbleError> {
+        let url = format!("{}/1/submit-listens", self.config.url.trim_end_matches('/'));
+
+        ureq::post(&url)
+            .timeout(TIMEOUT)
+            .set("Authorization", &format!("Token {}", self.config.token))
+            .send_json(Submission { listen_type, payload: listens })?;
+        Ok(())
+    }
+    /// Submit the listens saved while offline
+    /// Batches rejected by the server are moved aside, so they don't block the others
+    fn retry(&self) -> Result<(), ScrobbleError> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        let mut pending = self.load_pending()?;
+        let mut rejected = None;
+
+        while !pending.listens.is_empty() {
+            let len = pending.listens.len().min(MAX_BATCH);
+            match self.submit("import", &pending.listens[..len]) {
+                Ok(()) => (),
+                Err(ScrobbleError::Rejected(status)) => {
+                    self.save_rejected(&pending.listens[..len])?;
+                    rejected = Some(status);
+                }
+                Err(e) => return Err(e)
+            }
+
+            pending.listens.drain(..len);
+            self.write_pending(&pending)?;
+        }
+
+        match rejected {
+            Some(status) => Err(ScrobbleError::Rejected(status)),
+            None => Ok(())
+        }
+    }
+
+    fn load_pending(&self) -> Result<Pending, ScrobbleError> {
+        match &self.path {
+            Some(path) => read_listens(path),
+            None => Ok(Pending::default())
+        }
+    }
+    fn save_pending(&self, listen: Listen) -> Result<(), ScrobbleError> {
+        let mut pending = self.load_pending()?;
+        pending.listens.push(listen);
+        self.write_pending(&pending)
+    }
+    fn write_pending(&self, pending: &Pending) -> Result<(), ScrobbleError> {
+        match &self.path {
+            Some(path) => write_listens(path, pending),
+            None => Ok(())
+        }
+    }
+    /// Keep the listens rejected by the server next to the pending ones, so they can be checked by hand
+    fn save_rejected(&self, listens: &[Listen]) -> Result<(), ScrobbleError> {
+        let Some(path) = self.path.as_ref().map(|p| p.with_file_name("scrobbles-rejected.toml")) else {
+            return Ok(());
+        };
+
+        let mut rejected = read_listens(&path)?;
+        rejected.listens.extend_from_slice(listens);
+        write_listens(&path, &rejected)
+    }
+
+    fn notify(&self, error: ScrobbleError) {
+        let notif = Notif::Error(format!("Scrobbling error: {}", error));
+        let _ = self.notifier.send(UpdateKind::Notif(notif));
+    }
+}
+
+fn read_listens(path: &Path) -> Result<Pending, ScrobbleError> {
+    match fs::read_to_string(path) {
+        Ok(content) => toml::from_str(&content).map_err(ScrobbleError::Parse),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Pending::default()),
+        Err(e) => Err(e.into())
+    }
+}
+fn write_listens(path: &Path, listens: &Pending) -> Result<(), ScrobbleError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let content = toml::to_string(listens)
+        .map_err(ScrobbleError::Serialize)?;
+    fs::write(path, content)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread::JoinHandle,
+    };
+
+    use super::*;
+
+    fn listen(title: &str) -> Listen {
+        Listen {
+            listened_at: Some(1_700_000_000),
+            track_metadata: TrackMetadata {
+                artist_name: "Low Roar".to_string(),
+                track_name: title.to_string(),
+                release_name: None,
+                additional_info: AdditionalInfo {
+                    media_player: "voru".to_string(),
+                    duration_ms: Some(250_000),
+                    tracknumber: None,
+                }
+            }
+        }
+    }
+    fn worker(url: String, path: Option<PathBuf>) -> Worker {
+        let (notifier, _) = mpsc::channel();
+        Worker {
+            config: ConfigScrobble { enabled: true, url, token: "secret".to_string() },
+            path,
+            notifier,
+            unauthorized: false,
+        }
+    }
+    fn pending_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir()
+            .join(format!("voru-test-{}-{}", std::process::id(), name))
+            .join("scrobbles.toml");
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    /// Stand-in server which answers `requests` requests with the status
+    /// Returns its url and a handle returning the received headers and bodies
+    fn serve(status: u16, requests: usize) -> (String, JoinHandle<Vec<(String, String)>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let mut received = vec![];
+
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut head = String::new();
+                let mut len = 0;
+
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            len = value.trim().parse().unwrap();
+                        }
+                    }
+                    head.push_str(&line);
+                }
+
+                let mut body = vec![0; len];
+                reader.read_exact(&mut body).unwrap();
+                received.push((head, String::from_utf8(body).unwrap()));
+
+                write!(stream, "HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).unwrap();
+            }
+
+            received
+        });
+
+        (url, handle)
+    }
+
+    #[test]
+    fn submit() {
+        let (url, server) = serve(200, 1);
+
+        worker(url, None).submit("single", &[listen("Rolling Over")]).unwrap();
+
+        let received = server.join().unwrap();
+        let (head, body) = &received[0];
+        assert!(head.starts_with("POST /1/submit-listens "));
+        assert!(head.contains("Authorization: Token secret"));
+        assert!(body.contains(r#""listen_type":"single""#));
+        assert!(body.contains(r#""listened_at":1700000000"#));
+        assert!(body.contains(r#""track_name":"Rolling Over""#));
+        assert!(body.contains(r#""media_player":"voru""#));
+        // Missing fields aren't sent at all
+        assert!(!body.contains("release_name"));
+    }
+
+    #[test]
+    fn unauthorized() {
+        let (url, server) = serve(401, 1);
+
+        let result = worker(url, None).submit("single", &[listen("Rolling Over")]);
+        assert!(matches!(result, Err(ScrobbleError::Unauthorized)));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn save_and_retry_pending() {
+        let path = pending_path("retry");
+
+        // Nothing listens on the port anymore
+        let url = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let offline = worker(url, Some(path.clone()));
+        for title in ["Rolling Over", "Easy Way Out"] {
+            let listen = listen(title);
+            assert!(offline.submit("single", std::slice::from_ref(&listen)).is_err());
+            offline.save_pending(listen).unwrap();
+        }
+
+        let saved = offline.load_pending().unwrap();
+        assert_eq!(saved.listens, vec![listen("Rolling Over"), listen("Easy Way Out")]);
+        assert!(fs::read_to_string(&path).unwrap().contains("Easy Way Out"));
+
+        let (url, server) = serve(200, 1);
+        worker(url, Some(path.clone())).retry().unwrap();
+
+        let received = server.join().unwrap();
+        let (_, body) = &received[0];
+        assert!(body.contains(r#""listen_type":"import""#));
+        assert!(body.contains("Rolling Over") && body.contains("Easy Way Out"));
+        assert!(offline.load_pending().unwrap().listens.is_empty());
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn move_rejected_aside() {
+        let path = pending_path("rejected");
+        let rejected_path = path.with_file_name("scrobbles-rejected.toml");
+        let _ = fs::remove_file(&rejected_path);
+
+        let (url, server) = serve(400, 1);
+        let worker = worker(url, Some(path.clone()));
+        worker.save_pending(listen("Rolling Over")).unwrap();
+
+        assert!(matches!(worker.retry(), Err(ScrobbleError::Rejected(400))));
+        server.join().unwrap();
+
+        // Doesn't block the queue, nothing is submitted again
+        assert!(worker.load_pending().unwrap().listens.is_empty());
+        worker.retry().unwrap();
+        assert_eq!(read_listens(&rejected_path).unwrap().listens, vec![listen("Rolling Over")]);
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn keep_pending_after_too_many_requests() {
+        let path = pending_path("throttled");
+
+        let (url, server) = serve(429, 1);
+        let worker = worker(url, Some(path.clone()));
+        worker.save_pending(listen("Rolling Over")).unwrap();
+
+        assert!(matches!(worker.retry(), Err(ScrobbleError::Request(_))));
+        server.join().unwrap();
+        assert_eq!(worker.load_pending().unwrap().listens, vec![listen("Rolling Over")]);
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
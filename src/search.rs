@@ -0,0 +1,98 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{library::{Album, Group}, player::QueueTrack, playlist::Playlist, track::Track};
+
+/// Search kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchKind {
+    /// Highlight matching items
+    Search,
+    /// Show only matching items
+    Filter
+}
+
+// Traits
+pub trait Searchable {
+    /// Returns whether the item matches a search query
+    fn matches(&self, query: &str) -> bool;
+}
+
+/// Case-insensitive fuzzy matching
+/// Returns `None` if not every char of the pattern was found in the text in the same order,
+/// otherwise returns a score (higher score means better match)
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<usize> {
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0_usize;
+    let mut text_index = 0_usize;
+    let mut prev_index: Option<usize> = None;
+
+    for pat_char in pattern.chars().flat_map(char::to_lowercase) {
+        if pat_char.is_whitespace() { continue; }
+
+        let offset = text[text_index..]
+            .iter()
+            .position(|c| *c == pat_char)?;
+        let index = text_index + offset;
+
+        // Reward consecutive matches and matches at the start of a word
+        if prev_index.is_some_and(|i| i + 1 == index) { score += 3; }
+        if index == 0 || !text[index - 1].is_alphanumeric() { score += 2; }
+        score += 1;
+
+        prev_index = Some(index);
+        text_index = index + 1;
+    }
+
+    Some(score)
+}
+/// Returns whether every word of the query fuzzy matches at least one of the fields
+pub fn matches_fields<'a, I: IntoIterator<Item = &'a str> + Clone>(query: &str, fields: I) -> bool {
+    query
+        .split_whitespace()
+        .all(|word| fields.clone().into_iter().any(|f| fuzzy_match(word, f).is_some()))
+}
+
+// Implement
+impl Searchable for Track {
+    fn matches(&self, query: &str) -> bool {
+        let fields = [
+            self.try_title(),
+            self.try_artist(),
+            self.try_album(),
+            self.filename.as_deref()
+        ];
+
+        matches_fields(query, fields.into_iter().flatten())
+    }
+}
+impl Searchable for QueueTrack {
+    fn matches(&self, query: &str) -> bool {
+        let track: &Track = self;
+        track.matches(query)
+    }
+}
+impl Searchable for Playlist {
+    fn matches(&self, query: &str) -> bool {
+        matches_fields(query, [self.name.as_str()])
+    }
+}
+impl Searchable for Group {
+    fn matches(&self, query: &str) -> bool {
+        matches_fields(query, [self.name()])
+    }
+}
+impl Searchable for Album {
+    fn matches(&self, query: &str) -> bool {
+        matches_fields(query, [self.name()])
+    }
+}
+impl<T: Searchable> Searchable for Rc<T> {
+    fn matches(&self, query: &str) -> bool {
+        T::matches(self, query)
+    }
+}
+impl<T: Searchable> Searchable for RefCell<T> {
+    fn matches(&self, query: &str) -> bool {
+        self.borrow().matches(query)
+    }
+}
@@ -0,0 +1,84 @@
+use tuich::{
+    buffer::Buffer,
+    event::Key,
+    layout::Rect,
+    widget::{prompt::PromptState, Clear, Draw, Prompt},
+};
+
+use crate::{
+    app::{AppContext, Mode, UpdateError},
+    match_keys,
+    search::SearchKind,
+    widget::ListState,
+    Action,
+};
+
+/// Search line
+#[derive(Debug)]
+pub struct SearchLine {
+    pub state: PromptState,
+}
+impl SearchLine {
+    pub fn new() -> Self {
+        Self {
+            state: PromptState::default(),
+        }
+    }
+
+    pub fn handle_key(
+        &mut self,
+        ctx: &mut AppContext,
+        kind: SearchKind,
+        list: &mut ListState,
+        key: Key,
+    ) -> Result<Action, UpdateError> {
+        match_keys! {
+            ctx.config, key,
+
+            enter => self.exit(ctx),
+            escape => {
+                list.clear_query();
+                self.exit(ctx);
+            };
+
+            else {
+                self.state.handle_keys(key);
+                list.set_query(self.value(), kind == SearchKind::Filter);
+            }
+        }
+
+        Ok(Action::Draw)
+    }
+
+    fn exit(&mut self, ctx: &mut AppContext) {
+        self.state.clear();
+        ctx.state.enter_mode(Mode::Normal);
+    }
+
+    pub fn draw(&self, ctx: &AppContext, kind: SearchKind, buf: &mut Buffer, rect: Rect) -> Rect {
+        let prompt_rect = rect.with_height(1);
+
+        Clear::new(ctx.config.theme.searchline)
+            .draw(buf, prompt_rect);
+
+        // Draw search prefix
+        let prefix = match kind {
+            SearchKind::Search => "/",
+            SearchKind::Filter => "\\",
+        };
+        buf.set(prompt_rect.pos(), Some(prefix), ());
+
+        // Draw prompt
+        Prompt::new(&self.state)
+            .style(ctx.config.theme.searchline)
+            .draw(buf, prompt_rect.margin_left(1));
+
+        prompt_rect
+    }
+
+    // Get
+
+    pub fn value(&self) -> &String {
+        self.state.value()
+    }
+}
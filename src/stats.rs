@@ -0,0 +1,492 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{config::data_dir, player::{PlayState, Player, QueueTrack}};
+
+// Consts
+/// Part of a track that has to be listened to count it as played
+const PLAY_THRESHOLD: f32 = 0.5;
+/// Listening time after which a long track is counted as played anyway
+const PLAY_THRESHOLD_MAX: Duration = Duration::from_secs(4 * 60);
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+/// Max number of the listening history items
+const MAX_HISTORY: usize = 1000;
+
+// Errors
+#[derive(Debug, Error)]
+pub enum StatsError {
+    #[error("I/O error: {0}")]
+    Io(io::Error),
+    #[error("Bad syntax: {0}")]
+    Parse(toml::de::Error),
+    #[error("Unable to serialize: {0}")]
+    Serialize(toml::ser::Error),
+}
+impl From<io::Error> for StatsError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+/// Statistics of a single track
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackStats {
+    pub plays: u32,
+    pub skips: u32,
+    /// Unix timestamp of the last play
+    pub last_played: Option<u64>,
+    /// Total listening time in seconds
+    pub listened: u64,
+    /// Rating in stars if the tags of the track are read-only, `0` if it was removed
+    pub rating: Option<u8>,
+    pub loved: bool,
+}
+impl TrackStats {
+    /// Returns number of days since the last play
+    pub fn days_since_played(&self) -> Option<u64> {
+        self.last_played
+            .map(|t| now_secs().saturating_sub(t) / SECS_PER_DAY)
+    }
+}
+
+/// Listening history item
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Played {
+    pub path: PathBuf,
+    /// Unix timestamp
+    pub time: u64,
+}
+
+/// Saved statistics
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct StatsData {
+    tracks: BTreeMap<PathBuf, TrackStats>,
+    /// Listening time in seconds per day (`YYYY-MM-DD` in UTC)
+    days: BTreeMap<String, u64>,
+    /// Played tracks from the oldest to the latest one
+    history: Vec<Played>,
+}
+
+/// Listening event
+#[derive(Debug, Clone)]
+pub enum ListenEvent {
+    /// Another track started playing
+    Started(Rc<QueueTrack>),
+    /// The track was listened enough to count it as played
+    /// Contains unix timestamp of when the listening was started
+    Played(Rc<QueueTrack>, u64),
+}
+
+/// Currently listened track
+#[derive(Debug)]
+struct Listening {
+    track: Rc<QueueTrack>,
+    /// Unix timestamp
+    started_at: u64,
+    listened: Duration,
+    last_pos: Duration,
+    counted: bool,
+}
+
+/// Play statistics
+/// Saved to a file in the data dir
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Whether plays, skips, listening time and history are recorded
+    pub record: bool,
+    data: StatsData,
+    /// Nothing is saved if there is no path
+    path: Option<PathBuf>,
+    listening: Option<Listening>,
+    last_tick: Option<Instant>,
+}
+impl Stats {
+    /// Load the statistics saved by the previous sessions
+    /// Starts with empty statistics if there is nothing to load
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file exists but couldn't be read or parsed
+    pub fn load() -> Result<Self, StatsError> {
+        let Ok(path) = data_dir().map(|d| d.join("stats.toml")) else {
+            return Ok(Self::default());
+        };
+
+        let data = match fs::read_to_string(&path) {
+            Ok(content) => toml::from_str(&content).map_err(StatsError::Parse)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => StatsData::default(),
+            Err(e) => return Err(e.into())
+        };
+
+        Ok(Self { record: true, data, path: Some(path), ..Default::default() })
+    }
+    /// Save the statistics
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file couldn't be written
+    pub fn save(&self) -> Result<(), StatsError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content = toml::to_string(&self.data)
+            .map_err(StatsError::Serialize)?;
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Count listening time of the current track
+    /// Returns what happened since the previous tick
+    /// and an error if the statistics couldn't be saved,
+    /// the events are returned anyway, so they aren't lost because of a failed save
+    pub fn handle_tick(&mut self, player: &Player) -> (Vec<ListenEvent>, Result<(), StatsError>) {
+        let now = Instant::now();
+        let delta = self.last_tick.map(|t| now - t).unwrap_or_default();
+        self.last_tick = Some(now);
+
+        let is_playing = player.playstate() == PlayState::Playing;
+        self.update(player.cur_track.as_ref(), player.pos(), is_playing, delta)
+    }
+    /// Count `delta` of listening time if the track is playing and it's at `pos` now
+    fn update(
+        &mut self,
+        cur_track: Option<&Rc<QueueTrack>>,
+        pos: Duration,
+        is_playing: bool,
+        delta: Duration,
+    ) -> (Vec<ListenEvent>, Result<(), StatsError>) {
+        let mut events = vec![];
+        let mut result = Ok(());
+
+        let changed = match (&self.listening, cur_track) {
+            (Some(l), Some(track)) => {
+                // The same track was played again
+                let replayed = l.counted && pos < l.last_pos && pos < Duration::from_secs(1);
+                // Tracks are replaced when their data is reloaded, but keep their ids
+                l.track.id != track.id || replayed
+            }
+            (None, None) => false,
+            _ => true
+        };
+
+        if changed {
+            // Another track was played before the current one was counted
+            let skipped = cur_track.is_some();
+            result = self.finish(skipped);
+
+            self.listening = cur_track.map(|track| Listening {
+                track: Rc::clone(track),
+                started_at: now_secs(),
+                listened: Duration::default(),
+                last_pos: pos,
+                counted: false,
+            });
+            if let Some(track) = cur_track {
+                events.push(ListenEvent::Started(Rc::clone(track)));
+            }
+        }
+
+        let Some(listening) = &mut self.listening else {
+            return (events, result);
+        };
+
+        listening.last_pos = pos;
+        if !is_playing {
+            return (events, result);
+        }
+        listening.listened += delta;
+
+        let duration = listening.track.duration();
+        let threshold =
+            if duration.is_zero() { PLAY_THRESHOLD_MAX }
+            else { duration.mul_f32(PLAY_THRESHOLD).min(PLAY_THRESHOLD_MAX) };
+        if listening.counted || listening.listened < threshold {
+            return (events, result);
+        }
+
+        listening.counted = true;
+        events.push(ListenEvent::Played(Rc::clone(&listening.track), listening.started_at));
+        if !self.record {
+            return (events, result);
+        }
+
+        let path = listening.track.path.clone();
+        let time = now_secs();
+
+        let track = self.data.tracks
+            .entry(path.clone())
+            .or_default();
+        track.plays += 1;
+        track.last_played = Some(time);
+
+        self.data.history.push(Played { path, time });
+        let len = self.data.history.len();
+        if len > MAX_HISTORY {
+            self.data.history.drain(..len - MAX_HISTORY);
+        }
+
+        (events, result.and(self.save()))
+    }
+    /// Save listening time of the current track
+    /// Counts a skip if the track wasn't listened enough and `skipped` is true
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the statistics couldn't be saved
+    pub fn finish(&mut self, skipped: bool) -> Result<(), StatsError> {
+        let Some(listening) = self.listening.take() else {
+            return Ok(());
+        };
+        if !self.record {
+            return Ok(());
+        }
+
+        let secs = listening.listened.as_secs();
+        let track = self.data.tracks
+            .entry(listening.track.path.clone())
+            .or_default();
+        track.listened += secs;
+        if skipped && !listening.counted {
+            track.skips += 1;
+        }
+        *self.data.days.entry(today()).or_default() += secs;
+
+        self.save()
+    }
+    /// Set rating of the track kept in the statistics
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the statistics couldn't be saved
+    pub fn set_rating(&mut self, path: &Path, rating: Option<u8>) -> Result<(), StatsError> {
+        let track = self.data.tracks
+            .entry(path.into())
+            .or_default();
+        if track.rating == rating {
+            return Ok(());
+        }
+
+        track.rating = rating;
+        self.save()
+    }
+    /// Love or unlove the track
+    /// Returns whether the track is loved now
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the statistics couldn't be saved
+    pub fn toggle_loved(&mut self, path: &Path) -> Result<bool, StatsError> {
+        let track = self.data.tracks
+            .entry(path.into())
+            .or_default();
+        track.loved = !track.loved;
+
+        let loved = track.loved;
+        self.save()?;
+        Ok(loved)
+    }
+    /// Remove all the listening history items
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the statistics couldn't be saved
+    pub fn clear_history(&mut self) -> Result<(), StatsError> {
+        self.data.history.clear();
+        self.save()
+    }
+
+    // Get
+
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<&TrackStats> {
+        self.data.tracks.get(path.as_ref())
+    }
+    /// Returns statistics of all the tracks
+    pub fn tracks(&self) -> impl Iterator<Item = (&PathBuf, &TrackStats)> {
+        self.data.tracks.iter()
+    }
+    /// Returns listening time per day, from the oldest day to the latest one
+    pub fn days(&self) -> impl DoubleEndedIterator<Item = (&String, Duration)> {
+        self.data.days
+            .iter()
+            .map(|(day, secs)| (day, Duration::from_secs(*secs)))
+    }
+    /// Returns played tracks from the oldest to the latest one
+    pub fn history(&self) -> &[Played] {
+        &self.data.history
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+/// Returns current date in UTC: `YYYY-MM-DD`
+fn today() -> String {
+    format_date(now_secs())
+}
+/// Format a unix timestamp as a date and time in UTC: `YYYY-MM-DD HH:MM`
+pub fn format_time(time: u64) -> String {
+    let secs_of_day = time % SECS_PER_DAY;
+    format!("{} {:02}:{:02}", format_date(time), secs_of_day / 3600, secs_of_day % 3600 / 60)
+}
+/// Format a unix timestamp as a date in UTC: `YYYY-MM-DD`
+fn format_date(time: u64) -> String {
+    // Days since 0000-03-01, so leap days are at the end of the years
+    let days = (time / SECS_PER_DAY) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::track::{Track, TrackData};
+
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+    fn queue_track(path: &str, duration: Duration) -> Rc<QueueTrack> {
+        let data = TrackData { duration, ..Default::default() };
+        Rc::new(QueueTrack::Signle(Rc::new(Track::new(path, Rc::new(data)))))
+    }
+    /// Statistics which are recorded, but never saved
+    fn stats() -> Stats {
+        Stats { record: true, ..Default::default() }
+    }
+    fn tick(stats: &mut Stats, track: Option<&Rc<QueueTrack>>, pos: Duration, is_playing: bool, delta: Duration) -> Vec<ListenEvent> {
+        let (events, result) = stats.update(track, pos, is_playing, delta);
+        result.unwrap();
+        events
+    }
+    fn count_played(events: &[ListenEvent]) -> usize {
+        events.iter().filter(|e| matches!(e, ListenEvent::Played(..))).count()
+    }
+
+    #[test]
+    fn play_threshold() {
+        let mut stats = stats();
+        let track = queue_track("a.mp3", secs(200));
+
+        let events = tick(&mut stats, Some(&track), secs(0), true, secs(0));
+        assert!(matches!(events.as_slice(), [ListenEvent::Started(_)]));
+
+        // Half of the track has to be listened
+        let events = tick(&mut stats, Some(&track), secs(60), true, secs(60));
+        assert_eq!(count_played(&events), 0);
+        // Paused time doesn't count
+        let events = tick(&mut stats, Some(&track), secs(60), false, secs(60));
+        assert_eq!(count_played(&events), 0);
+        let events = tick(&mut stats, Some(&track), secs(100), true, secs(40));
+        assert_eq!(count_played(&events), 1);
+        // Counted only once
+        let events = tick(&mut stats, Some(&track), secs(110), true, secs(10));
+        assert_eq!(count_played(&events), 0);
+
+        let track_stats = stats.get("a.mp3").unwrap();
+        assert_eq!(track_stats.plays, 1);
+        assert!(track_stats.last_played.is_some());
+        assert_eq!(stats.history().len(), 1);
+    }
+
+    #[test]
+    fn play_threshold_of_long_tracks() {
+        let mut stats = stats();
+        let track = queue_track("a.mp3", secs(60 * 60));
+
+        tick(&mut stats, Some(&track), secs(0), true, secs(0));
+        let events = tick(&mut stats, Some(&track), secs(4 * 60), true, PLAY_THRESHOLD_MAX);
+        assert_eq!(count_played(&events), 1);
+    }
+
+    #[test]
+    fn skip() {
+        let mut stats = stats();
+        let a = queue_track("a.mp3", secs(200));
+        let b = queue_track("b.mp3", secs(200));
+
+        tick(&mut stats, Some(&a), secs(0), true, secs(0));
+        tick(&mut stats, Some(&a), secs(10), true, secs(10));
+        let events = tick(&mut stats, Some(&b), secs(0), true, secs(1));
+        assert!(matches!(events.as_slice(), [ListenEvent::Started(t)] if t.path == Path::new("b.mp3")));
+
+        let a_stats = stats.get("a.mp3").unwrap();
+        assert_eq!((a_stats.plays, a_stats.skips, a_stats.listened), (0, 1, 10));
+
+        // Stopping after the end isn't a skip
+        tick(&mut stats, Some(&b), secs(200), true, secs(200));
+        tick(&mut stats, None, secs(0), false, secs(1));
+        let b_stats = stats.get("b.mp3").unwrap();
+        assert_eq!((b_stats.plays, b_stats.skips), (1, 0));
+    }
+
+    #[test]
+    fn replay() {
+        let mut stats = stats();
+        let track = queue_track("a.mp3", secs(200));
+
+        tick(&mut stats, Some(&track), secs(0), true, secs(0));
+        tick(&mut stats, Some(&track), secs(150), true, secs(150));
+
+        // Played again from the start
+        let events = tick(&mut stats, Some(&track), Duration::from_millis(500), true, secs(1));
+        assert!(matches!(events.as_slice(), [ListenEvent::Started(_)]));
+        let events = tick(&mut stats, Some(&track), secs(100), true, secs(100));
+        assert_eq!(count_played(&events), 1);
+
+        let track_stats = stats.get("a.mp3").unwrap();
+        assert_eq!((track_stats.plays, track_stats.skips), (2, 0));
+        assert_eq!(stats.history().len(), 2);
+    }
+
+    #[test]
+    fn seek_back_before_counted() {
+        let mut stats = stats();
+        let track = queue_track("a.mp3", secs(200));
+
+        tick(&mut stats, Some(&track), secs(0), true, secs(0));
+        tick(&mut stats, Some(&track), secs(50), true, secs(50));
+        // Not a replay, the track wasn't counted yet
+        let events = tick(&mut stats, Some(&track), secs(0), true, secs(1));
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(1_709_164_800), "2024-02-29");
+        // 2100 isn't a leap year
+        assert_eq!(format_date(4_107_456_000 + SECS_PER_DAY), "2100-03-01");
+
+        assert_eq!(format_date(1_704_067_199), "2023-12-31");
+        assert_eq!(format_date(1_704_067_200), "2024-01-01");
+        assert_eq!(format_time(1_704_067_199), "2023-12-31 23:59");
+        assert_eq!(format_time(1_704_067_200 + 90 * 60), "2024-01-01 01:30");
+    }
+}
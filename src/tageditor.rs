@@ -0,0 +1,339 @@
+use std::path::{Path, PathBuf};
+
+use lofty::{config::WriteOptions, file::TaggedFileExt, tag::{ItemKey, Tag, TagExt}};
+use thiserror::Error;
+use tuich::{
+    buffer::Buffer,
+    event::Key,
+    layout::{Clip, Rect},
+    style::{Color, Style},
+    text::Text,
+    widget::{prompt::PromptState, Clear, Draw, Prompt},
+};
+
+use crate::{
+    app::{AppContext, Mode, UpdateError},
+    library::Library,
+    match_keys,
+    track::TrackData,
+    watch::reload_track,
+    widget::ViewWidget,
+    Action,
+};
+
+// Consts
+/// Width of the field labels
+const LABEL_WIDTH: u16 = 14;
+
+// Errors
+#[derive(Debug, Error)]
+pub enum TagError {
+    #[error("[lofty] Unable to write tags: {0}")]
+    Lofty(lofty::error::LoftyError),
+    #[error("{0} must be a number, got \"{1}\"")]
+    InvalidNumber(&'static str, String),
+}
+impl From<lofty::error::LoftyError> for TagError {
+    fn from(value: lofty::error::LoftyError) -> Self {
+        Self::Lofty(value)
+    }
+}
+
+/// Editable tag field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagField {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    TrackNumber,
+    Year,
+    Genre,
+}
+impl TagField {
+    pub const ALL: [Self; 7] = [
+        Self::Title,
+        Self::Artist,
+        Self::Album,
+        Self::AlbumArtist,
+        Self::TrackNumber,
+        Self::Year,
+        Self::Genre,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Title => "Title",
+            Self::Artist => "Artist",
+            Self::Album => "Album",
+            Self::AlbumArtist => "Album artist",
+            Self::TrackNumber => "Track number",
+            Self::Year => "Year",
+            Self::Genre => "Genre",
+        }
+    }
+    fn key(&self) -> ItemKey {
+        match self {
+            Self::Title => ItemKey::TrackTitle,
+            Self::Artist => ItemKey::TrackArtist,
+            Self::Album => ItemKey::AlbumTitle,
+            Self::AlbumArtist => ItemKey::AlbumArtist,
+            Self::TrackNumber => ItemKey::TrackNumber,
+            Self::Year => ItemKey::Year,
+            Self::Genre => ItemKey::Genre,
+        }
+    }
+    fn is_numeric(&self) -> bool {
+        matches!(self, Self::TrackNumber | Self::Year)
+    }
+    /// Returns value of the field as it's shown in the editor
+    fn value(&self, data: &TrackData) -> Option<String> {
+        match self {
+            Self::Title => data.title.clone(),
+            Self::Artist => data.artist.clone(),
+            Self::Album => data.album.clone(),
+            Self::AlbumArtist => data.album_artist.clone(),
+            Self::TrackNumber => data.track_number.map(|n| n.to_string()),
+            Self::Year => data.year.map(|n| n.to_string()),
+            Self::Genre => data.genre.clone(),
+        }
+    }
+}
+
+/// Change of a tag field, `None` removes the field
+pub type TagEdit = (TagField, Option<String>);
+
+/// Write the changed fields to the tags of the file
+/// Creates a tag if the file has none
+///
+/// # Errors
+///
+/// Returns an error if the file couldn't be read or written
+pub fn write_tags(path: &Path, edits: &[TagEdit]) -> Result<(), TagError> {
+    let mut tagged = lofty::read_from_path(path)?;
+
+    // Write to the same tag the track data is read from
+    let tag_type = tagged.primary_tag()
+        .or(tagged.first_tag())
+        .map(Tag::tag_type)
+        .unwrap_or(tagged.primary_tag_type());
+    if tagged.tag(tag_type).is_none() {
+        tagged.insert_tag(Tag::new(tag_type));
+    }
+    let Some(tag) = tagged.tag_mut(tag_type) else {
+        return Ok(());
+    };
+
+    for (field, value) in edits {
+        match value {
+            Some(value) => { tag.insert_text(field.key(), value.clone()); }
+            None => tag.remove_key(&field.key())
+        }
+    }
+
+    tag.save_to_path(path, WriteOptions::default())?;
+    Ok(())
+}
+
+/// Field of the form
+#[derive(Debug)]
+struct FieldState {
+    field: TagField,
+    prompt: PromptState,
+    /// Whether the tracks have different values, the field is empty then
+    mixed: bool,
+    /// Value of the field when the editor was opened
+    initial: String,
+}
+impl FieldState {
+    /// Returns whether the value differs from the initial one, only the changed fields are written
+    /// Fields with different values are changed only if something was typed in
+    fn is_changed(&self) -> bool {
+        let value = self.prompt.value().trim();
+
+        if self.mixed { !value.is_empty() }
+        else { value != self.initial.trim() }
+    }
+}
+
+/// Tag editor
+/// Edits tags of one or multiple tracks at once
+#[derive(Debug)]
+pub struct TagEditor {
+    paths: Vec<PathBuf>,
+    fields: Vec<FieldState>,
+    current: usize,
+}
+impl TagEditor {
+    pub fn new() -> Self {
+        Self {
+            paths: vec![],
+            fields: vec![],
+            current: 0,
+        }
+    }
+
+    /// Fill the form with the tags of the tracks and enter the tag editor mode
+    pub fn open(&mut self, ctx: &mut AppContext, paths: Vec<PathBuf>) {
+        if paths.is_empty() {
+            return;
+        }
+
+        let datas: Vec<Option<&TrackData>> = paths
+            .iter()
+            .map(|p| ctx.cache.get(p).map(|d| d.as_ref()))
+            .collect();
+
+        self.fields = TagField::ALL
+            .iter()
+            .map(|field| {
+                let mut values = datas
+                    .iter()
+                    .map(|d| d.and_then(|d| field.value(d)));
+                let first = values.next().flatten();
+                let mixed = values.any(|v| v != first);
+
+                let initial =
+                    if mixed { String::new() }
+                    else { first.unwrap_or_default() };
+                let mut prompt = PromptState::default();
+                prompt.set_value(initial.clone());
+                prompt.move_end();
+
+                FieldState { field: *field, prompt, mixed, initial }
+            })
+            .collect();
+        self.paths = paths;
+        self.current = 0;
+
+        ctx.state.enter_mode(Mode::TagEditor);
+    }
+
+    pub fn handle_key(
+        &mut self,
+        ctx: &mut AppContext,
+        key: Key,
+    ) -> Result<Action, UpdateError> {
+        match_keys! {
+            ctx.config, key,
+
+            enter => self.save(ctx)?,
+            escape => self.exit(ctx),
+            complete => self.select_next(),
+            next_history => self.select_next(),
+            complete_prev => self.select_prev(),
+            prev_history => self.select_prev();
+
+            else {
+                if let Some(field) = self.fields.get_mut(self.current) {
+                    field.prompt.handle_keys(key);
+                }
+            }
+        }
+
+        Ok(Action::Draw)
+    }
+
+    fn select_next(&mut self) {
+        self.current = (self.current + 1) % self.fields.len().max(1);
+    }
+    fn select_prev(&mut self) {
+        self.current = self.current
+            .checked_sub(1)
+            .unwrap_or(self.fields.len().saturating_sub(1));
+    }
+
+    /// Write the changed fields to all the tracks and reload them
+    /// Keeps the editor open if some value is invalid
+    fn save(&mut self, ctx: &mut AppContext) -> Result<(), UpdateError> {
+        let mut edits = vec![];
+        for field in self.fields.iter().filter(|f| f.is_changed()) {
+            let value = field.prompt.value().trim();
+
+            if field.field.is_numeric() && !value.is_empty() && value.parse::<u32>().is_err() {
+                return Err(TagError::InvalidNumber(field.field.label(), value.to_string()).into());
+            }
+            edits.push((field.field, Some(value.to_string()).filter(|v| !v.is_empty())));
+        }
+
+        if edits.is_empty() {
+            self.exit(ctx);
+            return Ok(());
+        }
+
+        // Keep writing other tracks on error
+        let mut error = None;
+        let mut saved = 0;
+        for path in &self.paths {
+            match write_tags(path, &edits) {
+                Ok(()) => {
+                    reload_track(ctx, path);
+                    saved += 1;
+                }
+                Err(e) => error = Some(e)
+            }
+        }
+
+        ctx.library = Library::from_cache(&ctx.cache);
+        ctx.player.refresh_smart_playlists(&ctx.cache, &ctx.stats);
+        self.exit(ctx);
+
+        match error {
+            Some(e) => Err(e.into()),
+            None => {
+                ctx.state.notify(format!("Tags of {} tracks were saved", saved));
+                Ok(())
+            }
+        }
+    }
+
+    fn exit(&mut self, ctx: &mut AppContext) {
+        self.paths.clear();
+        self.fields.clear();
+        ctx.state.enter_mode(Mode::Normal);
+    }
+
+    pub fn draw(&self, ctx: &AppContext, buf: &mut Buffer, rect: Rect) -> Rect {
+        Clear::new(Style::empty())
+            .draw(buf, rect);
+
+        let desc =
+            if self.paths.len() == 1 { self.paths[0].display().to_string() }
+            else { format!("{} tracks", self.paths.len()) };
+        let content_rect = ViewWidget::new(&ctx.config, ctx.player.playstate(), "Edit tags")
+            .with_desc(desc)
+            .draw(buf, rect);
+
+        for (index, field) in self.fields.iter().enumerate() {
+            let line_rect = content_rect
+                .add_y(index as u16)
+                .with_height(1)
+                .margin((1, 0));
+            if line_rect.y >= content_rect.bottom() { break; }
+
+            let is_cur = index == self.current;
+            let label_style =
+                if is_cur { ctx.config.theme.track_selected }
+                else { ctx.config.theme.track };
+            Text::new(field.field.label(), label_style)
+                .clip(Clip::Ellipsis)
+                .draw(buf, line_rect.with_width(LABEL_WIDTH));
+
+            let value_rect = line_rect.margin_left(LABEL_WIDTH + 2);
+            if is_cur {
+                Prompt::new(&field.prompt)
+                    .draw(buf, value_rect);
+            } else if field.mixed && !field.is_changed() {
+                Text::new("<multiple values>", Color::Gray)
+                    .clip(Clip::Ellipsis)
+                    .draw(buf, value_rect);
+            } else {
+                Text::new(field.prompt.value(), ())
+                    .clip(Clip::Ellipsis)
+                    .draw(buf, value_rect);
+            }
+        }
+
+        rect
+    }
+}
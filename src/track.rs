@@ -1,9 +1,10 @@
-use std::{io, ops::Deref, fs, path::{Path, PathBuf}, rc::Rc, sync::atomic::{AtomicUsize, Ordering}, time::Duration};
+use std::{io, ops::Deref, fs, path::{Path, PathBuf}, rc::Rc, sync::atomic::{AtomicUsize, Ordering}, time::{Duration, UNIX_EPOCH}};
 
-use lofty::{file::{AudioFile, TaggedFileExt}, tag::{Accessor, TagType}};
+use lofty::{file::{AudioFile, TaggedFileExt}, tag::{Accessor, ItemKey}};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::cache::Cache;
+use crate::{cache::Cache, rating::{read_rating, track_rating}, stats::Stats};
 
 // Static
 static TRACK_ID: AtomicUsize = AtomicUsize::new(0);
@@ -33,12 +34,22 @@ impl From<usize> for Id {
 }
 
 /// Track data
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct TrackData {
     pub title: Option<String>,
     pub album: Option<String>,
     pub artist: Option<String>,
+    pub album_artist: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<u32>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    /// Rating in stars from the tags
+    pub rating: Option<u8>,
     pub duration: Duration,
+    /// File modification time in seconds since the unix epoch
+    pub modified: Option<u64>,
 }
 impl TrackData {
     /// Tries to read a audio file 
@@ -59,19 +70,35 @@ impl TrackData {
             // Other io error
             Err(e) => Err(TrackDataError::Io(e))
         }?;
+        let modified = file.metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
         let tagged = lofty::read_from(&mut file)
             .map_err(TrackDataError::Read)?;
         let duration = tagged.properties().duration();
 
-        Ok(match tagged.tag(TagType::Id3v2) {
+        let tags = tagged.primary_tag()
+            .or(tagged.first_tag());
+
+        Ok(match tags {
             Some(tags) => Self {
                 title: tags.title().map(|t| t.to_string()),
                 album: tags.album().map(|t| t.to_string()),
                 artist: tags.artist().map(|t| t.to_string()),
-                duration
+                album_artist: tags.get_string(&ItemKey::AlbumArtist).map(|t| t.to_string()),
+                genre: tags.genre().map(|t| t.to_string()),
+                year: tags.year(),
+                track_number: tags.track(),
+                disc_number: tags.disk(),
+                rating: read_rating(tags),
+                duration,
+                modified
             },
             None => Self {
                 duration,
+                modified,
                 ..Default::default()
             }
         })
@@ -87,9 +114,8 @@ pub struct Track {
     pub data: Option<Rc<TrackData>>
 }
 impl Track {
-    pub fn from_path<P: AsRef<Path>>(cache: &mut Cache, path: P) -> Result<Self, TrackDataError> {
+    pub fn new<P: AsRef<Path>>(path: P, data: Rc<TrackData>) -> Self {
         let path = PathBuf::from(path.as_ref());
-        let data = cache.get_or_create(&path)?;
 
         let filename = {
             let filename = path
@@ -110,12 +136,25 @@ impl Track {
             }
         };
 
-        Ok(Self {
+        Self {
             id: TRACK_ID.fetch_add(1, Ordering::Relaxed).into(),
             filename,
             path,
-            data: Some(Rc::clone(data))
-        })
+            data: Some(data)
+        }
+    }
+    pub fn from_path<P: AsRef<Path>>(cache: &mut Cache, path: P) -> Result<Self, TrackDataError> {
+        let data = cache.get_or_create(&path)?;
+        Ok(Self::new(path, Rc::clone(data)))
+    }
+    /// Returns the same track (with the same id) with different data
+    pub fn with_data(&self, data: Rc<TrackData>) -> Self {
+        Self {
+            id: self.id,
+            path: self.path.clone(),
+            filename: self.filename.clone(),
+            data: Some(data)
+        }
     }
 
     pub fn try_title(&self) -> Option<&str> {
@@ -127,9 +166,34 @@ impl Track {
     pub fn try_artist(&self) -> Option<&str> {
         self.data.as_ref().and_then(|d| d.artist.as_deref())
     }
+    pub fn try_album_artist(&self) -> Option<&str> {
+        self.data.as_ref().and_then(|d| d.album_artist.as_deref())
+    }
+    pub fn try_genre(&self) -> Option<&str> {
+        self.data.as_ref().and_then(|d| d.genre.as_deref())
+    }
+    pub fn try_year(&self) -> Option<u32> {
+        self.data.as_ref().and_then(|d| d.year)
+    }
+    pub fn try_track_number(&self) -> Option<u32> {
+        self.data.as_ref().and_then(|d| d.track_number)
+    }
+    pub fn try_disc_number(&self) -> Option<u32> {
+        self.data.as_ref().and_then(|d| d.disc_number)
+    }
     pub fn try_duration(&self) -> Option<&Duration> {
         self.data.as_ref().map(|d| &d.duration)
     }
+    pub fn try_modified(&self) -> Option<u64> {
+        self.data.as_ref().and_then(|d| d.modified)
+    }
+    /// Returns rating in stars, the one from the statistics takes precedence
+    pub fn try_rating(&self, stats: &Stats) -> Option<u8> {
+        track_rating(self.data.as_deref(), stats.get(&self.path))
+    }
+    pub fn is_loved(&self, stats: &Stats) -> bool {
+        stats.get(&self.path).is_some_and(|s| s.loved)
+    }
 
     /// Returns title if any
     /// If there is no title, returns file name 
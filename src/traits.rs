@@ -1,6 +1,7 @@
 use std::{io, path::{Path, PathBuf}, time::Duration};
 
 use rand::Rng;
+use tuich::layout::{Point, Rect};
 
 // Traits
 pub trait Expand {
@@ -37,6 +38,15 @@ pub trait Cycle {
     fn cycle_next(&self) -> Self;
     fn cycle_prev(&self) -> Self;
 }
+pub trait ContainsPos {
+    /// Returns whether the point is inside, used to find what was clicked
+    fn contains_pos(&self, pos: Point) -> bool;
+}
+pub trait StableHash {
+    /// Returns a 64-bit FNV-1a hash
+    /// Unlike [std::hash::DefaultHasher] it never changes between builds, so it can name cached files
+    fn stable_hash(&self) -> u64;
+}
 
 // Implement
 impl<T: AsRef<Path>> Expand for T {
@@ -93,6 +103,19 @@ impl ToReadable for Duration {
         }
     }
 }
+impl ContainsPos for Rect {
+    fn contains_pos(&self, pos: Point) -> bool {
+        pos.x >= self.x && pos.x < self.x + self.width &&
+        pos.y >= self.y && pos.y < self.y + self.height
+    }
+}
+impl StableHash for [u8] {
+    fn stable_hash(&self) -> u64 {
+        self.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+}
 impl<T> MoveTo for Vec<T> {
     fn move_to(&mut self, from_index: usize, to_index: usize) {
         let item = self.remove(from_index);
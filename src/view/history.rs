@@ -0,0 +1,146 @@
+use std::rc::Rc;
+
+use tuich::{
+    buffer::Buffer,
+    event::{Key, Mouse},
+    layout::{Align, Rect},
+    style::Color,
+    text::Text,
+    widget::Draw,
+};
+
+use crate::{
+    app::AppContext,
+    match_keys,
+    player::PlaybackError,
+    stats::format_time,
+    track::Track,
+    widget::{List, ListEvent, ListState, TrackTable, TrackWidget, ViewWidget},
+    Action,
+};
+
+/// Width of the play time column: `YYYY-MM-DD HH:MM`
+const TIME_WIDTH: u16 = 16;
+
+/// Listening history view
+#[derive(Debug)]
+pub struct HistoryView {
+    list_state: ListState,
+    /// Played tracks from the latest to the oldest one
+    tracks: Vec<Rc<Track>>,
+    /// Length of the history and play time of its last item when the tracks were collected
+    collected: Option<(usize, Option<u64>)>,
+}
+impl HistoryView {
+    pub fn new() -> Self {
+        Self {
+            list_state: ListState::new(),
+            tracks: vec![],
+            collected: None,
+        }
+    }
+
+    pub fn handle_key(&mut self, ctx: &mut AppContext, key: Key) -> Result<Action, PlaybackError> {
+        match_keys! {
+            ctx.config, key,
+
+            play => self.play(ctx)?,
+            queue_add => {
+                let track = self.load_track(ctx)?;
+                ctx.player.queue_add_track(track);
+            };
+
+            else {
+                return Ok(self.list_state.handle_key(ctx, key).into())
+            }
+        }
+
+        Ok(Action::Draw)
+    }
+
+    pub fn handle_mouse(&mut self, ctx: &mut AppContext, mouse: Mouse) -> Result<Action, PlaybackError> {
+        match self.list_state.handle_mouse(mouse) {
+            ListEvent::Chosen(_) => self.play(ctx)?,
+            event => return Ok(event.into())
+        }
+
+        Ok(Action::Draw)
+    }
+
+    /// Add the selected track to the queue and play it
+    fn play(&self, ctx: &mut AppContext) -> Result<(), PlaybackError> {
+        let track = self.load_track(ctx)?;
+        ctx.player.queue_add_track(track);
+        ctx.player.play(ctx.player.queue.len() - 1)
+    }
+    /// Load the selected track again, its data could be missing in the cache
+    fn load_track(&self, ctx: &mut AppContext) -> Result<Rc<Track>, PlaybackError> {
+        let played = ctx.stats.history()
+            .iter()
+            .rev()
+            .nth(self.list_state.current())
+            .ok_or(PlaybackError::NoTrack)?;
+
+        let path = played.path.clone();
+        Track::from_path(&mut ctx.cache, path)
+            .map(Rc::new)
+            .map_err(|_| PlaybackError::NoTrack)
+    }
+
+    pub fn draw(&mut self, ctx: &AppContext, buf: &mut Buffer, rect: Rect) -> Rect {
+        let history = ctx.stats.history();
+
+        // Collect the tracks again only if something was played or the history was cleared
+        let collected = (history.len(), history.last().map(|p| p.time));
+        if self.collected != Some(collected) {
+            self.collected = Some(collected);
+            self.tracks = history
+                .iter()
+                .rev()
+                .map(|played| {
+                    let data = ctx.cache
+                        .get(&played.path)
+                        .cloned()
+                        .unwrap_or_default();
+                    Rc::new(Track::new(&played.path, data))
+                })
+                .collect();
+            self.list_state.invalidate();
+        }
+
+        let content_rect = ViewWidget::new(&ctx.config, ctx.player.playstate(), "History")
+            .with_desc(format!("{} tracks", history.len()))
+            .draw(buf, rect);
+
+        // Leave some space for the play time
+        let table = TrackTable::new(&ctx.config.layout.track_columns, self.tracks.len(), content_rect.margin_right(TIME_WIDTH + 2));
+
+        List::new(&mut self.list_state, &self.tracks)
+            .draw(buf, content_rect, |index, track, list_state, buf, rect| {
+                let rect = TrackWidget {
+                    index,
+                    state: list_state,
+                    ctx,
+                    track,
+                    playing: ctx.player.is_track_path_current(&track.path)
+                }.draw(&table, buf, rect);
+
+                // Tracks are listed in reverse order
+                if let Some(played) = history.get(history.len() - 1 - index) {
+                    Text::new(format_time(played.time), Color::Gray)
+                        .align(Align::End)
+                        .draw(buf, rect.margin((1, 0)));
+                }
+
+                rect
+            });
+
+        rect
+    }
+
+    // Get
+
+    pub fn list_state_mut(&mut self) -> &mut ListState {
+        &mut self.list_state
+    }
+}
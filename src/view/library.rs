@@ -0,0 +1,299 @@
+use std::rc::Rc;
+
+use tuich::{
+    buffer::Buffer,
+    event::{Key, Mouse, MouseKind},
+    layout::{Rect, Stack},
+    widget::{Draw, RefDraw}
+};
+
+use crate::{
+    app::{AppContext, View},
+    library::{Grouping, Library},
+    match_keys,
+    player::{PlaybackError, PlaybackResult},
+    track::Track,
+    traits::{ContainsPos, Cycle, ToReadable},
+    widget::{List, ListEvent, ListItem, ListState, TrackTable, TrackWidget, ViewWidget},
+    Action,
+};
+
+/// Library column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Groups,
+    Albums,
+    Tracks
+}
+impl Cycle for Column {
+    fn cycle_next(&self) -> Self {
+        match self {
+            Self::Groups => Self::Albums,
+            Self::Albums => Self::Tracks,
+            Self::Tracks => Self::Groups,
+        }
+    }
+    fn cycle_prev(&self) -> Self {
+        match self {
+            Self::Tracks => Self::Albums,
+            Self::Albums => Self::Groups,
+            Self::Groups => Self::Tracks,
+        }
+    }
+}
+
+/// Library view
+#[derive(Debug)]
+pub struct LibraryView {
+    grouping: Grouping,
+    column: Column,
+    groups_state: ListState,
+    albums_state: ListState,
+    tracks_state: ListState,
+    /// Where the columns were drawn last time
+    column_rects: Vec<Rect>,
+}
+impl LibraryView {
+    pub fn new() -> Self {
+        Self {
+            grouping: Grouping::default(),
+            column: Column::Groups,
+            groups_state: ListState::new(),
+            albums_state: ListState::new(),
+            tracks_state: ListState::new(),
+            column_rects: vec![],
+        }
+    }
+
+    /// Returns tracks of the selected item in the focused column
+    /// and index of the selected track within them
+    fn selected_tracks(&self, library: &Library) -> Option<(Vec<Rc<Track>>, usize)> {
+        let group = library
+            .groups(self.grouping)
+            .get(self.groups_state.current())?;
+
+        if self.column == Column::Groups {
+            return Some((group.tracks(), 0));
+        }
+
+        let album = group.albums.get(self.albums_state.current())?;
+
+        match self.column {
+            Column::Tracks => {
+                let index = self.tracks_state.current();
+                if index >= album.tracks.len() {
+                    return None;
+                }
+
+                Some((album.tracks.clone(), index))
+            }
+            _ => Some((album.tracks.clone(), 0))
+        }
+    }
+
+    fn play(&mut self, ctx: &mut AppContext) -> PlaybackResult {
+        let (tracks, index) = self.selected_tracks(&ctx.library)
+            .ok_or(PlaybackError::NoTrack)?;
+
+        ctx.player.play_tracks(tracks, index)
+    }
+    fn play_shuffled(&mut self, ctx: &mut AppContext) -> PlaybackResult {
+        let (tracks, index) = self.selected_tracks(&ctx.library)
+            .ok_or(PlaybackError::NoTrack)?;
+
+        if self.column == Column::Tracks {
+            // Shuffle the album, but start from the selected track
+            ctx.player.play_tracks(tracks, index)?;
+            ctx.player.queue_shuffle();
+            ctx.player.queue_move_current_to_front();
+        } else {
+            ctx.player.queue_set_tracks(tracks);
+            ctx.player.queue_shuffle();
+            ctx.player.play(0)?;
+        }
+
+        Ok(())
+    }
+    fn queue_add(&mut self, ctx: &mut AppContext) -> PlaybackResult {
+        let (tracks, index) = self.selected_tracks(&ctx.library)
+            .ok_or(PlaybackError::NoTrack)?;
+
+        if self.column == Column::Tracks {
+            // Add only the selected track
+            ctx.player.queue_add_track(Rc::clone(&tracks[index]));
+        } else {
+            for track in tracks {
+                ctx.player.queue_add_track(track);
+            }
+        }
+
+        Ok(())
+    }
+    /// Recalculate search matches, the library or the selected group and album could be changed
+    pub fn invalidate(&mut self) {
+        self.groups_state.invalidate();
+        self.albums_state.invalidate();
+        self.tracks_state.invalidate();
+    }
+    fn cycle_grouping(&mut self) {
+        self.grouping = self.grouping.cycle_next();
+        self.groups_state.select_first();
+        self.albums_state.select_first();
+        self.tracks_state.select_first();
+    }
+
+    pub fn handle_key(&mut self, ctx: &mut AppContext, key: Key) -> Result<Action, PlaybackError> {
+        match_keys! {
+            ctx.config, key,
+
+            play => self.play(ctx)?,
+            play_shuffled => self.play_shuffled(ctx)?,
+            queue_add => self.queue_add(ctx)?,
+            next_column => self.column = self.column.cycle_next(),
+            prev_column => self.column = self.column.cycle_prev(),
+            cycle_grouping => self.cycle_grouping();
+
+            else {
+                return Ok(self.list_state_mut().handle_key(ctx, key).into())
+            }
+        }
+
+        Ok(Action::Draw)
+    }
+
+    /// Focus the clicked column and pass the mouse event to its list
+    pub fn handle_mouse(&mut self, ctx: &mut AppContext, mouse: Mouse) -> Result<Action, PlaybackError> {
+        let columns = [Column::Groups, Column::Albums, Column::Tracks];
+        let Some(column) = self.column_rects
+            .iter()
+            .position(|r| r.contains_pos(mouse.pos))
+            .and_then(|i| columns.get(i).copied()) else {
+            return Ok(Action::Nope);
+        };
+
+        let clicked = matches!(mouse.kind, MouseKind::Down(_));
+        if clicked {
+            self.column = column;
+        }
+        let switched = if clicked { Action::Draw } else { Action::Nope };
+
+        let state = match column {
+            Column::Groups => &mut self.groups_state,
+            Column::Albums => &mut self.albums_state,
+            Column::Tracks => &mut self.tracks_state,
+        };
+        match state.handle_mouse(mouse) {
+            ListEvent::Chosen(_) => self.play(ctx)?,
+            event => return Ok(Action::from(event) | switched)
+        }
+
+        Ok(Action::Draw)
+    }
+
+    pub fn draw(&mut self, ctx: &AppContext, buf: &mut Buffer, rect: Rect) -> Rect {
+        let rects = Stack::row(&[1, 1, 2])
+            .gap(1)
+            .calc(rect);
+        self.column_rects = rects.to_vec();
+
+        let theme = &ctx.config.theme;
+        let playstate = ctx.player.playstate();
+        let is_active = ctx.state.view == View::Library;
+
+        self.groups_state.active = is_active && self.column == Column::Groups;
+        self.albums_state.active = is_active && self.column == Column::Albums;
+        self.tracks_state.active = is_active && self.column == Column::Tracks;
+
+        let groups = ctx.library.groups(self.grouping);
+
+        // Draw groups list
+        let groups_rect = ViewWidget::new(&ctx.config, playstate, self.grouping.to_string())
+            .with_desc(groups.len().to_string())
+            .with_active(self.groups_state.active)
+            .draw(buf, rects[0]);
+
+        List::new(&mut self.groups_state, groups)
+            .draw(buf, groups_rect, |index, group, list_state, buf, rect| {
+                ListItem::new(index, list_state, group.name())
+                    .with_style(theme.playlist)
+                    .with_cur_style(theme.playlist_selected)
+                    .with_match_style(theme.playlist_matched)
+                    .draw(buf, rect)
+            });
+
+        let Some(group) = groups.get(self.groups_state.current()) else {
+            ViewWidget::new(&ctx.config, playstate, "Albums")
+                .with_active(self.albums_state.active)
+                .draw(buf, rects[1]);
+            ViewWidget::new(&ctx.config, playstate, "Tracks")
+                .with_active(self.tracks_state.active)
+                .draw(buf, rects[2]);
+            return rect;
+        };
+
+        // Draw albums list
+        let albums_rect = ViewWidget::new(&ctx.config, playstate, group.name())
+            .with_desc(group.albums.len().to_string())
+            .with_active(self.albums_state.active)
+            .draw(buf, rects[1]);
+
+        List::new(&mut self.albums_state, &group.albums)
+            .draw(buf, albums_rect, |index, album, list_state, buf, rect| {
+                let content = match album.year {
+                    Some(year) => format!("{}  {}", year, album.name()),
+                    None => album.name().to_string()
+                };
+
+                ListItem::new(index, list_state, content)
+                    .with_style(theme.playlist)
+                    .with_cur_style(theme.playlist_selected)
+                    .with_match_style(theme.playlist_matched)
+                    .draw(buf, rect)
+            });
+
+        // Draw tracks list
+        if let Some(album) = group.albums.get(self.albums_state.current()) {
+            let tracks_count = album.tracks.len();
+            let desc = ctx.config.format.tracks_desc.render(|name| match name {
+                "count" => Some(tracks_count.to_string()),
+                "duration" => Some(album.duration.to_readable()),
+                _ => None
+            });
+
+            let tracks_rect = ViewWidget::new(&ctx.config, playstate, album.name())
+                .with_desc(desc)
+                .with_active(self.tracks_state.active)
+                .draw(buf, rects[2]);
+
+            let table = TrackTable::new(&ctx.config.layout.track_columns, tracks_count, tracks_rect);
+
+            List::new(&mut self.tracks_state, &album.tracks)
+                .draw(buf, tracks_rect, |index, track, list_state, buf, rect| {
+                    TrackWidget {
+                        index,
+                        state: list_state,
+                        ctx,
+                        track,
+                        playing: ctx.player.is_track_path_current(&track.path)
+                    }.draw(&table, buf, rect)
+                });
+        } else {
+            ViewWidget::new(&ctx.config, playstate, "Tracks")
+                .with_active(self.tracks_state.active)
+                .draw(buf, rects[2]);
+        }
+
+        rect
+    }
+
+    // Get
+
+    /// Returns state of the focused list
+    pub fn list_state_mut(&mut self) -> &mut ListState {
+        match self.column {
+            Column::Groups => &mut self.groups_state,
+            Column::Albums => &mut self.albums_state,
+            Column::Tracks => &mut self.tracks_state,
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::path::PathBuf;
+
+use tuich::{
+    buffer::Buffer,
+    event::{Key, Mouse},
+    layout::{Align, Clip, Rect},
+    style::Color,
+    text::Text,
+    widget::{Clear, Draw},
+};
+
+use crate::{
+    app::AppContext,
+    lyrics::Lyrics,
+    match_keys,
+    player::PlaybackError,
+    widget::{List, ListEvent, ListState, ViewWidget},
+    Action,
+};
+
+/// Lyrics view
+#[derive(Debug)]
+pub struct LyricsView {
+    list_state: ListState,
+    lyrics: Option<Lyrics>,
+    /// Track which lyrics are loaded
+    track_path: Option<PathBuf>,
+    /// Whether the playing line is selected automatically
+    follow: bool,
+}
+impl LyricsView {
+    pub fn new() -> Self {
+        Self {
+            list_state: ListState::new().with_scrolloff(5),
+            lyrics: None,
+            track_path: None,
+            follow: true,
+        }
+    }
+
+    /// Load lyrics of the current track if it was changed
+    pub fn update_lyrics(&mut self, ctx: &AppContext) {
+        let track = ctx.player.cur_track.as_ref();
+        let path = track.map(|t| t.path.clone());
+        if path == self.track_path {
+            return;
+        }
+
+        self.lyrics = track.and_then(|t| Lyrics::load(t, &ctx.config));
+        self.track_path = path;
+        self.list_state = ListState::new().with_scrolloff(5);
+        self.follow = true;
+    }
+
+    pub fn handle_key(&mut self, ctx: &mut AppContext, key: Key) -> Result<Action, PlaybackError> {
+        match_keys! {
+            ctx.config, key,
+
+            play => self.seek_to_selected(ctx)?,
+            queue_focus => self.follow = true;
+
+            else {
+                let event = self.list_state.handle_key(ctx, key);
+                // Stop following the playing line while the user scrolls through the lyrics
+                if let ListEvent::Selected(_) = event {
+                    self.follow = false;
+                }
+                return Ok(event.into())
+            }
+        }
+
+        Ok(Action::Draw)
+    }
+
+    pub fn handle_mouse(&mut self, ctx: &mut AppContext, mouse: Mouse) -> Result<Action, PlaybackError> {
+        match self.list_state.handle_mouse(mouse) {
+            ListEvent::Chosen(_) => self.seek_to_selected(ctx)?,
+            event => {
+                if let ListEvent::Selected(_) | ListEvent::Scrolled(_) = event {
+                    self.follow = false;
+                }
+                return Ok(event.into())
+            }
+        }
+
+        Ok(Action::Draw)
+    }
+
+    /// Seek to the selected line
+    fn seek_to_selected(&mut self, ctx: &mut AppContext) -> Result<(), PlaybackError> {
+        let time = self.lyrics
+            .as_ref()
+            .and_then(|l| l.lines.get(self.list_state.current()))
+            .and_then(|l| l.time);
+
+        if let Some(time) = time {
+            ctx.player.seek(time)?;
+            self.follow = true;
+        }
+        Ok(())
+    }
+
+    pub fn draw(&mut self, ctx: &AppContext, buf: &mut Buffer, rect: Rect) -> Rect {
+        let theme = &ctx.config.theme;
+        let title = ctx.player.cur_track
+            .as_ref()
+            .map(|t| t.title())
+            .unwrap_or("Lyrics");
+
+        let Some(lyrics) = &self.lyrics else {
+            let content_rect = ViewWidget::new(&ctx.config, ctx.player.playstate(), title)
+                .draw(buf, rect);
+            Text::new("No lyrics found", Color::Gray)
+                .clip(Clip::Ellipsis)
+                .draw(buf, content_rect.margin((1, 0)));
+            return rect;
+        };
+
+        let desc = if lyrics.is_synced() { "synced" } else { "unsynced" };
+        let content_rect = ViewWidget::new(&ctx.config, ctx.player.playstate(), title)
+            .with_desc(desc)
+            .draw(buf, rect);
+
+        let playing_line = lyrics.line_at(ctx.player.pos());
+        if self.follow {
+            if let Some(index) = playing_line {
+                self.list_state.select_index(index);
+            }
+        }
+
+        let follow = self.follow;
+        List::new(&mut self.list_state, &lyrics.lines)
+            .draw(buf, content_rect, |index, line, list_state, buf, rect| {
+                let rect = rect.with_height(1);
+                let is_cur = !follow && list_state.active && list_state.current() == index;
+
+                let style =
+                    if Some(index) == playing_line && is_cur { theme.track_selected_playing }
+                    else if Some(index) == playing_line { theme.track_playing }
+                    else if is_cur { theme.track_selected }
+                    else if list_state.is_match(index) { theme.track_matched }
+                    else { theme.track };
+
+                Clear::new(style)
+                    .draw(buf, rect);
+                Text::new(&line.text, ())
+                    .align(Align::Center)
+                    .clip(Clip::Ellipsis)
+                    .draw(buf, rect.margin((1, 0)));
+
+                rect
+            });
+
+        rect
+    }
+
+    // Get
+
+    pub fn list_state_mut(&mut self) -> &mut ListState {
+        &mut self.list_state
+    }
+}
@@ -1,7 +1,15 @@
 mod playlists;
 mod queue;
 mod player;
+mod library;
+mod stats;
+mod history;
+mod lyrics;
 
 pub use playlists::*;
 pub use queue::*;
 pub use player::*;
+pub use library::*;
+pub use stats::*;
+pub use history::*;
+pub use lyrics::*;
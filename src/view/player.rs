@@ -1,27 +1,175 @@
+use std::{io::{self, Write}, path::PathBuf};
+
 use tuich::{buffer::Buffer, layout::Rect, widget::RefDraw};
 
-use crate::{app::AppContext, widget::PlayerWidget};
+use crate::{
+    app::AppContext,
+    cover::{clear_kitty, Cover, CoverProtocol},
+    player::PlayState,
+    widget::{PlayerWidget, VisualizerWidget},
+    Action,
+};
 
 #[derive(Debug)]
-pub struct PlayerView {}
+pub struct PlayerView {
+    cover: Option<Cover>,
+    /// Track which cover is loaded
+    cover_track: Option<PathBuf>,
+    /// Where the cover should be drawn with a graphics protocol
+    image_rect: Option<Rect>,
+    /// Where the cover was drawn with a graphics protocol
+    /// The image isn't sent to the terminal again until it changes
+    drawn_rect: Option<Rect>,
+    cover_changed: bool,
+}
 impl PlayerView {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            cover: None,
+            cover_track: None,
+            image_rect: None,
+            drawn_rect: None,
+            cover_changed: false,
+        }
+    }
+
+    /// Start loading cover art of the current track if it was changed
+    /// The old cover is hidden until the new one is loaded
+    pub fn update_cover(&mut self, ctx: &mut AppContext) {
+        let path = ctx.player.cur_track
+            .as_ref()
+            .map(|t| t.path.clone());
+        if path == self.cover_track {
+            return;
+        }
+
+        if let Some(path) = path.clone().filter(|_| ctx.config.cover.enabled) {
+            ctx.covers.load(path);
+        }
+        self.cover_track = path;
+        self.set_loaded_cover(ctx, None);
+    }
+    /// Show the cover loaded in the background
+    /// It's ignored if another track is playing already
+    pub fn set_cover(&mut self, ctx: &mut AppContext, track_path: PathBuf, cover: Option<Cover>) -> Action {
+        if self.cover_track.as_ref() != Some(&track_path) {
+            return Action::Nope;
+        }
+
+        self.set_loaded_cover(ctx, cover);
+        Action::Draw
+    }
+    fn set_loaded_cover(&mut self, ctx: &mut AppContext, cover: Option<Cover>) {
+        self.cover = cover;
+        self.cover_changed = true;
+
+        let art_url = self.cover
+            .as_ref()
+            .and_then(|c| c.path.as_ref())
+            .map(|p| format!("file://{}", p.display()));
+        ctx.player.set_art_url(art_url);
     }
 
-    pub fn draw(&self, ctx: &AppContext, buf: &mut Buffer, rect: Rect) -> Rect {
+    pub fn draw(&mut self, ctx: &AppContext, buf: &mut Buffer, rect: Rect) -> Rect {
         let max_width =
             if ctx.config.layout.player_max_width == 0 { rect.width }
             else { ctx.config.layout.player_max_width + 2 };
-        let player_rect = PlayerWidget::style_rect(rect, ctx.config.style.player)
+        let mut player_rect = PlayerWidget::style_rect(rect, ctx.config.style.player)
             .min_size((max_width, rect.height))
             .align_center(rect);
 
-        PlayerWidget {
+        self.image_rect = None;
+
+        // The visualizer goes under the player
+        let visualizer_height =
+            if ctx.config.visualizer.enabled {
+                ctx.config.visualizer.height.min(rect.height.saturating_sub(player_rect.height + 1))
+            } else { 0 };
+        let visualizer_space =
+            if visualizer_height > 0 { visualizer_height + 1 }
+            else { 0 };
+
+        // Place the cover above the player, cells are about twice as high as wide
+        let cover_width = ctx.config.cover.max_width.min(rect.width);
+        let cover_height = match &self.cover {
+            Some(_) => (cover_width / 2).min(rect.height.saturating_sub(player_rect.height + visualizer_space + 2)),
+            None => 0
+        };
+        let cover_space =
+            if cover_height > 0 { cover_height + 1 }
+            else { 0 };
+
+        let group_rect = rect
+            .min_size((rect.width, cover_space + player_rect.height + visualizer_space))
+            .align_center(rect);
+        player_rect = player_rect.with_y(group_rect.y + cover_space);
+
+        if let Some(cover) = self.cover.as_mut().filter(|_| cover_height > 0) {
+            let cover_rect = group_rect
+                .with_height(cover_height)
+                .min_size((cover_width, cover_height))
+                .align_center(group_rect.with_height(cover_height));
+
+            match ctx.config.cover.protocol.detect() {
+                CoverProtocol::Kitty |
+                CoverProtocol::Sixel => self.image_rect = Some(cover_rect),
+                _ => { cover.draw_half_blocks(buf, cover_rect); }
+            }
+        }
+
+        let player_rect = PlayerWidget {
             ctx,
             style: ctx.config.style.player
         }.draw(buf, player_rect);
 
+        if visualizer_height > 0 {
+            let color = match ctx.player.playstate() {
+                PlayState::Stopped => ctx.config.theme.player_stopped,
+                PlayState::Playing => ctx.config.theme.player_playing,
+                _ => ctx.config.theme.player_paused
+            };
+            let visualizer_rect = player_rect
+                .with_y(player_rect.bottom() + 1)
+                .with_height(visualizer_height);
+
+            VisualizerWidget {
+                visualizer: ctx.player.visualizer(),
+                playstate: ctx.player.playstate(),
+                style: ctx.config.visualizer.style,
+                color
+            }.draw(buf, visualizer_rect);
+        }
+
         player_rect
     }
+    /// Send the cover to the terminal after the rest of the app was drawn
+    /// Does nothing if the half blocks are used
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the output couldn't be written
+    pub fn draw_image<W: Write>(&mut self, ctx: &AppContext, visible: bool, out: &mut W) -> io::Result<()> {
+        let rect = self.image_rect.filter(|_| visible);
+        if rect == self.drawn_rect && !self.cover_changed {
+            return Ok(());
+        }
+
+        let protocol = ctx.config.cover.protocol.detect();
+        if protocol == CoverProtocol::Kitty {
+            clear_kitty(out)?;
+        }
+
+        self.drawn_rect = rect;
+        self.cover_changed = false;
+
+        if let (Some(rect), Some(cover)) = (rect, &mut self.cover) {
+            match protocol {
+                CoverProtocol::Kitty => cover.write_kitty(out, rect)?,
+                CoverProtocol::Sixel => cover.write_sixel(out, rect)?,
+                _ => ()
+            }
+        }
+
+        out.flush()
+    }
 }
@@ -1,16 +1,20 @@
+use std::path::PathBuf;
+
 use tuich::{
     buffer::Buffer,
-    event::Key,
+    event::{Key, Mouse, MouseKind},
     layout::{Rect, Stack},
     widget::{Draw, RefDraw}
 };
 
 use crate::{
-    app::{AppContext, View},
+    app::{AppContext, UpdateError, View},
     match_keys,
     player::{PlaybackError, PlaybackResult},
-    traits::ToReadable,
-    widget::{List, ListState, PlaylistWidget, TrackTable, TrackWidget, ViewWidget},
+    track::Id,
+    rating::{add_rating, toggle_loved},
+    traits::{ContainsPos, ToReadable},
+    widget::{List, ListEvent, ListState, PlaylistWidget, TrackTable, TrackWidget, ViewWidget},
     Action,
 };
 
@@ -19,12 +23,18 @@ use crate::{
 pub struct PlaylistsView {
     playlists_state: ListState,
     tracks_state: ListState,
+    /// Playlist which tracks are shown, the marks are dropped when it changes
+    tracks_playlist: Option<Id>,
+    /// Where the playlists and the tracks were drawn last time
+    panel_rects: Vec<Rect>,
 }
 impl PlaylistsView {
     pub fn new() -> Self {
         Self {
             playlists_state: ListState::new(),
             tracks_state: ListState::new(),
+            tracks_playlist: None,
+            panel_rects: vec![],
         }
     }
 
@@ -35,9 +45,9 @@ impl PlaylistsView {
         ctx.player.play_playlist(self.cur_playlist(), self.cur_track())
     }
 
-    pub fn handle_key(&mut self, ctx: &mut AppContext, key: Key) -> Result<Action, PlaybackError> {
+    pub fn handle_key(&mut self, ctx: &mut AppContext, key: Key) -> Result<Action, UpdateError> {
         match ctx.state.view {
-            View::Playlists => self.handle_playlists_key(ctx, key),
+            View::Playlists => Ok(self.handle_playlists_key(ctx, key)?),
             View::Tracks => self.handle_tracks_key(ctx, key),
             _ => Ok(Action::Nope)
         }
@@ -60,7 +70,7 @@ impl PlaylistsView {
 
         Ok(Action::Draw)
     }
-    fn handle_tracks_key(&mut self, ctx: &mut AppContext, key: Key) -> Result<Action, PlaybackError> {
+    fn handle_tracks_key(&mut self, ctx: &mut AppContext, key: Key) -> Result<Action, UpdateError> {
         match_keys! {
             ctx.config, key,
 
@@ -71,7 +81,16 @@ impl PlaylistsView {
                 ctx.player.queue.swap(ctx.player.cur_track_index.unwrap(), 0);
                 ctx.player.cur_track_index = Some(0);
             }
-            queue_add => ctx.player.queue_add_from_playlist(self.cur_playlist(), self.cur_track())?;
+            queue_add => ctx.player.queue_add_from_playlist(self.cur_playlist(), self.cur_track())?,
+            rating_up => add_rating(ctx, &self.cur_track_path(ctx)?, 1)?,
+            rating_down => add_rating(ctx, &self.cur_track_path(ctx)?, -1)?,
+            love_toggle => {
+                toggle_loved(ctx, &self.cur_track_path(ctx)?)?;
+            }
+            mark => {
+                self.tracks_state.toggle_mark(self.cur_track_path(ctx)?);
+                self.tracks_state.select_next(1);
+            };
 
             else {
                 return Ok(self.tracks_state.handle_key(ctx, key).into());
@@ -81,10 +100,41 @@ impl PlaylistsView {
         Ok(Action::Draw)
     }
 
+    /// Switch to the clicked panel and pass the mouse event to its list
+    pub fn handle_mouse(&mut self, ctx: &mut AppContext, mouse: Mouse) -> Result<Action, UpdateError> {
+        let views = [View::Playlists, View::Tracks];
+        let Some(view) = self.panel_rects
+            .iter()
+            .position(|r| r.contains_pos(mouse.pos))
+            .and_then(|i| views.get(i).copied()) else {
+            return Ok(Action::Nope);
+        };
+
+        let clicked = matches!(mouse.kind, MouseKind::Down(_));
+        if clicked {
+            ctx.state.view = view;
+        }
+        let switched = if clicked { Action::Draw } else { Action::Nope };
+
+        match view {
+            View::Playlists => match self.playlists_state.handle_mouse(mouse) {
+                ListEvent::Chosen(_) => self.play_playlist(ctx)?,
+                event => return Ok(Action::from(event) | switched)
+            },
+            _ => match self.tracks_state.handle_mouse(mouse) {
+                ListEvent::Chosen(_) => self.play_track(ctx)?,
+                event => return Ok(Action::from(event) | switched)
+            }
+        }
+
+        Ok(Action::Draw)
+    }
+
     pub fn draw(&mut self, ctx: &AppContext, buf: &mut Buffer, rect: Rect) -> Rect {
         let rects = Stack::row(&[1, 2])
             .gap(1)
             .calc(rect);
+        self.panel_rects = rects.to_vec();
 
         let playstate = ctx.player.playstate();
 
@@ -105,22 +155,32 @@ impl PlaylistsView {
                     state: list_state,
                     ctx,
                     playlist: &playlist,
-                    playing: ctx.player.is_playlist_index_current(&index)
+                    playing: ctx.player.is_playlist_current(&playlist.id)
                 }.draw(buf, rect)
             });
 
         // Draw tracks list
         if let Some(playlist) = ctx.player.playlists.get(self.cur_playlist()) {
             let playlist = playlist.borrow();
+
+            if self.tracks_playlist != Some(playlist.id) {
+                self.tracks_playlist = Some(playlist.id);
+                self.tracks_state.clear_marks();
+                self.tracks_state.invalidate();
+            }
             let tracks_count = playlist.tracks.len();
-            let desc = format!("{} tracks  {}", tracks_count, playlist.duration.to_readable());
+            let desc = ctx.config.format.tracks_desc.render(|name| match name {
+                "count" => Some(tracks_count.to_string()),
+                "duration" => Some(playlist.duration.to_readable()),
+                _ => None
+            });
 
             let tracks_rect = ViewWidget::new(&ctx.config, playstate, &playlist.name)
                 .with_desc(desc)
                 .with_active(self.tracks_state.active)
                 .draw(buf, rects[1]);
 
-            let table = TrackTable::new(tracks_count, tracks_rect);
+            let table = TrackTable::new(&ctx.config.layout.track_columns, tracks_count, tracks_rect);
             
             List::new(&mut self.tracks_state, &playlist.tracks)
                 .draw(buf, tracks_rect, |index, track, list_state, buf, rect| {
@@ -141,12 +201,64 @@ impl PlaylistsView {
         rect
     }
 
+    /// Recalculate search matches, the playlists or their tracks could be changed
+    pub fn invalidate(&mut self) {
+        self.playlists_state.invalidate();
+        self.tracks_state.invalidate();
+    }
+    /// Select the playlist, so its tracks are shown
+    pub fn open_playlist(&mut self, ctx: &AppContext, id: Id) {
+        let Some(index) = ctx.player.playlists.iter().position(|p| p.borrow().id == id) else {
+            return;
+        };
+
+        self.playlists_state.clear_query();
+        self.playlists_state.select_index(index);
+    }
+
     // Get
 
+    pub fn list_state_mut(&mut self, view: View) -> Option<&mut ListState> {
+        match view {
+            View::Playlists => Some(&mut self.playlists_state),
+            View::Tracks => Some(&mut self.tracks_state),
+            _ => None
+        }
+    }
     fn cur_playlist(&self) -> usize {
         self.playlists_state.current()
     }
     fn cur_track(&self) -> usize {
         self.tracks_state.current()
     }
+    /// Returns paths of the marked tracks, or of the selected one if nothing is marked
+    /// The marks are cleared
+    pub fn take_marked_paths(&mut self, ctx: &AppContext) -> Vec<PathBuf> {
+        let Some(playlist) = ctx.player.playlists.get(self.cur_playlist()) else {
+            return vec![];
+        };
+        let playlist = playlist.borrow();
+
+        // Keep the playlist order, tracks which were removed from it are dropped
+        let mut marked = self.tracks_state.take_marks();
+        let paths: Vec<PathBuf> = playlist.tracks
+            .iter()
+            .filter(|t| marked.remove(&t.path))
+            .map(|t| t.path.clone())
+            .collect();
+
+        if paths.is_empty() {
+            return playlist.tracks
+                .get(self.cur_track())
+                .map(|t| vec![t.path.clone()])
+                .unwrap_or_default();
+        }
+        paths
+    }
+    fn cur_track_path(&self, ctx: &AppContext) -> Result<PathBuf, PlaybackError> {
+        ctx.player.playlists
+            .get(self.cur_playlist())
+            .and_then(|p| p.borrow().tracks.get(self.cur_track()).map(|t| t.path.clone()))
+            .ok_or(PlaybackError::NoTrack)
+    }
 }
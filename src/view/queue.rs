@@ -1,16 +1,19 @@
+use std::path::PathBuf;
+
 use tuich::{
     buffer::Buffer,
-    event::Key,
+    event::{Key, Mouse},
     layout::Rect,
     widget::Draw,
 };
 
 use crate::{
-    app::AppContext,
+    app::{AppContext, UpdateError},
     match_keys,
     player::{PlaybackError, PlaybackResult},
+    rating::{add_rating, toggle_loved},
     traits::ToReadable,
-    widget::{List, ListState, TrackTable, TrackWidget, ViewWidget},
+    widget::{List, ListEvent, ListState, TrackTable, TrackWidget, ViewWidget},
     Action,
 };
 
@@ -26,7 +29,12 @@ impl QueueView {
         }
     }
 
-    pub fn handle_key(&mut self, ctx: &mut AppContext, key: Key) -> Result<Action, PlaybackError> {
+    /// Recalculate search matches, the queue could be reordered
+    pub fn invalidate(&mut self) {
+        self.list_state.invalidate();
+    }
+
+    pub fn handle_key(&mut self, ctx: &mut AppContext, key: Key) -> Result<Action, UpdateError> {
         match_keys! {
             ctx.config, key,
 
@@ -34,7 +42,12 @@ impl QueueView {
             queue_focus => self.focus(ctx),
             queue_move_up => self.move_up(ctx, 1)?,
             queue_move_down => self.move_down(ctx, 1)?,
-            queue_remove => ctx.player.queue_remove(self.cur_track())?;
+            queue_remove => ctx.player.queue_remove(self.cur_track())?,
+            rating_up => add_rating(ctx, &self.cur_track_path(ctx)?, 1)?,
+            rating_down => add_rating(ctx, &self.cur_track_path(ctx)?, -1)?,
+            love_toggle => {
+                toggle_loved(ctx, &self.cur_track_path(ctx)?)?;
+            };
 
             else {
                 return Ok(self.list_state.handle_key(ctx, key).into())
@@ -44,23 +57,32 @@ impl QueueView {
         Ok(Action::Draw)
     }
 
+    pub fn handle_mouse(&mut self, ctx: &mut AppContext, mouse: Mouse) -> Result<Action, UpdateError> {
+        match self.list_state.handle_mouse(mouse) {
+            ListEvent::Chosen(index) => ctx.player.play(index)?,
+            event => return Ok(event.into())
+        }
+
+        Ok(Action::Draw)
+    }
+
     fn focus(&mut self, ctx: &AppContext) {
         if let Some(index) = ctx.player.cur_track_index {
-            self.list_state.select(index);
+            self.list_state.select_index(index);
         }
     }
     fn move_up(&mut self, ctx: &mut AppContext, jump: usize) -> PlaybackResult {
         let cur = self.list_state.current();
         let new_index = cur.saturating_sub(jump);
         ctx.player.queue_move_to(cur, new_index)?;
-        self.list_state.select(new_index);
+        self.list_state.select_index(new_index);
         Ok(())
     }
     fn move_down(&mut self, ctx: &mut AppContext, jump: usize) -> PlaybackResult {
         let cur = self.list_state.current();
         let new_index = cur + jump;
         ctx.player.queue_move_to(cur, new_index)?;
-        self.list_state.select(new_index);
+        self.list_state.select_index(new_index);
         Ok(())
     }
 
@@ -69,18 +91,20 @@ impl QueueView {
         let tracks_count = ctx.player.queue.len();
         let queue_dur = ctx.player.queue_dur.to_readable();
 
-        let desc = if let Some(cur_index) = ctx.player.cur_track_index {
-            let elapsed = (ctx.player.elapsed + ctx.player.pos()).to_readable();
-            format!("{} / {} tracks  {} / {}", cur_index + 1, tracks_count, elapsed, queue_dur)
-        } else {
-            format!("{} tracks  {}", tracks_count, queue_dur)
-        };
+        let cur_index = ctx.player.cur_track_index;
+        let desc = ctx.config.format.queue_desc.render(|name| match name {
+            "index" => cur_index.map(|i| (i + 1).to_string()),
+            "count" => Some(tracks_count.to_string()),
+            "elapsed" => cur_index.map(|_| (ctx.player.elapsed + ctx.player.pos()).to_readable()),
+            "duration" => Some(queue_dur.clone()),
+            _ => None
+        });
 
         let content_rect = ViewWidget::new(&ctx.config, playstate, "Queue")
             .with_desc(desc)
             .draw(buf, rect);
 
-        let table = TrackTable::new(tracks_count, content_rect);
+        let table = TrackTable::new(&ctx.config.layout.track_columns, tracks_count, content_rect);
 
         List::new(&mut self.list_state, &ctx.player.queue)
             .draw(buf, content_rect, |index, track, list_state, buf, rect| {
@@ -98,7 +122,16 @@ impl QueueView {
 
     // Get
 
+    pub fn list_state_mut(&mut self) -> &mut ListState {
+        &mut self.list_state
+    }
     fn cur_track(&self) -> usize {
         self.list_state.current()
     }
+    fn cur_track_path(&self, ctx: &AppContext) -> Result<PathBuf, PlaybackError> {
+        ctx.player.queue
+            .get(self.cur_track())
+            .map(|t| t.path.clone())
+            .ok_or(PlaybackError::NoTrack)
+    }
 }
@@ -0,0 +1,138 @@
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use tuich::{
+    buffer::Buffer,
+    layout::{Align, Clip, Rect, Stack},
+    style::Color,
+    text::Text,
+    widget::Draw,
+};
+
+use crate::{app::AppContext, traits::ToReadable, widget::ViewWidget};
+
+/// Play statistics view
+#[derive(Debug)]
+pub struct StatsView {
+    /// Paths of the played tracks from the most played one
+    tracks: Vec<(PathBuf, u32)>,
+    /// Played artists from the most played one
+    artists: Vec<(String, u32)>,
+    total_plays: u32,
+    /// Length of the history, play time of its last item and number of the library tracks
+    /// when the statistics were collected
+    collected: Option<(usize, Option<u64>, usize)>,
+}
+impl StatsView {
+    pub fn new() -> Self {
+        Self {
+            tracks: vec![],
+            artists: vec![],
+            total_plays: 0,
+            collected: None,
+        }
+    }
+
+    /// Collect the most played tracks and artists
+    fn collect(&mut self, ctx: &AppContext) {
+        let mut tracks = vec![];
+        let mut artists: HashMap<&str, u32> = HashMap::new();
+        let mut total_plays = 0;
+        for (path, stats) in ctx.stats.tracks() {
+            if stats.plays == 0 { continue; }
+
+            if let Some(artist) = ctx.cache.get(path).and_then(|d| d.artist.as_deref()) {
+                *artists.entry(artist).or_default() += stats.plays;
+            }
+
+            total_plays += stats.plays;
+            tracks.push((path.clone(), stats.plays));
+        }
+        tracks.sort_by(|a, b| b.1.cmp(&a.1));
+        let mut artists: Vec<(String, u32)> = artists
+            .into_iter()
+            .map(|(artist, plays)| (artist.to_string(), plays))
+            .collect();
+        artists.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        self.tracks = tracks;
+        self.artists = artists;
+        self.total_plays = total_plays;
+    }
+
+    pub fn draw(&mut self, ctx: &AppContext, buf: &mut Buffer, rect: Rect) -> Rect {
+        let rects = Stack::row(&[2, 2, 1])
+            .gap(1)
+            .calc(rect);
+
+        let playstate = ctx.player.playstate();
+
+        // Collect the statistics again only if something was played or the library was changed
+        let history = ctx.stats.history();
+        let collected = (history.len(), history.last().map(|p| p.time), ctx.library.tracks_count);
+        if self.collected != Some(collected) {
+            self.collected = Some(collected);
+            self.collect(ctx);
+        }
+
+        // Draw top tracks
+        let tracks_rect = ViewWidget::new(&ctx.config, playstate, "Top tracks")
+            .with_desc(format!("{} plays", self.total_plays))
+            .draw(buf, rects[0]);
+        let lines = self.tracks
+            .iter()
+            .map(|(path, plays)| {
+                let title = ctx.cache
+                    .get(path)
+                    .and_then(|d| d.title.as_deref())
+                    .or(path.file_name().and_then(|f| f.to_str()))
+                    .unwrap_or_default();
+                (title, plays.to_string())
+            });
+        draw_lines(ctx, buf, tracks_rect, lines);
+
+        // Draw top artists
+        let artists_rect = ViewWidget::new(&ctx.config, playstate, "Top artists")
+            .with_desc(self.artists.len().to_string())
+            .draw(buf, rects[1]);
+        let lines = self.artists
+            .iter()
+            .map(|(artist, plays)| (artist.as_str(), plays.to_string()));
+        draw_lines(ctx, buf, artists_rect, lines);
+
+        // Draw listening time per day, the latest day first
+        let total = ctx.stats
+            .days()
+            .fold(Duration::default(), |acc, (_, dur)| acc + dur);
+        let days_rect = ViewWidget::new(&ctx.config, playstate, "Listening time")
+            .with_desc(total.to_readable())
+            .draw(buf, rects[2]);
+        let lines = ctx.stats
+            .days()
+            .rev()
+            .map(|(day, dur)| (day.as_str(), dur.to_readable()));
+        draw_lines(ctx, buf, days_rect, lines);
+
+        rect
+    }
+}
+
+/// Draw lines with a name on the left and a value on the right
+fn draw_lines<'a, I>(ctx: &AppContext, buf: &mut Buffer, rect: Rect, lines: I)
+where
+    I: Iterator<Item = (&'a str, String)>
+{
+    for (index, (name, value)) in lines.take(rect.height as usize).enumerate() {
+        let line_rect = rect
+            .add_y(index as u16)
+            .with_height(1)
+            .margin((1, 0));
+
+        let value_width = Text::new(value, Color::Gray)
+            .align(Align::End)
+            .draw(buf, line_rect)
+            .width;
+        Text::new(name, ctx.config.theme.track)
+            .clip(Clip::Ellipsis)
+            .draw(buf, line_rect.margin_right(value_width + 2));
+    }
+}
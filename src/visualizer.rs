@@ -0,0 +1,289 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    f32::consts::PI,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rodio::{source::SeekError, Sample, Source};
+use serde::{Deserialize, Serialize};
+
+// Consts
+/// Number of the samples analyzed at once, must be a power of two
+const FFT_SIZE: usize = 2048;
+/// Number of the samples collected by the tap before they are passed to the visualizer
+const CHUNK_SIZE: usize = 512;
+const MIN_FREQ: f32 = 40.0;
+const MAX_FREQ: f32 = 16_000.0;
+/// Level in decibels shown as an empty bar
+const MIN_DB: f32 = -60.0;
+/// Part of the bar height kept on the next frame, so the bars fall smoothly
+const FALLOFF: f32 = 0.8;
+
+/// Visualizer style
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VisualizerStyle {
+    /// Spectrum bars from the low to the high frequencies
+    #[default]
+    Bars,
+    /// Waveform drawn with braille characters
+    Oscilloscope,
+}
+
+/// The latest samples of the playing audio mixed down to mono
+#[derive(Debug, Default)]
+struct Samples {
+    data: VecDeque<f32>,
+    sample_rate: u32,
+}
+
+/// Source passing the samples through to the sink and copying them to the visualizer
+pub struct Tap<I> {
+    input: I,
+    samples: Arc<Mutex<Samples>>,
+    pending: Vec<f32>,
+    /// Sum of the samples of the current frame
+    frame: f32,
+    channel: u16,
+    /// Whether the collected samples are from before a seek and should be dropped on the next flush
+    stale: bool,
+}
+impl<I: Source> Tap<I>
+where
+    I::Item: Sample
+{
+    fn flush(&mut self) {
+        // Never block the audio thread, the samples are passed on the next chunk
+        let Ok(mut samples) = self.samples.try_lock() else {
+            let excess = self.pending.len().saturating_sub(FFT_SIZE);
+            self.pending.drain(..excess);
+            return;
+        };
+
+        if self.stale {
+            samples.data.clear();
+            self.stale = false;
+        }
+        samples.sample_rate = self.input.sample_rate();
+        samples.data.extend(self.pending.drain(..));
+        let excess = samples.data.len().saturating_sub(FFT_SIZE);
+        samples.data.drain(..excess);
+    }
+}
+impl<I: Source> Iterator for Tap<I>
+where
+    I::Item: Sample
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.input.next()?;
+        let channels = self.input.channels().max(1);
+
+        self.frame += sample.to_f32();
+        self.channel += 1;
+        if self.channel >= channels {
+            self.pending.push(self.frame / channels as f32);
+            self.frame = 0.0;
+            self.channel = 0;
+
+            if self.pending.len() >= CHUNK_SIZE {
+                self.flush();
+            }
+        }
+
+        Some(sample)
+    }
+}
+impl<I: Source> Source for Tap<I>
+where
+    I::Item: Sample
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.pending.clear();
+        self.frame = 0.0;
+        self.channel = 0;
+        // The audio thread can't wait for the UI, clear the samples on the next flush if they are busy
+        match self.samples.try_lock() {
+            Ok(mut samples) => samples.data.clear(),
+            Err(_) => self.stale = true
+        }
+
+        self.input.try_seek(pos)
+    }
+}
+
+/// Visualizer
+/// Analyzes the samples going to the audio output
+#[derive(Debug, Default)]
+pub struct Visualizer {
+    samples: Arc<Mutex<Samples>>,
+    /// Bars of the previous frame
+    bars: RefCell<Vec<f32>>,
+}
+impl Visualizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wrap the source, so its samples are visualized
+    pub fn tap<I: Source>(&self, input: I) -> Tap<I>
+    where
+        I::Item: Sample
+    {
+        self.clear();
+
+        Tap {
+            input,
+            samples: Arc::clone(&self.samples),
+            pending: Vec::with_capacity(CHUNK_SIZE),
+            frame: 0.0,
+            channel: 0,
+            stale: false,
+        }
+    }
+    /// Forget the collected samples
+    pub fn clear(&self) {
+        if let Ok(mut samples) = self.samples.lock() {
+            samples.data.clear();
+        }
+    }
+
+    /// Returns loudness of the frequency bands from `0.0` to `1.0`, the lowest frequencies first
+    /// The bars fall to zero if the audio isn't `active`
+    pub fn spectrum(&self, bands: usize, active: bool) -> Vec<f32> {
+        let levels = active
+            .then(|| self.analyze(bands))
+            .flatten()
+            .unwrap_or_else(|| vec![0.0; bands]);
+
+        let mut bars = self.bars.borrow_mut();
+        if bars.len() != bands {
+            *bars = vec![0.0; bands];
+        }
+        for (bar, level) in bars.iter_mut().zip(levels) {
+            *bar = level.max(*bar * FALLOFF);
+        }
+
+        bars.clone()
+    }
+    /// Returns the latest samples from `-1.0` to `1.0`
+    pub fn waveform(&self, len: usize, active: bool) -> Vec<f32> {
+        let mut wave = vec![0.0; len];
+        if !active {
+            return wave;
+        }
+        let Ok(samples) = self.samples.lock() else {
+            return wave;
+        };
+
+        let skip = samples.data.len().saturating_sub(len);
+        let offset = len.saturating_sub(samples.data.len());
+        for (value, sample) in wave[offset..].iter_mut().zip(samples.data.iter().skip(skip)) {
+            *value = sample.clamp(-1.0, 1.0);
+        }
+        wave
+    }
+
+    /// Returns levels of the log-spaced frequency bands
+    /// Returns `None` if there are not enough samples yet
+    fn analyze(&self, bands: usize) -> Option<Vec<f32>> {
+        let (mut re, sample_rate) = {
+            let samples = self.samples.lock().ok()?;
+            if samples.data.len() < FFT_SIZE || samples.sample_rate == 0 {
+                return None;
+            }
+            (samples.data.iter().copied().collect::<Vec<f32>>(), samples.sample_rate as f32)
+        };
+        let mut im = vec![0.0; FFT_SIZE];
+
+        // Hann window, so the edges of the chunk don't leak into other frequencies
+        for (index, value) in re.iter_mut().enumerate() {
+            *value *= 0.5 - 0.5 * (2.0 * PI * index as f32 / (FFT_SIZE - 1) as f32).cos();
+        }
+        fft(&mut re, &mut im);
+
+        let bins = FFT_SIZE / 2;
+        let max_freq = MAX_FREQ.min(sample_rate / 2.0);
+        let bin_of = |freq: f32| ((freq * FFT_SIZE as f32 / sample_rate) as usize).clamp(1, bins - 1);
+
+        let levels = (0..bands)
+            .map(|band| {
+                let freq_at = |b: usize| MIN_FREQ * (max_freq / MIN_FREQ).powf(b as f32 / bands as f32);
+                let start = bin_of(freq_at(band));
+                let end = bin_of(freq_at(band + 1)).max(start);
+
+                let magnitude = (start..=end)
+                    .map(|bin| (re[bin] * re[bin] + im[bin] * im[bin]).sqrt())
+                    .fold(0.0, f32::max);
+                // The window halves the amplitude
+                let db = 20.0 * (magnitude * 4.0 / FFT_SIZE as f32).max(f32::MIN_POSITIVE).log10();
+
+                ((db - MIN_DB) / -MIN_DB).clamp(0.0, 1.0)
+            })
+            .collect();
+
+        Some(levels)
+    }
+}
+
+/// In-place radix-2 fast Fourier transform
+/// Length of the slices must be a power of two
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let len = re.len();
+
+    // Bit-reversal permutation
+    let mut j = 0;
+    for i in 1..len {
+        let mut bit = len >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j ^= bit;
+
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut size = 2;
+    while size <= len {
+        let angle = -2.0 * PI / size as f32;
+        let (step_re, step_im) = (angle.cos(), angle.sin());
+        let half = size / 2;
+
+        for start in (0..len).step_by(size) {
+            let (mut w_re, mut w_im) = (1.0, 0.0);
+            for k in start..start + half {
+                let (odd_re, odd_im) = (
+                    re[k + half] * w_re - im[k + half] * w_im,
+                    re[k + half] * w_im + im[k + half] * w_re,
+                );
+
+                re[k + half] = re[k] - odd_re;
+                im[k + half] = im[k] - odd_im;
+                re[k] += odd_re;
+                im[k] += odd_im;
+
+                (w_re, w_im) = (w_re * step_re - w_im * step_im, w_re * step_im + w_im * step_re);
+            }
+        }
+        size <<= 1;
+    }
+}
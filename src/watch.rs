@@ -0,0 +1,154 @@
+use std::{
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{
+    app::AppContext,
+    config::Config,
+    playlist::{Playlist, PlaylistError},
+    track::TrackData,
+    traits::Expand,
+    UpdateKind,
+};
+
+// Consts
+/// How long to wait for other changes before sending them to the main loop
+const DEBOUNCE_INTERVAL: u64 = 300;
+
+/// Watch the playlists and the music directories from the config
+/// and the config file itself if enabled
+/// Changed paths are sent to the main loop as [UpdateKind::Fs]
+///
+/// The watcher stops when the returned value is dropped
+///
+/// # Errors
+///
+/// Returns an error if the watcher couldn't be created (see [notify::recommended_watcher])
+pub fn watch_config_paths(
+    config: &Config,
+    config_path: &Path,
+    sender: mpsc::Sender<UpdateKind>,
+) -> notify::Result<RecommendedWatcher> {
+    let (paths_sender, paths_receiver) = mpsc::channel::<PathBuf>();
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if event.kind.is_access() || event.kind.is_other() {
+            return;
+        }
+
+        for path in event.paths {
+            let _ = paths_sender.send(path);
+        }
+    })?;
+
+    // Watch parent directories of the files, because editors often
+    // replace files instead of writing into them
+    if config.watch {
+        for path in &config.playlists {
+            let path = path.expand()
+                .unwrap_or(path.clone());
+            let dir =
+                if path.is_dir() { path.as_path() }
+                else { path.parent().unwrap_or(&path) };
+
+            let _ = watcher.watch(dir, RecursiveMode::NonRecursive);
+        }
+        for path in &config.music_dirs {
+            let path = path.expand()
+                .unwrap_or(path.clone());
+
+            let _ = watcher.watch(&path, RecursiveMode::Recursive);
+        }
+    }
+    if config.watch_config {
+        if let Some(dir) = config_path.parent() {
+            let _ = watcher.watch(dir, RecursiveMode::NonRecursive);
+        }
+    }
+
+    // Collect changes until they settle down and send them at once
+    thread::spawn(move || {
+        while let Ok(path) = paths_receiver.recv() {
+            let mut paths = vec![path];
+
+            while let Ok(path) = paths_receiver.recv_timeout(Duration::from_millis(DEBOUNCE_INTERVAL)) {
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+
+            if sender.send(UpdateKind::Fs(paths)).is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(watcher)
+}
+
+/// Reload a changed track
+/// Returns whether the library should be rebuilt
+pub fn reload_track(ctx: &mut AppContext, path: &Path) -> bool {
+    // The track was removed
+    if !path.exists() {
+        ctx.player.remove_track(path);
+        return ctx.cache.tracks_data.remove(path).is_some();
+    }
+
+    let in_music_dirs = ctx.config.music_dirs
+        .iter()
+        .any(|d| path.starts_with(d.expand().unwrap_or(d.clone())));
+
+    // Nobody knows about this track
+    if !in_music_dirs && !ctx.cache.has(path) {
+        return false;
+    }
+
+    // The file may be not fully written yet, it will be read on the next change
+    let Ok(data) = TrackData::from_path(path) else {
+        return false;
+    };
+    let data = Rc::new(data);
+
+    ctx.cache.insert(path, Rc::clone(&data));
+    ctx.player.refresh_track(path, data);
+    true
+}
+
+/// Reload, add or remove a changed playlist
+///
+/// # Errors
+///
+/// See [Playlist::from_path]
+pub fn reload_playlist(ctx: &mut AppContext, path: &Path) -> Result<(), PlaylistError> {
+    let index = ctx.player.playlist_find_by_path(path);
+
+    // The playlist was removed
+    if !path.exists() {
+        if let Some(index) = index {
+            let _ = ctx.player.playlist_remove(index);
+        }
+        return Ok(());
+    }
+    if path.is_dir() {
+        return Ok(());
+    }
+
+    let playlist = Playlist::from_path(&mut ctx.cache, &ctx.stats, path)?;
+
+    match index {
+        Some(index) => { let _ = ctx.player.playlist_set(index, playlist); }
+        None => ctx.player.playlist_add(playlist)
+    }
+
+    Ok(())
+}
@@ -0,0 +1,191 @@
+use std::{
+    fs,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicUsize, Ordering}, mpsc, Arc},
+    thread,
+    time::UNIX_EPOCH,
+};
+
+use rodio::{Decoder, Source};
+
+use crate::{config::cache_dir, traits::StableHash, UpdateKind};
+
+// Consts
+/// Number of the points the envelope is downsampled to
+const WAVEFORM_POINTS: usize = 1000;
+/// Number of the frames reduced to a single peak while decoding
+const BLOCK_FRAMES: usize = 1024;
+
+/// Amplitude envelope of a track
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waveform {
+    /// Peaks from `0.0` to `1.0`, normalized to the loudest one
+    pub peaks: Vec<f32>,
+}
+impl Waveform {
+    /// Decode the whole track and compute its envelope
+    /// Decoding stops as soon as `cancelled` returns `true`
+    /// Returns `None` if the track couldn't be decoded or it was cancelled
+    pub fn compute(path: &Path, cancelled: impl Fn() -> bool) -> Option<Self> {
+        let file = fs::File::open(path).ok()?;
+        let decoder = Decoder::new(BufReader::new(file)).ok()?;
+        let block_len = BLOCK_FRAMES * decoder.channels().max(1) as usize;
+
+        let mut blocks = vec![];
+        let mut peak = 0_i32;
+        for (index, sample) in decoder.enumerate() {
+            peak = peak.max((sample as i32).abs());
+            if (index + 1) % block_len == 0 {
+                blocks.push(peak);
+                peak = 0;
+
+                if cancelled() {
+                    return None;
+                }
+            }
+        }
+        if peak > 0 {
+            blocks.push(peak);
+        }
+        if blocks.is_empty() {
+            return None;
+        }
+
+        let points = WAVEFORM_POINTS.min(blocks.len());
+        let loudest = blocks.iter().copied().max().unwrap_or_default().max(1) as f32;
+        let peaks = (0..points)
+            .map(|point| {
+                let start = point * blocks.len() / points;
+                let end = ((point + 1) * blocks.len() / points).max(start + 1);
+                blocks[start..end].iter().copied().max().unwrap_or_default() as f32 / loudest
+            })
+            .collect();
+
+        Some(Self { peaks })
+    }
+
+    /// Load the envelope computed earlier
+    pub fn load_cached(path: &Path) -> Option<Self> {
+        let data = fs::read(cache_path(path)?).ok()?;
+        let peaks = data
+            .into_iter()
+            .map(|p| p as f32 / u8::MAX as f32)
+            .collect();
+
+        Some(Self { peaks }).filter(|w| !w.peaks.is_empty())
+    }
+    /// Save the envelope to the cache dir, a byte per peak
+    /// Does nothing if it couldn't be saved
+    fn save(&self, path: &Path) {
+        let Some(cache_path) = cache_path(path) else {
+            return;
+        };
+
+        let data: Vec<u8> = self.peaks
+            .iter()
+            .map(|p| (p.clamp(0.0, 1.0) * u8::MAX as f32).round() as u8)
+            .collect();
+        if let Some(dir) = cache_path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(cache_path, data);
+    }
+
+    /// Returns the loudest peak between the positions from `0.0` to `1.0`
+    pub fn peak_between(&self, start: f32, end: f32) -> f32 {
+        let len = self.peaks.len();
+        let start = ((start * len as f32) as usize).min(len.saturating_sub(1));
+        let end = ((end * len as f32).ceil() as usize).clamp(start + 1, len.max(start + 1));
+
+        self.peaks
+            .get(start..end)
+            .and_then(|p| p.iter().copied().reduce(f32::max))
+            .unwrap_or_default()
+    }
+}
+
+/// Returns path of the cached envelope
+/// The modification time is a part of the name, so changed files are decoded again
+fn cache_path(path: &Path) -> Option<PathBuf> {
+    let modified = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+
+    let mut key = path.as_os_str().as_encoded_bytes().to_vec();
+    key.extend(modified.as_nanos().to_le_bytes());
+
+    let dir = cache_dir().ok()?.join("waveforms");
+    Some(dir.join(format!("{:016x}", key.stable_hash())))
+}
+
+/// Waveform loader
+/// Keeps the envelope of the current track, computing it in the background
+#[derive(Debug)]
+pub struct WaveformLoader {
+    sender: mpsc::Sender<(usize, PathBuf)>,
+    /// Number of the latest request, the older ones are cancelled
+    latest: Arc<AtomicUsize>,
+    /// Track which envelope is loaded or being computed
+    path: Option<PathBuf>,
+    waveform: Option<Waveform>,
+}
+impl WaveformLoader {
+    pub fn new(notifier: mpsc::Sender<UpdateKind>) -> Self {
+        let (sender, receiver) = mpsc::channel::<(usize, PathBuf)>();
+        let latest = Arc::new(AtomicUsize::new(0));
+        let worker_latest = Arc::clone(&latest);
+
+        thread::spawn(move || {
+            while let Ok(mut request) = receiver.recv() {
+                // Only the latest track matters if they were skipped quickly
+                while let Ok(next) = receiver.try_recv() {
+                    request = next;
+                }
+
+                let (number, path) = request;
+                let cancelled = || worker_latest.load(Ordering::Relaxed) != number;
+                if let Some(waveform) = Waveform::compute(&path, cancelled) {
+                    waveform.save(&path);
+                    if notifier.send(UpdateKind::Waveform(path, waveform)).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Self { sender, latest, path: None, waveform: None }
+    }
+
+    /// Load the envelope of the track if it was changed
+    /// Envelopes which aren't cached yet are computed in the background
+    pub fn update(&mut self, path: Option<PathBuf>) {
+        if path == self.path {
+            return;
+        }
+
+        // Stop computing the envelope of the previous track
+        let number = self.latest.fetch_add(1, Ordering::Relaxed) + 1;
+
+        self.waveform = path.as_deref().and_then(Waveform::load_cached);
+        if let (Some(path), None) = (&path, &self.waveform) {
+            let _ = self.sender.send((number, path.clone()));
+        }
+        self.path = path;
+    }
+    /// Set the envelope computed in the background
+    /// It's ignored if another track is playing already
+    pub fn set(&mut self, path: PathBuf, waveform: Waveform) {
+        if self.path.as_ref() == Some(&path) {
+            self.waveform = Some(waveform);
+        }
+    }
+
+    // Get
+
+    pub fn get(&self) -> Option<&Waveform> {
+        self.waveform.as_ref()
+    }
+}
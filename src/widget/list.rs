@@ -1,15 +1,21 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashSet, path::{Path, PathBuf}, time::{Duration, Instant}};
 
 use tuich::{
     buffer::Buffer,
-    event::Key,
-    layout::{Clip, Rect},
+    event::{Key, Mouse, MouseButton, MouseKind},
+    layout::{Clip, Point, Rect},
     style::Style,
     text::Text,
     widget::{Clear, Draw, RefDraw},
 };
 
-use crate::{app::AppContext, match_keys};
+use crate::{app::AppContext, match_keys, search::Searchable, traits::ContainsPos};
+
+// Consts
+/// Max time between the clicks of a double click
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+/// Number of the rows scrolled by a mouse wheel step
+const MOUSE_SCROLL: u16 = 3;
 
 /// List event
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -28,12 +34,35 @@ pub struct ListState {
     scrolloff: u16,
     pub active: bool,
 
+    /// Where the items were drawn last time
+    rect: Rect,
     /// Visible height of the list
     height: u16,
     /// Visible height of a list item
     item_height: u16,
-    /// Items count
+    /// Visible items count
     count: usize,
+    /// Items count before filtering
+    total: usize,
+
+    /// Search query
+    query: String,
+    /// Whether only items matching the query should be visible
+    filtered: bool,
+    /// Indexes of the items matching the query
+    matches: Vec<usize>,
+    /// Indexes of the visible items, `None` if the list is not filtered
+    visible: Option<Vec<usize>>,
+    /// Whether the matches should be recalculated on the next draw
+    dirty: bool,
+    /// Whether to select the nearest match on the next draw
+    jump: bool,
+
+    /// Paths of the marked tracks
+    /// Tracks stay marked when they are reordered or reloaded
+    marked: HashSet<PathBuf>,
+    /// Position of the last clicked item and when it was clicked
+    last_click: Option<(usize, Instant)>,
 }
 impl ListState {
     pub fn new() -> Self {
@@ -43,9 +72,21 @@ impl ListState {
             scrolloff: 2,
             active: true,
 
+            rect: Rect::default(),
             height: 0,
             item_height: 1,
             count: 0,
+            total: 0,
+
+            query: String::new(),
+            filtered: false,
+            matches: vec![],
+            visible: None,
+            dirty: false,
+            jump: false,
+
+            marked: HashSet::new(),
+            last_click: None,
         }
     }
 
@@ -66,12 +107,45 @@ impl ListState {
             select_next_item_super_fast => self.select_next(ctx.config.super_fast_jump),
             select_prev_item_super_fast => self.select_prev(ctx.config.super_fast_jump),
             select_first_item => self.select_first(),
-            select_last_item => self.select_last();
+            select_last_item => self.select_last(),
+            search_next => self.select_next_match(),
+            search_prev => self.select_prev_match(),
+            escape => self.escape();
 
             else { ListEvent::Nope }
         }
     }
+    /// Select the clicked item, choose it on a double click, or scroll with the wheel
+    pub fn handle_mouse(&mut self, mouse: Mouse) -> ListEvent {
+        if !self.rect.contains_pos(mouse.pos) {
+            return ListEvent::Nope;
+        }
+
+        match mouse.kind {
+            MouseKind::ScrollUp => self.scroll_up(MOUSE_SCROLL),
+            MouseKind::ScrollDown => self.scroll_down(MOUSE_SCROLL),
+            MouseKind::Down(MouseButton::Left) => {
+                let Some(pos) = self.pos_at(mouse.pos) else {
+                    return ListEvent::Nope;
+                };
+
+                let now = Instant::now();
+                let is_double = self.last_click
+                    .is_some_and(|(p, time)| p == pos && now - time < DOUBLE_CLICK_TIME);
+                self.last_click =
+                    if is_double { None }
+                    else { Some((pos, now)) };
+
+                // Don't scroll, so the second click of a double click hits the same item
+                self.current = pos;
+                if is_double { ListEvent::Chosen(self.current()) }
+                else { ListEvent::Selected(self.current()) }
+            }
+            _ => ListEvent::Nope
+        }
+    }
     
+    /// Select an item by its position in the visible part of the list
     pub fn select(&mut self, index: usize) -> ListEvent {
         let index = index.clamp(0, self.count.saturating_sub(1));
         self.current = index;
@@ -88,7 +162,16 @@ impl ListState {
             self.scroll_up(scroll_top.saturating_sub(cur));
         }
 
-        ListEvent::Selected(index)
+        ListEvent::Selected(self.current())
+    }
+    /// Select an item by its index in the list of items
+    /// If the item is filtered out, the nearest visible item will be selected
+    pub fn select_index(&mut self, index: usize) -> ListEvent {
+        let pos = match &self.visible {
+            Some(visible) => visible.partition_point(|i| *i < index),
+            None => index
+        };
+        self.select(pos)
     }
     pub fn select_next(&mut self, jump: usize) -> ListEvent {
         self.select(self.current + jump)
@@ -103,6 +186,139 @@ impl ListState {
         self.select(self.count)
     }
 
+    /// Clear the search query, or the marks if there is no query
+    fn escape(&mut self) -> ListEvent {
+        if self.query.is_empty() && self.visible.is_none() && !self.marked.is_empty() {
+            self.marked.clear();
+            return ListEvent::Selected(self.current());
+        }
+        self.clear_query()
+    }
+
+    // Marks
+    /// Mark or unmark a track
+    pub fn toggle_mark(&mut self, path: PathBuf) {
+        if !self.marked.remove(&path) {
+            self.marked.insert(path);
+        }
+    }
+    pub fn clear_marks(&mut self) {
+        self.marked.clear();
+    }
+    /// Returns paths of the marked tracks and clears the marks
+    pub fn take_marks(&mut self) -> HashSet<PathBuf> {
+        std::mem::take(&mut self.marked)
+    }
+
+    // Search
+    /// Set a search query
+    /// Matches will be recalculated and the nearest one selected on the next draw
+    pub fn set_query<S: ToString>(&mut self, query: S, filtered: bool) {
+        self.query = query.to_string();
+        self.filtered = filtered && !self.query.is_empty();
+        self.dirty = true;
+        self.jump = true;
+    }
+    /// Clear the search query and show all the items
+    pub fn clear_query(&mut self) -> ListEvent {
+        if self.query.is_empty() && self.visible.is_none() {
+            return ListEvent::Nope;
+        }
+
+        let cur = self.current();
+
+        self.query.clear();
+        self.filtered = false;
+        self.matches.clear();
+        self.visible = None;
+        self.count = self.total;
+        self.dirty = true;
+
+        // Keep the same item selected
+        self.select_index(cur)
+    }
+    /// Select the next item matching the query
+    pub fn select_next_match(&mut self) -> ListEvent {
+        let cur = self.current();
+        let next = self.matches
+            .iter()
+            .find(|i| **i > cur)
+            .or(self.matches.first());
+
+        match next {
+            Some(&index) => self.select_index(index),
+            None => ListEvent::Nope
+        }
+    }
+    /// Select the previous item matching the query
+    pub fn select_prev_match(&mut self) -> ListEvent {
+        let cur = self.current();
+        let prev = self.matches
+            .iter()
+            .rev()
+            .find(|i| **i < cur)
+            .or(self.matches.last());
+
+        match prev {
+            Some(&index) => self.select_index(index),
+            None => ListEvent::Nope
+        }
+    }
+    /// Recalculate the matches on the next draw
+    /// Should be called when the items are replaced or reordered
+    pub fn invalidate(&mut self) {
+        self.dirty = true;
+    }
+    /// Recalculate the matches and the visible items
+    /// Does nothing unless the query was changed, the list was invalidated or the items count was changed
+    fn update_matches<T: Searchable>(&mut self, items: &[T]) {
+        if !self.dirty && self.total == items.len() {
+            return;
+        }
+
+        let cur = self.current();
+
+        self.total = items.len();
+        self.matches =
+            if self.query.is_empty() { vec![] }
+            else {
+                items
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, item)| item.matches(&self.query))
+                    .map(|(index, _)| index)
+                    .collect()
+            };
+
+        let visible =
+            if self.filtered { Some(self.matches.clone()) }
+            else { None };
+        let visible_changed = visible != self.visible;
+
+        self.visible = visible;
+        self.count = self.visible.as_ref().map_or(self.total, |v| v.len());
+        self.dirty = false;
+
+        if self.jump {
+            self.jump = false;
+
+            // Select the nearest match
+            let nearest = self.matches
+                .iter()
+                .find(|i| **i >= cur)
+                .or(self.matches.first());
+
+            if let Some(&index) = nearest {
+                self.select_index(index);
+                return;
+            }
+        }
+
+        if visible_changed {
+            self.select_index(cur);
+        }
+    }
+
     pub fn set_scroll(&mut self, scroll: u16) -> ListEvent {
         let scroll = scroll.clamp(0, self.scroll_height());
         self.scroll = scroll;
@@ -115,10 +331,40 @@ impl ListState {
         self.set_scroll(self.scroll + speed)
     }
 
-    pub fn current(&self) -> usize { self.current }
+    /// Returns index of the selected item
+    /// If the list is filtered and nothing matches, returns an out of bounds index
+    pub fn current(&self) -> usize {
+        self.index_at(self.current)
+    }
+    /// Returns index of the item at some position in the visible part of the list
+    pub fn index_at(&self, pos: usize) -> usize {
+        match &self.visible {
+            Some(visible) => visible.get(pos).copied().unwrap_or(self.total),
+            None => pos
+        }
+    }
+    /// Returns position of the item drawn at the point in the visible part of the list
+    pub fn pos_at(&self, point: Point) -> Option<usize> {
+        if !self.rect.contains_pos(point) {
+            return None;
+        }
+
+        let row = (point.y - self.rect.y) / self.item_height.max(1);
+        Some((self.scroll + row) as usize).filter(|p| *p < self.count)
+    }
+    pub fn contains_pos(&self, point: Point) -> bool {
+        self.rect.contains_pos(point)
+    }
     pub fn count(&self) -> usize { self.count }
     pub fn height(&self) -> u16 { self.height }
     pub fn scroll(&self) -> u16 { self.scroll }
+    pub fn query(&self) -> &str { &self.query }
+    pub fn is_match(&self, index: usize) -> bool {
+        self.matches.binary_search(&index).is_ok()
+    }
+    pub fn is_marked(&self, path: &Path) -> bool {
+        self.marked.contains(path)
+    }
     pub fn scroll_top(&self) -> u16 {
         self.scroll + self.scrolloff
     }
@@ -140,7 +386,7 @@ pub struct List<'a, T> {
     items: &'a Vec<T>,
     item_height: u16,
 }
-impl<'a, T> List<'a, T> {
+impl<'a, T: Searchable> List<'a, T> {
     pub fn new(state: &'a mut ListState, items: &'a Vec<T>) -> Self {
         Self {
             state,
@@ -157,20 +403,24 @@ impl<'a, T> List<'a, T> {
     pub fn draw<F: Fn(usize, &T, &mut ListState, &mut Buffer, Rect) -> Rect>(&mut self, buf: &mut Buffer, rect: Rect, draw_item: F) -> Rect {
         let mut height = 0u16;
 
-        self.state.count = self.items.len();
+        self.state.rect = rect;
         self.state.height = rect.height;
         self.state.item_height = self.item_height;
+        self.state.update_matches(self.items);
 
         if self.state.current >= self.state.count {
             self.state.select(self.state.current);
         }
 
-        for (index, item) in self.items.iter().enumerate() {
-            // Skip item drawing if its index is less than scroll offset
-            if (index as u16) < self.state.scroll() { continue; }
+        for pos in (self.state.scroll() as usize)..self.state.count() {
             // Break if items are overflow
             if height > rect.height.saturating_sub(1) { break; }
 
+            let index = self.state.index_at(pos);
+            let Some(item) = self.items.get(index) else {
+                break;
+            };
+
             let item_rect = rect
                 .add_y(height)
                 .with_height(self.item_height);
@@ -192,6 +442,7 @@ pub struct ListItem<'a> {
     content: Cow<'a, str>,
     style: Style,
     cur_style: Style,
+    match_style: Style,
 }
 impl<'a> ListItem<'a> {
     pub fn new<C: Into<Cow<'a, str>>>(index: usize, state: &'a ListState, content: C) -> Self {
@@ -200,7 +451,8 @@ impl<'a> ListItem<'a> {
             state,
             content: content.into(),
             style: Style::default(),
-            cur_style: Style::default().reverse(true)
+            cur_style: Style::default().reverse(true),
+            match_style: Style::default().italic(true)
         }
     }
 
@@ -212,6 +464,10 @@ impl<'a> ListItem<'a> {
         self.cur_style = style.into();
         self
     }
+    pub fn with_match_style<S: Into<Style>>(mut self, style: S) -> Self {
+        self.match_style = style.into();
+        self
+    }
 }
 impl<'a> RefDraw for ListItem<'a> {
     fn draw(&self, buf: &mut Buffer, rect: Rect) -> Rect {
@@ -219,6 +475,7 @@ impl<'a> RefDraw for ListItem<'a> {
         let is_cur = self.state.active && self.index == self.state.current();
         let style =
             if is_cur { self.cur_style }
+            else if self.state.is_match(self.index) { self.match_style }
             else { self.style };
 
         Clear::new(style)
@@ -231,3 +488,48 @@ impl<'a> RefDraw for ListItem<'a> {
         rect
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lyrics::LyricsLine;
+
+    fn lines(texts: &[&str]) -> Vec<LyricsLine> {
+        texts
+            .iter()
+            .map(|t| LyricsLine { time: None, text: t.to_string() })
+            .collect()
+    }
+
+    #[test]
+    fn rematch_reordered_items() {
+        let mut state = ListState::new();
+        state.set_query("rain", true);
+
+        state.update_matches(&lines(&["rain", "sun", "snow"]));
+        assert_eq!(state.matches, vec![0]);
+        assert_eq!(state.visible, Some(vec![0]));
+
+        // Same count, so only invalidating tells the items were changed
+        let reordered = lines(&["sun", "snow", "rain"]);
+        state.invalidate();
+        state.update_matches(&reordered);
+        assert_eq!(state.matches, vec![2]);
+        assert_eq!(state.visible, Some(vec![2]));
+        assert_eq!(state.count(), 1);
+    }
+
+    #[test]
+    fn rematch_changed_count() {
+        let mut state = ListState::new();
+        state.set_query("rain", false);
+
+        state.update_matches(&lines(&["rain", "sun"]));
+        assert_eq!(state.matches, vec![0]);
+
+        state.update_matches(&lines(&["sun", "rain", "rain"]));
+        assert_eq!(state.matches, vec![1, 2]);
+        assert_eq!(state.visible, None);
+        assert_eq!(state.count(), 3);
+    }
+}
@@ -4,6 +4,8 @@ mod track;
 mod progress;
 mod playlist;
 mod player;
+mod visualizer;
+mod waveform;
 
 pub use view::*;
 pub use list::*;
@@ -11,3 +13,5 @@ pub use track::*;
 pub use progress::*;
 pub use playlist::*;
 pub use player::*;
+pub use visualizer::*;
+pub use waveform::*;
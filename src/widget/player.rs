@@ -1,9 +1,9 @@
 use serde::{Deserialize, Serialize};
 use tuich::{buffer::Buffer, layout::{Align, Clip, Rect}, style::{Style, Stylized}, text::Text, widget::{Draw, RefDraw}};
 
-use crate::{app::AppContext, player::PlayState, traits::ToReadable};
+use crate::{app::AppContext, format::player_var, player::PlayState};
 
-use super::Progress;
+use super::{Progress, VisualizerWidget, WaveformProgress};
 
 /// Player style
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -13,7 +13,11 @@ pub enum PlayerStyle {
     Classic,
     ClassicReverse,
     Progress,
-    Text
+    Text,
+    /// Track info above the visualizer
+    Visualizer,
+    /// Track info above the progress drawn over the amplitude envelope of the track
+    Waveform
 }
 
 /// Player widget
@@ -25,7 +29,9 @@ impl<'a> PlayerWidget<'a> {
     pub fn style_rect(rect: Rect, style: PlayerStyle) -> Rect {
         match style {
             PlayerStyle::Classic |
-            PlayerStyle::ClassicReverse => rect
+            PlayerStyle::ClassicReverse |
+            PlayerStyle::Visualizer |
+            PlayerStyle::Waveform => rect
                 .with_height(2)
                 .margin((1, 0)),
 
@@ -35,6 +41,19 @@ impl<'a> PlayerWidget<'a> {
                 .margin((1, 0)),
         }
     }
+    /// Returns where the progress bar is in the drawn player, `None` if the style has no progress
+    pub fn progress_rect(rect: Rect, style: PlayerStyle) -> Option<Rect> {
+        match style {
+            PlayerStyle::Classic |
+            PlayerStyle::Progress => Some(rect.with_height(1)),
+
+            PlayerStyle::ClassicReverse |
+            PlayerStyle::Waveform => Some(rect.margin_top(1).with_height(1)),
+
+            PlayerStyle::Text |
+            PlayerStyle::Visualizer => None,
+        }
+    }
 }
 impl<'a> RefDraw for PlayerWidget<'a> {
     fn draw(&self, buf: &mut Buffer, rect: Rect) -> Rect {
@@ -56,6 +75,10 @@ impl<'a> RefDraw for PlayerWidget<'a> {
             PlayerStyle::Progress => draw_progress(self.ctx, style, buf, rect),
 
             PlayerStyle::Text => draw_info(self.ctx, style, buf, rect),
+
+            PlayerStyle::Visualizer => draw_visualizer(self.ctx, style, buf, rect),
+
+            PlayerStyle::Waveform => draw_waveform(self.ctx, style, buf, rect),
         }
     }
 }
@@ -63,38 +86,26 @@ impl<'a> RefDraw for PlayerWidget<'a> {
 // Draw styles
 fn draw_info(ctx: &AppContext, style: Style, buf: &mut Buffer, rect: Rect) -> Rect {
     let rect = rect.with_height(1);
+    let format = &ctx.config.format;
+    let var = |name: &str| player_var(ctx, name);
 
-    if let Some(track) = &ctx.player.cur_track {
-        let playstate = ctx.player.playstate();
-        let title = track.title();
-        let volume =
-            if ctx.player.muted() { "muted".to_string() }
-            else { format!("{}%", (ctx.player.volume() * 100.0).round()) };
-        let loopstate = ctx.player.loopstate().to_string();
-        let pos = ctx.player.pos();
-        let dur = ctx.player.duration();
-
+    if ctx.player.cur_track.is_some() {
         // Draw play info
-        let play_info = format!("{} / {}  {}  {}", pos.to_readable(), dur.to_readable(), loopstate, volume);
-        let play_info_rect = Text::new(play_info, style)
+        let play_info_rect = Text::new(format.player_info.render(var), style)
             .align(Align::End)
             .draw(buf, rect);
 
-        let track_info_rect = rect.margin_right(play_info_rect.width + 2);
+        let track_info_rect =
+            if play_info_rect.width == 0 { rect }
+            else { rect.margin_right(play_info_rect.width + 2) };
 
         // Draw track info
-        if let Some(artist) = track.try_artist() {
-            Text::new(format!("{}  {} - {}", playstate, title, artist), style)
-                .clip(Clip::Ellipsis)
-                .draw(buf, track_info_rect)
-        } else {
-            Text::new(format!("{}  {}", playstate, title), style)
-                .clip(Clip::Ellipsis)
-                .draw(buf, track_info_rect)
-        };
+        Text::new(format.player_track.render(var), style)
+            .clip(Clip::Ellipsis)
+            .draw(buf, track_info_rect);
     } else {
         // Draw something else...
-        Text::new("There should be some smart quote... - Unknown man", ctx.config.theme.player_stopped)
+        Text::new(format.player_idle.render(var), ctx.config.theme.player_stopped)
             .italic()
             .clip(Clip::Ellipsis)
             .draw(buf, rect);
@@ -132,3 +143,35 @@ fn draw_classic(widget: &PlayerWidget, ctx: &AppContext, style: Style, buf: &mut
 
     rect
 }
+fn draw_visualizer(ctx: &AppContext, style: Style, buf: &mut Buffer, rect: Rect) -> Rect {
+    draw_info(ctx, style, buf, rect);
+
+    VisualizerWidget {
+        visualizer: ctx.player.visualizer(),
+        playstate: ctx.player.playstate(),
+        style: ctx.config.visualizer.style,
+        color: style
+    }.draw(buf, rect.margin_top(1));
+
+    rect
+}
+fn draw_waveform(ctx: &AppContext, style: Style, buf: &mut Buffer, rect: Rect) -> Rect {
+    draw_info(ctx, style, buf, rect);
+
+    let progress_rect = rect.margin_top(1);
+    // Draw the flat progress until the envelope is computed
+    let Some(waveform) = ctx.waveform.get() else {
+        draw_progress(ctx, style, buf, progress_rect);
+        return rect;
+    };
+
+    let pos = ctx.player.pos();
+    let dur = ctx.player.duration();
+    let progress = pos.as_secs() as f32 / dur.as_secs() as f32;
+
+    WaveformProgress::new(waveform, progress)
+        .with_style(style)
+        .draw(buf, progress_rect);
+
+    rect
+}
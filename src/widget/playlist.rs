@@ -29,6 +29,7 @@ impl<'a> RefDraw for PlaylistWidget<'a> {
             else if self.playing { theme.playlist_playing }
 
             else if is_cur { theme.playlist_selected }
+            else if self.state.is_match(self.index) { theme.playlist_matched }
             else { theme.playlist };
 
         Clear::new(style)
@@ -1,37 +1,151 @@
-use tuich::{buffer::{Buffer, Cell}, layout::{Align, Clip, Rect, Stack}, style::Color, text::Text, widget::{Clear, Draw}};
+use serde::{Deserialize, Serialize};
+use tuich::{buffer::{Buffer, Cell}, layout::{Align, Clip, Rect}, style::Style, text::Text, widget::{Clear, Draw}};
 
-use crate::{app::AppContext, player::PlayState, track::Track, traits::ToReadable};
+use crate::{
+    app::AppContext,
+    config::ConfigTrackColumn,
+    player::PlayState,
+    rating::format_rating,
+    stats::Stats,
+    track::Track,
+    traits::ToReadable,
+};
 
 use super::ListState;
 
+// Consts
+/// Space between the columns
+const COLUMN_GAP: u16 = 2;
+
+/// Column of the track table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackColumn {
+    /// Position of the track in the list
+    Index,
+    Title,
+    Artist,
+    Album,
+    Year,
+    Duration,
+    TrackNumber,
+    /// Rating in stars and the loved mark
+    Rating,
+    PlayCount,
+    FileName,
+}
+impl TrackColumn {
+    /// Returns text of the column for the track
+    pub fn value(&self, index: usize, track: &Track, stats: &Stats) -> Option<String> {
+        match self {
+            Self::Index => Some(format!("{}.", index + 1)),
+            Self::Title => Some(track.title().to_string()),
+            Self::Artist => track.try_artist().map(str::to_string),
+            Self::Album => track.try_album().map(str::to_string),
+            Self::Year => track.try_year().map(|y| y.to_string()),
+            Self::Duration => track.try_duration().map(|d| d.to_readable()),
+            Self::TrackNumber => track.try_track_number().map(|n| n.to_string()),
+            Self::Rating => {
+                let mut rating = track
+                    .try_rating(stats)
+                    .map(format_rating)
+                    .unwrap_or_default();
+                if track.is_loved(stats) {
+                    rating.insert_str(0, "♥ ");
+                }
+                Some(rating).filter(|r| !r.is_empty())
+            }
+            Self::PlayCount => stats.get(&track.path)
+                .map(|s| s.plays)
+                .filter(|p| *p > 0)
+                .map(|p| p.to_string()),
+            Self::FileName => track.path
+                .file_name()
+                .map(|f| f.to_string_lossy().into_owned()),
+        }
+    }
+}
+
+/// Alignment of the column content
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+impl From<ColumnAlign> for Align {
+    fn from(value: ColumnAlign) -> Self {
+        match value {
+            ColumnAlign::Left => Align::Start,
+            ColumnAlign::Center => Align::Center,
+            ColumnAlign::Right => Align::End,
+        }
+    }
+}
+
 /// Track table
+/// Places the configured columns into a list row
 #[derive(Debug, Clone)]
-pub struct TrackTable {
-    index_rect: Rect,
-    title_rect: Rect,
-    artist_rect: Rect
+pub struct TrackTable<'a> {
+    columns: &'a [ConfigTrackColumn],
+    rects: Vec<Rect>,
 }
-impl TrackTable {
-    pub fn new(tracks_count: usize, rect: Rect) -> Self {
-        let index_width =
-            if tracks_count <= 9 { 3 }
-            else if tracks_count <= 99 { 4 }
-            else if tracks_count <= 999 { 5 }
-            else { 6 };
-
-        let stack_rect = rect
+impl<'a> TrackTable<'a> {
+    pub fn new(columns: &'a [ConfigTrackColumn], tracks_count: usize, rect: Rect) -> Self {
+        // The index column fits the largest index by default
+        let index_width = tracks_count.max(1).to_string().len() as u16 + 2;
+
+        let rect = rect
             .with_height(1)
-            .margin_left(index_width + 1)
             .margin_right(1);
-        let rects = Stack::row(&[2, 1])
-            .gap(2)
-            .calc(stack_rect);
-
-        Self {
-            index_rect: rect.with_width(index_width),
-            title_rect: rects[0],
-            artist_rect: rects[1],
-        }
+        let widths: Vec<Option<u16>> = columns
+            .iter()
+            .map(|c| match (c.width, c.column) {
+                (Some(width), _) => Some(width),
+                (None, TrackColumn::Index) => Some(index_width),
+                (None, _) => None
+            })
+            .collect();
+
+        // The columns without a fixed width share the rest of the row
+        // Computed in u32, large widths and ratios from the config overflow u16
+        let fixed: u32 = widths.iter().flatten().map(|&w| w as u32).sum();
+        let gaps = COLUMN_GAP as u32 * columns.len().saturating_sub(1) as u32;
+        let free = (rect.width as u32).saturating_sub(fixed + gaps);
+        let total_ratio: u32 = columns
+            .iter()
+            .zip(&widths)
+            .filter(|(_, w)| w.is_none())
+            .map(|(c, _)| c.ratio.max(1) as u32)
+            .sum();
+
+        let right = rect.x + rect.width;
+        let mut x = rect.x;
+        let mut left = free;
+        let mut ratio_left = total_ratio;
+        let rects = columns
+            .iter()
+            .zip(widths)
+            .map(|(column, width)| {
+                let width = width.unwrap_or_else(|| {
+                    // The last flexible column gets the rounding remainder
+                    let ratio = column.ratio.max(1) as u32;
+                    let width = if ratio == ratio_left { left } else { free * ratio / total_ratio.max(1) };
+                    ratio_left -= ratio;
+                    left -= width;
+                    width.min(u16::MAX as u32) as u16
+                });
+
+                let width = width.min(right.saturating_sub(x));
+                let column_rect = rect.with_x(x).with_width(width);
+                x = x.saturating_add(width).saturating_add(COLUMN_GAP).min(right);
+                column_rect
+            })
+            .collect();
+
+        Self { columns, rects }
     }
 }
 
@@ -48,9 +162,6 @@ impl<'a> TrackWidget<'a> {
         let theme = &self.ctx.config.theme;
 
         let rect = rect.with_height(1);
-        let index_rect = table.index_rect.with_y(rect.y);
-        let title_rect = table.title_rect.with_y(rect.y);
-        let artist_rect = table.artist_rect.with_y(rect.y);
 
         let is_cur = self.state.active && self.state.current() == self.index;
         let is_paused = self.playing && self.ctx.player.playstate() != PlayState::Playing;
@@ -63,37 +174,26 @@ impl<'a> TrackWidget<'a> {
             else if self.playing { theme.track_playing }
 
             else if is_cur { theme.track_selected }
+            else if self.state.is_marked(&self.track.path) { theme.track_marked }
+            else if self.state.is_match(self.index) { theme.track_matched }
             else { theme.track };
 
-        let title = self.track.title();
-
-        // Draw index
-        Text::from(format!("{}.", self.index + 1))
-            .align(Align::End)
-            .draw(buf, index_rect);
-        // Draw title
-        let title_text_rect = Text::from(title)
-            .clip(Clip::Ellipsis)
-            .draw(buf, title_rect);
-        // Draw album
-        if let Some(album) = self.track.try_album() {
-            Text::from(format!("- {}", album))
-                .style(Color::Gray)
-                .clip(Clip::Ellipsis)
-                .draw(buf, title_rect.margin_left(title_text_rect.width + 1));
-        }
-        // Draw duration
-        let dur_width = if let Some(dur) = self.track.try_duration() {
-            Text::from(dur.to_readable())
-                .align(Align::End)
-                .draw(buf, artist_rect)
-                .width + 2
-        } else { 0 };
-        // Draw artist
-        if let Some(artist) = self.track.try_artist() {
-            Text::from(artist)
+        // Draw columns
+        for (column, column_rect) in table.columns.iter().zip(&table.rects) {
+            if column_rect.width == 0 { continue; }
+            let Some(value) = column.column.value(self.index, self.track, &self.ctx.stats) else {
+                continue;
+            };
+
+            let column_style = column.style.unwrap_or(match column.column {
+                TrackColumn::Rating => theme.rating,
+                _ => Style::empty()
+            });
+
+            Text::new(value, column_style)
+                .align(column.align.into())
                 .clip(Clip::Ellipsis)
-                .draw(buf, artist_rect.margin_right(dur_width));
+                .draw(buf, column_rect.with_y(rect.y));
         }
 
         // Fill the item with some color
@@ -32,6 +32,11 @@ impl<'a> ViewWidget<'a> {
         self.active = active;
         self
     }
+
+    /// Returns the row where the title and the description are drawn
+    pub fn header_rect(rect: Rect) -> Rect {
+        rect.margin((1, 0)).with_height(1)
+    }
 }
 impl<'a> Draw for ViewWidget<'a> {
     fn draw(self, buf: &mut Buffer, rect: Rect) -> Rect {
@@ -41,7 +46,7 @@ impl<'a> Draw for ViewWidget<'a> {
             else if self.active && !is_playing { self.config.theme.title_active_paused }
             else { self.config.theme.title_inactive };
 
-        let header_rect = rect.margin((1, 0)).with_height(1);
+        let header_rect = Self::header_rect(rect);
 
         // Draw description
         let desc_width = if let Some(desc) = self.desc {
@@ -0,0 +1,94 @@
+use tuich::{buffer::Buffer, layout::Rect, style::Style, widget::RefDraw};
+
+use crate::{player::PlayState, visualizer::{Visualizer, VisualizerStyle}};
+
+// Consts
+/// Bar characters from the empty to the full cell, eighths of the cell each
+const BAR_CHARS: [&str; 9] = [" ", "▁", "▂", "▃", "▄", "▅", "▆", "▇", "█"];
+const BRAILLE_START: u32 = 0x2800;
+/// Braille dot bits by the row and column of the dot in the cell
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// Visualizer widget
+/// Draws the audio going to the output as spectrum bars or a waveform
+pub struct VisualizerWidget<'a> {
+    pub visualizer: &'a Visualizer,
+    pub playstate: PlayState,
+    pub style: VisualizerStyle,
+    pub color: Style,
+}
+impl<'a> RefDraw for VisualizerWidget<'a> {
+    fn draw(&self, buf: &mut Buffer, rect: Rect) -> Rect {
+        let active = self.playstate == PlayState::Playing;
+
+        match self.style {
+            VisualizerStyle::Bars => {
+                let bars = self.visualizer.spectrum(rect.width as usize, active);
+                draw_bars(&bars, self.color, buf, rect);
+            }
+            VisualizerStyle::Oscilloscope => {
+                let wave = self.visualizer.waveform(rect.width as usize * 2, active);
+                draw_oscilloscope(&wave, self.color, buf, rect);
+            }
+        }
+
+        rect
+    }
+}
+
+fn draw_bars(bars: &[f32], style: Style, buf: &mut Buffer, rect: Rect) {
+    let eighths = rect.height as f32 * 8.0;
+
+    for (x, bar) in bars.iter().enumerate() {
+        let height = (bar * eighths).round() as u16;
+
+        for row in 0..rect.height {
+            let fill = height.saturating_sub(row * 8).min(8) as usize;
+            if fill == 0 { break; }
+
+            buf.set(
+                rect.pos().add((x as u16, rect.height - 1 - row)),
+                Some(BAR_CHARS[fill]),
+                style
+            );
+        }
+    }
+}
+fn draw_oscilloscope(wave: &[f32], style: Style, buf: &mut Buffer, rect: Rect) {
+    let width = rect.width as usize;
+    let dots_height = rect.height as usize * 4;
+    if width == 0 || dots_height == 0 {
+        return;
+    }
+
+    let mut cells = vec![0_u32; width * rect.height as usize];
+    let dot_y = |sample: f32| (((1.0 - sample) / 2.0 * (dots_height - 1) as f32).round() as usize).min(dots_height - 1);
+
+    let mut prev_y = None;
+    for (x, sample) in wave.iter().enumerate() {
+        let y = dot_y(*sample);
+        // Connect the dots of neighbouring samples, so the line is continuous
+        let (from, to) = match prev_y {
+            Some(prev) if prev < y => (prev + 1, y),
+            Some(prev) if prev > y => (y, prev - 1),
+            _ => (y, y)
+        };
+        prev_y = Some(y);
+
+        for y in from..=to {
+            cells[y / 4 * width + x / 2] |= BRAILLE_DOTS[y % 4][x % 2];
+        }
+    }
+
+    for (index, bits) in cells.into_iter().enumerate() {
+        let Some(char) = char::from_u32(BRAILLE_START + bits).filter(|_| bits != 0) else {
+            continue;
+        };
+
+        buf.set(
+            rect.pos().add(((index % width) as u16, (index / width) as u16)),
+            Some(char.to_string().as_str()),
+            style
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use tuich::{buffer::Buffer, layout::Rect, style::{Color, Style}, widget::Draw};
+
+use crate::waveform::Waveform;
+
+// Consts
+/// Characters from the quietest to the loudest column
+const LEVEL_CHARS: [&str; 8] = ["▁", "▂", "▃", "▄", "▅", "▆", "▇", "█"];
+
+/// Progress drawn over the amplitude envelope of the track
+#[derive(Debug, Clone)]
+pub struct WaveformProgress<'a> {
+    waveform: &'a Waveform,
+    value: f32,
+    style: Style,
+    track_style: Style,
+}
+impl<'a> WaveformProgress<'a> {
+    pub fn new(waveform: &'a Waveform, value: f32) -> Self {
+        Self {
+            waveform,
+            value,
+            style: Color::Green.into(),
+            track_style: Color::LightBlack.into(),
+        }
+    }
+
+    pub fn with_style<S: Into<Style>>(mut self, style: S) -> Self {
+        self.style = style.into();
+        self
+    }
+    pub fn with_track_style<S: Into<Style>>(mut self, style: S) -> Self {
+        self.track_style = style.into();
+        self
+    }
+}
+impl<'a> Draw for WaveformProgress<'a> {
+    fn draw(self, buf: &mut Buffer, rect: Rect) -> Rect {
+        let rect = rect.with_height(1);
+
+        let width = rect.width as f32;
+        for i in 0..rect.width {
+            let start = i as f32 / width;
+            let peak = self.waveform.peak_between(start, (i + 1) as f32 / width);
+            let level = (peak * (LEVEL_CHARS.len() - 1) as f32).round() as usize;
+
+            let style =
+                if start < self.value { self.style }
+                else { self.track_style };
+
+            buf.set(
+                rect.pos().add((i, 0)),
+                Some(LEVEL_CHARS[level.min(LEVEL_CHARS.len() - 1)]),
+                style
+            );
+        }
+
+        rect
+    }
+}